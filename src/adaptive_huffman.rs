@@ -0,0 +1,149 @@
+mod tree;
+
+use crate::adaptive_huffman::tree::Tree;
+use crate::checksum::ChecksumKind;
+use crate::container::{CodecId, Header};
+use crate::framing::{self, Frame};
+use crate::huffman::DEFAULT_BLOCK_SIZE;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered before they are coded and written.
+    pub block_size: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+}
+
+/// Compresses `input` in a single pass with a Huffman code that adapts to
+/// the bytes seen so far, so no code table is sent.
+///
+/// The stream is framed as `framing` describes, each block holding just the
+/// codes of its bytes, padded to a byte. The code carries over from one
+/// block to the next.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let frame = Frame {
+        codec: CodecId::AdaptiveHuffman,
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+    let mut tree = Tree::new();
+
+    framing::compress(input, output, &frame, |writer, block| {
+        for &byte in block {
+            tree.encode(byte, writer)?;
+        }
+
+        writer.flush()
+    })
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::AdaptiveHuffman)?;
+
+    let mut tree = Tree::new();
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        output.write_decoded(length, || tree.decode(reader))?;
+        reader.align_to_byte();
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::checksum::ChecksumKind;
+    use crate::container::{CodecId, Header};
+    use crate::test_support::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_records_adaptive_codec() {
+        let compressed = compress_bytes(&Options::default(), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.codec, CodecId::AdaptiveHuffman);
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let options = Options::default();
+
+        assert_round_trip(&options, b"");
+        assert_round_trip(&options, b"a");
+        assert_round_trip(&options, b"aaaaaaaaaa");
+        assert_round_trip(&options, &(0..=255).collect::<Vec<u8>>());
+        assert_round_trip(&options, &[0; 100_000]);
+    }
+
+    #[test]
+    fn skewed_input_compresses_without_a_table() {
+        let input: Vec<u8> = (0..10_000u32)
+            .map(|i| if i % 10 == 0 { b'b' } else { b'a' })
+            .collect();
+
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        // Close to one bit per byte, with no table to amortise.
+        assert!(compressed.len() < input.len() / 7);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn code_carries_over_between_blocks() {
+        let input = b"the rain in spain stays mainly in the plain. ".repeat(200);
+        let options = Options {
+            block_size: 100,
+            checksum: ChecksumKind::Crc32,
+        };
+
+        let compressed = compress_bytes(&options, &input);
+
+        assert!(compressed.len() < input.len() * 2 / 3);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        assert_rejects_corrupted_data(
+            &options,
+            &b"the rain in spain stays mainly in the plain. ".repeat(20),
+        );
+    }
+
+    #[test]
+    fn rejects_zero_block_size() {
+        assert_rejects_options(&Options {
+            block_size: 0,
+            ..Options::default()
+        });
+    }
+}
@@ -0,0 +1,369 @@
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use std::io::{self, Read, Write};
+
+const NO_NODE: usize = usize::MAX;
+const BYTE_ALPHABET_SIZE: usize = 256;
+
+#[derive(Debug)]
+struct Node {
+    weight: u64,
+    parent: usize,
+    /// The children of an internal node, reached with a 0 and a 1 bit.
+    children: Option<[usize; 2]>,
+    /// Position in the implicit numbering, lowest weight first.
+    number: usize,
+    /// The byte of a leaf other than the NYT leaf.
+    symbol: Option<u8>,
+}
+
+/// A Huffman tree that both sides update after every symbol with Vitter's
+/// algorithm, so no code table is ever sent.
+///
+/// Symbols that have not been seen yet are sent as the code of the NYT
+/// ("not yet transmitted") leaf, which has weight zero, followed by the raw
+/// byte. The nodes are kept numbered in order of weight with leaves of a
+/// weight before internal nodes of the same weight, and each update slides
+/// nodes ahead of the block of nodes they would otherwise overtake.
+pub struct Tree {
+    nodes: Vec<Node>,
+    /// Node of each implicit number.
+    order: Vec<usize>,
+    /// Leaf of each byte that has been seen.
+    leaves: [usize; BYTE_ALPHABET_SIZE],
+    nyt: usize,
+    /// Scratch space for a code, collected leaf first.
+    path: Vec<bool>,
+}
+
+impl Tree {
+    pub fn new() -> Self {
+        Tree {
+            nodes: vec![Node {
+                weight: 0,
+                parent: NO_NODE,
+                children: None,
+                number: 0,
+                symbol: None,
+            }],
+            order: vec![0],
+            leaves: [NO_NODE; BYTE_ALPHABET_SIZE],
+            nyt: 0,
+            path: vec![],
+        }
+    }
+
+    pub fn encode<W: Write>(&mut self, byte: u8, writer: &mut BitWriter<W>) -> io::Result<()> {
+        let leaf = self.leaves[byte as usize];
+
+        if leaf == NO_NODE {
+            self.write_code(self.nyt, writer)?;
+            writer.write_bits(byte.into(), u8::BITS as u8)?;
+        } else {
+            self.write_code(leaf, writer)?;
+        }
+
+        self.update(byte);
+
+        Ok(())
+    }
+
+    pub fn decode<R: Read>(&mut self, reader: &mut BitReader<R>) -> io::Result<u8> {
+        let mut node = self.root();
+
+        while let Some(children) = self.nodes[node].children {
+            node = children[reader.read_bits(1)? as usize];
+        }
+
+        let byte = match self.nodes[node].symbol {
+            Some(byte) => byte,
+            None => reader.read_bits(u8::BITS as u8)? as u8,
+        };
+
+        self.update(byte);
+
+        Ok(byte)
+    }
+
+    fn root(&self) -> usize {
+        self.order[self.order.len() - 1]
+    }
+
+    fn write_code<W: Write>(&mut self, leaf: usize, writer: &mut BitWriter<W>) -> io::Result<()> {
+        self.path.clear();
+
+        let mut node = leaf;
+        while self.nodes[node].parent != NO_NODE {
+            let parent = self.nodes[node].parent;
+            self.path.push(self.child_index(parent, node) == 1);
+            node = parent;
+        }
+
+        for &bit in self.path.iter().rev() {
+            writer.write_bit(bit)?;
+        }
+
+        Ok(())
+    }
+
+    fn child_index(&self, parent: usize, child: usize) -> usize {
+        self.nodes[parent]
+            .children
+            .map_or(0, |children| (children[1] == child) as usize)
+    }
+
+    fn is_leaf(&self, node: usize) -> bool {
+        self.nodes[node].children.is_none()
+    }
+
+    /// Counts one more occurrence of `byte`.
+    fn update(&mut self, byte: u8) {
+        let mut node = self.leaves[byte as usize];
+
+        // A leaf whose sibling is the NYT leaf has the same weight as its
+        // parent, so it cannot slide until its ancestors have been
+        // incremented; by then no node stands in its way.
+        let mut leaf_to_increment = None;
+
+        if node == NO_NODE {
+            let leaf = self.split_nyt();
+            self.nodes[leaf].symbol = Some(byte);
+            self.leaves[byte as usize] = leaf;
+
+            leaf_to_increment = Some(leaf);
+            node = self.nodes[leaf].parent;
+        } else {
+            let leader = self.leader(node);
+            if leader != node {
+                self.swap(node, leader);
+            }
+
+            if self.sibling(node) == Some(self.nyt) {
+                leaf_to_increment = Some(node);
+                node = self.nodes[node].parent;
+            }
+        }
+
+        // Every node reached here leads its block.
+        while node != NO_NODE {
+            node = self.slide_and_increment(node);
+        }
+
+        if let Some(leaf) = leaf_to_increment {
+            self.slide_and_increment(leaf);
+        }
+    }
+
+    fn sibling(&self, node: usize) -> Option<usize> {
+        let parent = self.nodes[node].parent;
+        let children = self.nodes.get(parent)?.children?;
+
+        Some(children[1 - self.child_index(parent, node)])
+    }
+
+    /// Turns the NYT leaf into an internal node with a new NYT leaf and a new
+    /// leaf of weight zero as its children, returning the new leaf.
+    fn split_nyt(&mut self) -> usize {
+        let parent = self.nyt;
+        let nyt = self.nodes.len();
+        let leaf = nyt + 1;
+
+        for number in 0..2 {
+            self.nodes.push(Node {
+                weight: 0,
+                parent,
+                children: None,
+                number,
+                symbol: None,
+            });
+        }
+
+        self.nodes[parent].children = Some([nyt, leaf]);
+        self.nyt = nyt;
+
+        self.order.splice(0..0, [nyt, leaf]);
+        for number in 2..self.order.len() {
+            self.nodes[self.order[number]].number = number;
+        }
+
+        leaf
+    }
+
+    /// Increments the weight of `node`, a block leader, first sliding it
+    /// past the nodes it now outweighs, and returns the node whose weight
+    /// must be incremented next.
+    fn slide_and_increment(&mut self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let is_leaf = self.is_leaf(node);
+        let former_parent = self.nodes[node].parent;
+
+        // A leaf moves ahead of the internal nodes of its weight, an internal
+        // node ahead of the leaves of its new weight.
+        let (slide_weight, slide_leaves) = if is_leaf {
+            (weight, false)
+        } else {
+            (weight + 1, true)
+        };
+
+        while let Some(&next) = self.order.get(self.nodes[node].number + 1) {
+            if self.nodes[next].weight != slide_weight || self.is_leaf(next) != slide_leaves {
+                break;
+            }
+
+            self.swap(node, next);
+        }
+
+        self.nodes[node].weight += 1;
+
+        if is_leaf {
+            self.nodes[node].parent
+        } else {
+            former_parent
+        }
+    }
+
+    /// The highest numbered node with the same weight as `node` that is a
+    /// leaf if and only if `node` is.
+    fn leader(&self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let is_leaf = self.is_leaf(node);
+        let mut leader = node;
+
+        while let Some(&next) = self.order.get(self.nodes[leader].number + 1) {
+            if self.nodes[next].weight != weight || self.is_leaf(next) != is_leaf {
+                break;
+            }
+
+            leader = next;
+        }
+
+        leader
+    }
+
+    /// Exchanges the places of two nodes, neither an ancestor of the other,
+    /// in the tree and in the numbering.
+    fn swap(&mut self, a: usize, b: usize) {
+        let parent_a = self.nodes[a].parent;
+        let parent_b = self.nodes[b].parent;
+        let index_a = self.child_index(parent_a, a);
+        let index_b = self.child_index(parent_b, b);
+
+        if let Some(children) = &mut self.nodes[parent_a].children {
+            children[index_a] = b;
+        }
+        if let Some(children) = &mut self.nodes[parent_b].children {
+            children[index_b] = a;
+        }
+
+        self.nodes[a].parent = parent_b;
+        self.nodes[b].parent = parent_a;
+
+        let number_a = self.nodes[a].number;
+        let number_b = self.nodes[b].number;
+        self.nodes[a].number = number_b;
+        self.nodes[b].number = number_a;
+        self.order.swap(number_a, number_b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::random_bytes;
+    use std::io::Cursor;
+
+    impl Tree {
+        /// Checks the sibling property: weights never decrease along the
+        /// numbering, siblings are numbered next to each other, leaves come
+        /// before internal nodes of the same weight, and every internal
+        /// node weighs as much as its children.
+        fn assert_invariants(&self) {
+            for pair in self.order.windows(2) {
+                let (lower, higher) = (&self.nodes[pair[0]], &self.nodes[pair[1]]);
+
+                assert!(lower.weight <= higher.weight);
+                if lower.weight == higher.weight {
+                    assert!(self.is_leaf(pair[0]) || !self.is_leaf(pair[1]));
+                }
+            }
+
+            for (id, node) in self.nodes.iter().enumerate() {
+                assert_eq!(self.order[node.number], id);
+
+                if let Some([left, right]) = node.children {
+                    assert_eq!(self.nodes[left].parent, id);
+                    assert_eq!(self.nodes[right].parent, id);
+                    assert_eq!(
+                        node.weight,
+                        self.nodes[left].weight + self.nodes[right].weight
+                    );
+                    assert_eq!(
+                        self.nodes[left].number.abs_diff(self.nodes[right].number),
+                        1
+                    );
+                }
+            }
+        }
+
+        fn code_len(&self, byte: u8) -> usize {
+            let mut len = 0;
+            let mut node = self.leaves[byte as usize];
+
+            while self.nodes[node].parent != NO_NODE {
+                node = self.nodes[node].parent;
+                len += 1;
+            }
+
+            len
+        }
+    }
+
+    #[test]
+    fn updates_keep_sibling_property() {
+        let mut tree = Tree::new();
+
+        for (i, random) in random_bytes(5000, 0).into_iter().enumerate() {
+            // Skewed, so that weights differ widely.
+            let byte = random % (1 + (i / 100) as u8 % 64);
+            tree.update(byte);
+            tree.assert_invariants();
+        }
+    }
+
+    #[test]
+    fn frequent_symbols_get_shorter_codes() {
+        let mut tree = Tree::new();
+
+        for _ in 0..100 {
+            tree.update(b'e');
+        }
+        for byte in b"abcdefghijklmnopqrstuvwxyz" {
+            tree.update(*byte);
+        }
+
+        tree.assert_invariants();
+        assert_eq!(tree.code_len(b'e'), 1);
+        assert!(tree.code_len(b'z') > 4);
+    }
+
+    #[test]
+    fn decoder_mirrors_encoder() {
+        let input = b"abracadabra, abracadabra! \x00\xFF";
+
+        let mut encoder = Tree::new();
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        for &byte in input {
+            encoder.encode(byte, &mut writer).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let mut decoder = Tree::new();
+        let mut reader = BitReader::new(Cursor::new(writer.writer.into_inner()));
+        let output: Vec<u8> = input
+            .iter()
+            .map(|_| decoder.decode(&mut reader).unwrap())
+            .collect();
+
+        assert_eq!(output, input);
+    }
+}
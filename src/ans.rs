@@ -0,0 +1,288 @@
+mod rans;
+mod tans;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{CodecId, Header};
+use crate::framing::{self, BlockType, Frame};
+use crate::huffman::DEFAULT_BLOCK_SIZE;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::frequency::Frequencies;
+use crate::range_coder::model::Model;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Bits of precision of the scaled frequencies for rANS.
+const RANS_PRECISION_BITS: u32 = 15;
+/// Bits of precision for tANS, which has a table slot per unit of scaled
+/// frequency; a smaller table is quicker to build and stays in cache.
+const TANS_TABLE_LOG: u32 = 12;
+
+/// Which asymmetric numeral systems coder to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// Arithmetic rANS with interleaved states, coding whole bytes.
+    #[default]
+    Rans,
+    /// Table driven tANS (as in FSE), coding bits.
+    Tans,
+}
+
+impl Variant {
+    pub(crate) fn codec(self) -> CodecId {
+        match self {
+            Variant::Rans => CodecId::Rans,
+            Variant::Tans => CodecId::Tans,
+        }
+    }
+
+    fn precision_bits(self) -> u32 {
+        match self {
+            Variant::Rans => RANS_PRECISION_BITS,
+            Variant::Tans => TANS_TABLE_LOG,
+        }
+    }
+}
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and coded as one block.
+    pub block_size: usize,
+    /// Which coder to use, recorded as the header's codec.
+    pub variant: Variant,
+    /// Most blocks coded at once, each on a thread of its own.
+    pub threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            variant: Variant::default(),
+            threads: 1,
+        }
+    }
+}
+
+/// Compresses `input` with an asymmetric numeral systems coder driven by
+/// each block's byte frequencies, which gets close to the order-0 entropy
+/// like `range_coder::compress` while decoding with table lookups and
+/// shifts like a Huffman code.
+///
+/// The stream is framed like `range_coder::compress`, with the frequencies
+/// scaled to the variant's table size, and the variant recorded as the
+/// header's codec.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let frame = Frame {
+        codec: options.variant.codec(),
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+
+    framing::compress_parallel(input, output, &frame, options.threads, |block| {
+        encode_block(block, options.variant)
+    })
+}
+
+/// The frequency table and coded bytes of a non-empty block.
+fn encode_block(block: &[u8], variant: Variant) -> io::Result<Vec<u8>> {
+    let frequencies = Frequencies::from_input(&mut &block[..])?;
+    let model = Model::new(&frequencies, variant.precision_bits())?;
+
+    let mut coded = Vec::new();
+    model.write_to(&mut coded)?;
+
+    match variant {
+        Variant::Rans => coded.extend(rans::encode(&model, block)),
+        Variant::Tans => {
+            let mut writer = BitWriter::new(coded);
+            tans::encode(&model, block, &mut writer)?;
+            writer.flush()?;
+            coded = writer.writer;
+        }
+    }
+
+    Ok(coded)
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let variant = match header.codec {
+        CodecId::Rans => Variant::Rans,
+        CodecId::Tans => Variant::Tans,
+        _ => return header.expect_codec(CodecId::Rans),
+    };
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        match framing::read_block_type(reader)? {
+            BlockType::Stored => output.copy_stored(reader, length),
+            BlockType::Coded => {
+                let model = Model::read_from(reader, variant.precision_bits())?;
+
+                match variant {
+                    Variant::Rans => {
+                        let mut decoder = rans::Decoder::new(reader, &model)?;
+                        output.write_decoded(length, || decoder.decode())?;
+                        decoder.finish()
+                    }
+                    Variant::Tans => {
+                        let mut decoder = tans::Decoder::new(reader, &model)?;
+                        output.write_decoded(length, || decoder.decode())?;
+                        decoder.finish()?;
+                        reader.align_to_byte();
+                        Ok(())
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Options, Variant};
+    use crate::checksum::ChecksumKind;
+    use crate::container::{CodecId, Header};
+    use crate::error::Error;
+    use crate::framing::BlockType;
+    use crate::test_support::*;
+    use std::io::Cursor;
+
+    const VARIANTS: [Variant; 2] = [Variant::Rans, Variant::Tans];
+
+    fn options(variant: Variant) -> Options {
+        Options {
+            variant,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn header_records_variant() {
+        let compressed = compress_bytes(&options(Variant::Rans), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+        assert_eq!(header.codec, CodecId::Rans);
+
+        let compressed = compress_bytes(&options(Variant::Tans), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+        assert_eq!(header.codec, CodecId::Tans);
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        for variant in VARIANTS {
+            for input in [
+                &b""[..],
+                b"a",
+                b"the rain in spain stays mainly in the plain",
+                &(0..=255).collect::<Vec<u8>>(),
+                &skewed_bytes(600_000, 0),
+            ] {
+                assert_round_trip(&options(variant), input);
+            }
+        }
+    }
+
+    #[test]
+    fn output_is_close_to_order_0_entropy() {
+        let input = skewed_bytes(super::DEFAULT_BLOCK_SIZE, 0);
+        let entropy = entropy_bytes(&input);
+
+        for variant in VARIANTS {
+            let compressed = compress_bytes(&options(variant), &input);
+
+            // The data within a percent, plus the table and framing.
+            assert!((compressed.len() as f64) < entropy * 1.01 + 100.0);
+            assert_eq!(first_block_type(&compressed), BlockType::Coded);
+        }
+    }
+
+    #[test]
+    fn repeated_symbol_costs_almost_nothing() {
+        let input = [b'a'; 100_000];
+
+        for variant in VARIANTS {
+            let compressed = compress_bytes(&options(variant), &input);
+
+            assert!(compressed.len() < 40);
+            assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn small_blocks_round_trip() {
+        let input = skewed_bytes(10_000, 0);
+
+        for variant in VARIANTS {
+            let options = Options {
+                checksum: ChecksumKind::Crc32,
+                block_size: 1000,
+                variant,
+                threads: 1,
+            };
+
+            assert_round_trip(&options, &input);
+
+            // Blocks coded on several threads are written just the same.
+            let parallel = Options {
+                threads: 4,
+                ..options.clone()
+            };
+            assert_eq!(
+                compress_bytes(&parallel, &input),
+                compress_bytes(&options, &input)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let input = skewed_bytes(10_000, 0);
+
+        for variant in VARIANTS {
+            let options = Options {
+                checksum: ChecksumKind::XxHash64,
+                variant,
+                ..Options::default()
+            };
+
+            assert_rejects_corrupted_data(&options, &input);
+        }
+    }
+
+    #[test]
+    fn rejects_block_longer_than_limits() {
+        for variant in VARIANTS {
+            // A block of one repeated byte costs no bits, however long.
+            let compressed = compress_bytes(&options(variant), &[b'a'; 1000]);
+            assert_eq!(first_block_type(&compressed), BlockType::Coded);
+
+            for original_length in [Some(1000), None] {
+                let patched = with_first_block_length(&compressed, original_length, 1 << 40);
+
+                let error = decompress_bytes(&patched).unwrap_err();
+                assert!(matches!(Error::from(error), Error::LimitExceeded(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_zero_block_size() {
+        assert_rejects_options(&Options {
+            block_size: 0,
+            ..Options::default()
+        });
+    }
+}
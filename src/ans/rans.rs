@@ -0,0 +1,159 @@
+use crate::error::Error;
+use crate::range_coder::model::Model;
+use std::io::{self, Read};
+
+/// Number of interleaved states; consecutive bytes use consecutive states,
+/// so their updates do not depend on each other.
+pub const STATES: usize = 4;
+
+/// The low end of the normalized state interval, `[LOWER_BOUND, LOWER_BOUND
+/// << 8)`, which states are brought back into a byte at a time.
+const LOWER_BOUND: u32 = 1 << 23;
+
+/// Codes `block` with static rANS, returning the bytes for `Decoder`.
+///
+/// rANS decodes in the reverse order it encodes, so the block is encoded
+/// from its end and the output reversed, putting the final states first.
+pub fn encode(model: &Model, block: &[u8]) -> Vec<u8> {
+    let precision_bits = model.precision_bits();
+    let mut states = [LOWER_BOUND; STATES];
+    let mut reversed = Vec::with_capacity(block.len() / 2);
+
+    for (i, &byte) in block.iter().enumerate().rev() {
+        let state = &mut states[i % STATES];
+        let (start, frequency) = model.range(byte);
+
+        // Shift out bytes until encoding cannot leave the interval.
+        let limit = ((LOWER_BOUND >> precision_bits) << 8) * frequency;
+        while *state >= limit {
+            reversed.push(*state as u8);
+            *state >>= 8;
+        }
+
+        *state = ((*state / frequency) << precision_bits) + *state % frequency + start;
+    }
+
+    for state in states.iter().rev() {
+        reversed.extend(state.to_be_bytes());
+    }
+
+    reversed.reverse();
+    reversed
+}
+
+/// Decodes what `encode` wrote, reading exactly the bytes it wrote.
+pub struct Decoder<'a, R: Read> {
+    reader: &'a mut R,
+    model: &'a Model,
+    lookup: Vec<u8>,
+    states: [u32; STATES],
+    next: usize,
+}
+
+impl<'a, R: Read> Decoder<'a, R> {
+    pub fn new(reader: &'a mut R, model: &'a Model) -> io::Result<Self> {
+        let mut states = [0; STATES];
+        for state in &mut states {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            *state = u32::from_le_bytes(bytes);
+        }
+
+        Ok(Decoder {
+            reader,
+            model,
+            lookup: model.lookup_table(),
+            states,
+            next: 0,
+        })
+    }
+
+    pub fn decode(&mut self) -> io::Result<u8> {
+        let precision_bits = self.model.precision_bits();
+        let state = &mut self.states[self.next];
+        self.next = (self.next + 1) % STATES;
+
+        let slot = *state & (self.model.total() - 1);
+        let byte = self.lookup[slot as usize];
+        let (start, frequency) = self.model.range(byte);
+
+        *state = frequency * (*state >> precision_bits) + slot - start;
+
+        while *state < LOWER_BOUND {
+            let mut next = [0];
+            self.reader.read_exact(&mut next)?;
+            *state = (*state << 8) | next[0] as u32;
+        }
+
+        Ok(byte)
+    }
+
+    /// Checks that every state is back where the encoder started.
+    pub fn finish(self) -> io::Result<()> {
+        if self.states.iter().any(|&state| state != LOWER_BOUND) {
+            return Err(
+                Error::CorruptData("rANS state does not match its initial state".into()).into(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::frequency::Frequencies;
+    use std::io::Cursor;
+
+    fn model_for(input: &[u8]) -> Model {
+        Model::new(&Frequencies::from_input(&mut &input[..]).unwrap(), 14).unwrap()
+    }
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let model = model_for(input);
+        let coded = encode(&model, input);
+
+        let mut reader = Cursor::new(&coded);
+        let mut decoder = Decoder::new(&mut reader, &model).unwrap();
+        let output: Vec<u8> = input.iter().map(|_| decoder.decode().unwrap()).collect();
+        decoder.finish().unwrap();
+
+        // The decoder reads exactly what the encoder wrote.
+        assert_eq!(reader.position(), coded.len() as u64);
+        assert_eq!(output, input);
+
+        coded
+    }
+
+    #[test]
+    fn round_trips_varied_input() {
+        round_trip(b"a");
+        round_trip(b"abc");
+        round_trip(b"abracadabra");
+        round_trip(&(0..=255).cycle().take(10_000).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn single_symbol_costs_only_the_states() {
+        let coded = round_trip(&[b'a'; 100_000]);
+
+        assert_eq!(coded.len(), STATES * 4);
+    }
+
+    #[test]
+    fn detects_corrupted_states() {
+        let input = b"abracadabra";
+        let model = model_for(input);
+        let mut coded = encode(&model, input);
+        coded[0] ^= 1;
+
+        let mut reader = Cursor::new(&coded);
+        let mut decoder = Decoder::new(&mut reader, &model).unwrap();
+        for _ in input {
+            decoder.decode().unwrap();
+        }
+
+        assert!(decoder.finish().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::error::Error;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::range_coder::model::Model;
+use std::io::{self, Read};
+
+/// A state of the decoding table: the byte it decodes to, and how to find
+/// the next state from the bits that follow.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    byte: u8,
+    bits: u8,
+    base: u32,
+}
+
+/// Spreads the bytes over a table with one slot per unit of scaled
+/// frequency, stepping by a stride coprime with the table size so each
+/// byte's slots are scattered across it, as in FSE.
+fn spread(model: &Model) -> Vec<u8> {
+    let size = model.total() as usize;
+    let step = (size >> 1) + (size >> 3) + 3;
+    let mut table = vec![0; size];
+    let mut position = 0;
+
+    for (byte, &frequency) in model.frequencies().iter().enumerate() {
+        for _ in 0..frequency {
+            table[position] = byte as u8;
+            position = (position + step) & (size - 1);
+        }
+    }
+
+    table
+}
+
+/// Calls `visit(slot, byte, next)` for each slot of the spread table in
+/// order, where `next` counts the slots of `byte` so far, starting from its
+/// frequency.
+fn for_each_slot(model: &Model, mut visit: impl FnMut(usize, u8, u32)) {
+    let mut next = [0; 256];
+    next.copy_from_slice(model.frequencies());
+
+    for (slot, byte) in spread(model).into_iter().enumerate() {
+        visit(slot, byte, next[byte as usize]);
+        next[byte as usize] += 1;
+    }
+}
+
+/// Codes `block` with tANS, writing bits for `Decoder` to `writer`.
+///
+/// States range over `[total, 2 * total)`. Encoding a byte of frequency
+/// `f` shifts out bits until the state is in `[f, 2f)`, then moves to the
+/// table slot that decodes to the byte from there. Like rANS it runs
+/// backwards, so the bits are written in the reverse order they were made.
+pub fn encode<W: io::Write>(
+    model: &Model,
+    block: &[u8],
+    writer: &mut BitWriter<W>,
+) -> io::Result<()> {
+    let total = model.total();
+    let mut slots = vec![0; total as usize];
+    for_each_slot(model, |slot, byte, next| {
+        let (start, frequency) = model.range(byte);
+        slots[(start + next - frequency) as usize] = slot as u32;
+    });
+
+    let mut state = total;
+    let mut chunks = Vec::with_capacity(block.len());
+
+    for &byte in block.iter().rev() {
+        let (start, frequency) = model.range(byte);
+
+        let mut bits = state.ilog2() - frequency.ilog2();
+        if state >> bits < frequency {
+            bits -= 1;
+        }
+
+        chunks.push((state & ((1 << bits) - 1), bits as u8));
+        state = total + slots[(start + (state >> bits) - frequency) as usize];
+    }
+
+    writer.write_bits(state - total, model.precision_bits() as u8)?;
+    for &(value, bits) in chunks.iter().rev() {
+        writer.write_bits(value, bits)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes what `encode` wrote.
+pub struct Decoder<'a, R: Read> {
+    reader: &'a mut BitReader<R>,
+    table: Vec<Entry>,
+    state: u32,
+}
+
+impl<'a, R: Read> Decoder<'a, R> {
+    pub fn new(reader: &'a mut BitReader<R>, model: &Model) -> io::Result<Self> {
+        let precision_bits = model.precision_bits();
+        let total = model.total();
+
+        let mut table = vec![
+            Entry {
+                byte: 0,
+                bits: 0,
+                base: 0,
+            };
+            total as usize
+        ];
+        for_each_slot(model, |slot, byte, next| {
+            let bits = precision_bits - next.ilog2();
+            table[slot] = Entry {
+                byte,
+                bits: bits as u8,
+                base: (next << bits) - total,
+            };
+        });
+
+        let state = reader.read_bits(precision_bits as u8)?;
+
+        Ok(Decoder {
+            reader,
+            table,
+            state,
+        })
+    }
+
+    pub fn decode(&mut self) -> io::Result<u8> {
+        let entry = self.table[self.state as usize];
+        self.state = entry.base + self.reader.read_bits(entry.bits)?;
+
+        Ok(entry.byte)
+    }
+
+    /// Checks that the state is back where the encoder started.
+    pub fn finish(self) -> io::Result<()> {
+        if self.state != 0 {
+            return Err(
+                Error::CorruptData("tANS state does not match its initial state".into()).into(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::frequency::Frequencies;
+    use std::io::Cursor;
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let model = Model::new(&Frequencies::from_input(&mut &input[..]).unwrap(), 11).unwrap();
+
+        let mut writer = BitWriter::new(vec![]);
+        encode(&model, input, &mut writer).unwrap();
+        writer.flush().unwrap();
+        let coded = writer.writer;
+
+        let mut reader = BitReader::new(Cursor::new(&coded));
+        let mut decoder = Decoder::new(&mut reader, &model).unwrap();
+        let output: Vec<u8> = input.iter().map(|_| decoder.decode().unwrap()).collect();
+        decoder.finish().unwrap();
+
+        // Only the padding of the last byte is left over.
+        assert!(reader.remaining_bits().unwrap().unwrap() < 8);
+        assert_eq!(output, input);
+
+        coded
+    }
+
+    #[test]
+    fn spread_gives_each_byte_its_frequency() {
+        let model = Model::new(&Frequencies::from_input(&mut &b"aaab"[..]).unwrap(), 8).unwrap();
+        let table = spread(&model);
+
+        assert_eq!(table.iter().filter(|&&byte| byte == b'a').count(), 192);
+        assert_eq!(table.iter().filter(|&&byte| byte == b'b').count(), 64);
+    }
+
+    #[test]
+    fn round_trips_varied_input() {
+        round_trip(b"a");
+        round_trip(b"abc");
+        round_trip(b"abracadabra");
+        round_trip(&(0..=255).cycle().take(10_000).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn single_symbol_costs_only_the_state() {
+        let coded = round_trip(&[b'a'; 100_000]);
+
+        assert_eq!(coded.len(), 2);
+    }
+}
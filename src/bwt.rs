@@ -0,0 +1,250 @@
+mod mtf;
+mod tables;
+mod transform;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{self, CodecId, Header};
+use crate::error::Error;
+use crate::framing::{self, BlockType, Frame};
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::{self, DEFAULT_MAX_CODE_LENGTH};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Like bzip2's largest blocks: the transform groups bytes by what follows
+/// them, which works better the more of the input it sees at once.
+pub const DEFAULT_BLOCK_SIZE: usize = 900 * 1024;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and transformed as one block.
+    pub block_size: usize,
+    /// Longest code, in bits, that a code table may assign to a symbol.
+    pub max_code_length: u8,
+    /// Most blocks coded at once, each on a thread of its own.
+    pub threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_code_length: DEFAULT_MAX_CODE_LENGTH,
+            threads: 1,
+        }
+    }
+}
+
+/// Compresses `input` like bzip2: each block is put through the
+/// Burrows–Wheeler transform, which brings together bytes that precede the
+/// same context, then move-to-front and zero-run coding turn that into
+/// mostly small numbers for a Huffman coder with several tables.
+///
+/// Each block is written as `framing` describes, stored or coded. A coded
+/// block holds the transform's primary index and the number of coded
+/// symbols as varints, then the tables and codes written by
+/// `tables::encode`.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    huffman::check_options(
+        options.block_size,
+        options.max_code_length,
+        mtf::ALPHABET_SIZE,
+    )?;
+
+    let frame = Frame {
+        codec: CodecId::Bwt,
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+
+    framing::compress_parallel(input, output, &frame, options.threads, |block| {
+        encode_block(block, options.max_code_length)
+    })
+}
+
+/// The coded form of a non-empty block.
+fn encode_block(block: &[u8], max_code_length: u8) -> io::Result<Vec<u8>> {
+    let (transformed, primary) = transform::forward(block);
+    let symbols = mtf::encode(&transformed);
+
+    let mut coded = Vec::new();
+    container::write_varint(&mut coded, primary as u64)?;
+    container::write_varint(&mut coded, symbols.len() as u64)?;
+
+    let mut writer = BitWriter::new(coded);
+    tables::encode(&symbols, max_code_length, &mut writer)?;
+    writer.flush()?;
+
+    Ok(writer.writer)
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::Bwt)?;
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        match framing::read_block_type(reader)? {
+            BlockType::Stored => output.copy_stored(reader, length),
+            BlockType::Coded => {
+                let primary = container::read_varint(reader)?;
+                let count = container::read_varint(reader)?;
+
+                // Each symbol stands for at least one byte.
+                if count > length {
+                    return Err(invalid_data("more symbols than bytes in block"));
+                }
+
+                let symbols = tables::decode(reader, count as usize)?;
+                reader.align_to_byte();
+
+                let transformed = mtf::decode(&symbols, length)?;
+                if transformed.len() as u64 != length {
+                    return Err(invalid_data("block length does not match its data"));
+                }
+
+                output.write_all(&transform::inverse(&transformed, primary as usize)?)
+            }
+        }
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::HuffmanOptions;
+    use crate::checksum::ChecksumKind;
+    use crate::container::{self, CodecId, Header};
+    use crate::error::Error;
+    use crate::framing::BlockType;
+    use crate::test_support::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn header_records_bwt_codec() {
+        let compressed = compress_bytes(&Options::default(), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.codec, CodecId::Bwt);
+        assert_eq!(header.original_length, Some(3));
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let options = Options::default();
+
+        assert_round_trip(&options, b"");
+        assert_round_trip(&options, b"a");
+        assert_round_trip(&options, b"banana");
+        assert_round_trip(&options, &[0; 100_000]);
+        assert_round_trip(&options, &(0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn round_trips_large_text_over_several_blocks() {
+        let input = text(300_000, 0);
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            block_size: 64 * 1024,
+            ..Options::default()
+        };
+        assert!(input.len() > 3 * options.block_size);
+
+        assert_round_trip(&options, &input);
+
+        // Blocks coded on several threads are written just the same.
+        let parallel = Options {
+            threads: 4,
+            ..options.clone()
+        };
+        assert_eq!(
+            compress_bytes(&parallel, &input),
+            compress_bytes(&options, &input)
+        );
+    }
+
+    #[test]
+    fn beats_byte_huffman_on_text() {
+        let input = text(200_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+        let order_0 = compress_bytes(&HuffmanOptions::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+        assert!(compressed.len() < order_0.len() / 2);
+    }
+
+    #[test]
+    fn random_input_is_stored() {
+        let input = random_bytes(10_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Stored);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        assert_rejects_corrupted_data(&options, &text(20_000, 0));
+    }
+
+    #[test]
+    fn rejects_huge_block_and_symbol_counts() {
+        let compressed = compress_bytes(&Options::default(), &[b'a'; 1000]);
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+
+        let mut reader = Cursor::new(&compressed);
+        let header = Header::read_from(&mut reader).unwrap();
+        container::read_varint(&mut reader).unwrap();
+        let mut block_type = [0];
+        reader.read_exact(&mut block_type).unwrap();
+        let primary = container::read_varint(&mut reader).unwrap();
+        container::read_varint(&mut reader).unwrap();
+        let tables = &compressed[reader.position() as usize..];
+
+        for original_length in [Some(1000), None] {
+            let mut patched = Vec::new();
+            Header::new(header.codec, header.checksum, original_length)
+                .write_to(&mut patched)
+                .unwrap();
+            container::write_varint(&mut patched, 1_000_000_000_000).unwrap();
+            patched.extend_from_slice(&block_type);
+            container::write_varint(&mut patched, primary).unwrap();
+            container::write_varint(&mut patched, 1_000_000_000_000).unwrap();
+            patched.extend_from_slice(tables);
+
+            let error = decompress_bytes(&patched).unwrap_err();
+            assert!(matches!(Error::from(error), Error::LimitExceeded(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_zero_block_size() {
+        assert_rejects_options(&Options {
+            block_size: 0,
+            ..Options::default()
+        });
+    }
+}
@@ -0,0 +1,179 @@
+use crate::error::Error;
+use crate::huffman::Symbol;
+use std::io;
+
+/// The two digits of a run of zeros, written in bijective base 2 with the
+/// least significant digit first: `RUN_A` is worth 1 and `RUN_B` 2 in each
+/// place.
+pub const RUN_A: Symbol = 0;
+pub const RUN_B: Symbol = 1;
+/// The run digits, then the move-to-front positions 1 to 255 as 2 to 256.
+pub const ALPHABET_SIZE: usize = 257;
+
+/// Longest run of zeros a stream may encode, which bounds the run digits.
+const MAX_RUN_DIGITS: u32 = u32::BITS;
+
+/// Replaces each byte with its position in a list of recently seen bytes,
+/// then writes each run of zero positions as `RUN_A` and `RUN_B` digits.
+///
+/// After a BWT, bytes cluster together, so most positions are small and
+/// runs of zeros are long.
+pub fn encode(bytes: &[u8]) -> Vec<Symbol> {
+    let mut recent: [u8; 256] = std::array::from_fn(|byte| byte as u8);
+    let mut symbols = Vec::with_capacity(bytes.len() / 2);
+    let mut run = 0u64;
+
+    for &byte in bytes {
+        if recent[0] == byte {
+            run += 1;
+            continue;
+        }
+
+        push_run(&mut symbols, run);
+        run = 0;
+
+        // Shift the bytes before this one along until it is reached.
+        let mut moved = byte;
+        let mut position = 0;
+        for (index, slot) in recent.iter_mut().enumerate() {
+            std::mem::swap(slot, &mut moved);
+            if moved == byte {
+                position = index;
+                break;
+            }
+        }
+        symbols.push(position as Symbol + 1);
+    }
+
+    push_run(&mut symbols, run);
+
+    symbols
+}
+
+fn push_run(symbols: &mut Vec<Symbol>, mut run: u64) {
+    while run > 0 {
+        if run & 1 == 1 {
+            symbols.push(RUN_A);
+            run = (run - 1) / 2;
+        } else {
+            symbols.push(RUN_B);
+            run = (run - 2) / 2;
+        }
+    }
+}
+
+/// Undoes `encode`, failing as soon as the bytes would grow past `length`.
+pub fn decode(symbols: &[Symbol], length: u64) -> io::Result<Vec<u8>> {
+    let mut recent: [u8; 256] = std::array::from_fn(|byte| byte as u8);
+    let mut bytes = Vec::with_capacity(symbols.len().saturating_mul(2).min(length as usize));
+    let mut run = 0u64;
+    let mut digit = 0;
+
+    for &symbol in symbols {
+        match symbol {
+            RUN_A | RUN_B => {
+                if digit == MAX_RUN_DIGITS {
+                    return Err(Error::LimitExceeded("run of zeros is too long".into()).into());
+                }
+
+                run += (symbol as u64 + 1) << digit;
+                digit += 1;
+            }
+            _ => {
+                push_repeated(&mut bytes, recent[0], run, length)?;
+                run = 0;
+                digit = 0;
+
+                let position = symbol as usize - 1;
+                if position >= recent.len() {
+                    return Err(invalid_data("move-to-front position out of range"));
+                }
+
+                let byte = recent[position];
+                recent.copy_within(0..position, 1);
+                recent[0] = byte;
+                push_repeated(&mut bytes, byte, 1, length)?;
+            }
+        }
+    }
+
+    push_repeated(&mut bytes, recent[0], run, length)?;
+
+    Ok(bytes)
+}
+
+/// Pushes `count` copies of `byte`, unless that takes `bytes` past `length`.
+fn push_repeated(bytes: &mut Vec<u8>, byte: u8, count: u64, length: u64) -> io::Result<()> {
+    if count > length - bytes.len() as u64 {
+        return Err(invalid_data("block data is longer than its length"));
+    }
+
+    bytes.extend(std::iter::repeat_n(byte, count as usize));
+
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_positions_and_runs() {
+        // b: position 98, a: 98 (b moved ahead of it), then a run of three
+        // a's (1 + 2 * 1), then b at position 1.
+        let symbols = encode(b"baaaab");
+
+        assert_eq!(symbols, [99, 99, RUN_A, RUN_A, 2]);
+    }
+
+    #[test]
+    fn run_digits_count_in_bijective_base_2() {
+        for run in 1..100 {
+            let mut symbols = vec![];
+            push_run(&mut symbols, run);
+
+            let value: u64 = symbols
+                .iter()
+                .enumerate()
+                .map(|(place, &digit)| (digit as u64 + 1) << place)
+                .sum();
+            assert_eq!(value, run);
+        }
+    }
+
+    #[test]
+    fn decode_undoes_encode() {
+        for bytes in [
+            &b""[..],
+            b"\0",
+            b"\0\0\0\0\0\0\0",
+            b"aaaaaaaaaabbbbbbbbbbaaaaaaaaaa",
+            &(0..=255).cycle().take(2000).collect::<Vec<u8>>(),
+        ] {
+            assert_eq!(decode(&encode(bytes), bytes.len() as u64).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_position() {
+        assert!(decode(&[ALPHABET_SIZE as Symbol], 1).is_err());
+        assert!(decode(&[RUN_B; 40], u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_output_longer_than_length() {
+        // A run of 2^33 - 2 zeros.
+        let mut symbols = vec![RUN_B; MAX_RUN_DIGITS as usize];
+        assert!(decode(&symbols, 100).is_err());
+
+        symbols = encode(b"aaaa");
+        assert_eq!(decode(&symbols, 4).unwrap(), b"aaaa");
+        assert!(decode(&symbols, 3).is_err());
+        symbols.push(2);
+        assert!(decode(&symbols, 4).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::bwt::mtf::ALPHABET_SIZE;
+use crate::error::Error;
+use crate::framing::MAX_BLOCK_SIZE;
+use crate::huffman::Symbol;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::byte_map::{ByteMap, CodeLength};
+use crate::huffman::decode_table::DecodeTable;
+use crate::huffman::frequency::Frequencies;
+use crate::huffman::packed_table::{self, PackedTable};
+use std::io::{self, Read, Write};
+
+/// Symbols coded with the same table, each group choosing its own.
+pub const GROUP_SIZE: usize = 50;
+pub const MAX_TABLES: usize = 6;
+const TABLE_COUNT_BITS: u8 = 3;
+/// Rounds of choosing a table for each group and rebuilding the tables from
+/// the groups that chose them.
+const ITERATIONS: usize = 4;
+/// Length that the starting tables give to the symbols outside their share.
+const OUTSIDE_LENGTH: u32 = 15;
+
+/// Huffman codes `symbols` in groups of `GROUP_SIZE`, with up to
+/// `MAX_TABLES` tables tuned to different stretches of the data, as bzip2
+/// does.
+///
+/// ```text
+/// table count (3 bits) | packed table of each table
+/// | the table of each group, move-to-front coded in unary | coded symbols
+/// ```
+pub fn encode<W: Write>(
+    symbols: &[Symbol],
+    max_code_length: u8,
+    writer: &mut BitWriter<W>,
+) -> io::Result<()> {
+    let (byte_maps, selectors) = choose_tables(symbols, max_code_length);
+
+    writer.write_bits(byte_maps.len() as u32, TABLE_COUNT_BITS)?;
+    for byte_map in &byte_maps {
+        PackedTable::new(byte_map, ALPHABET_SIZE).write_to(writer)?;
+    }
+
+    let mut recent: Vec<u8> = (0..byte_maps.len() as u8).collect();
+    for &selector in &selectors {
+        let position = recent
+            .iter()
+            .position(|&table| table == selector)
+            .ok_or_else(|| corrupt_table("selector names no table"))?;
+        recent[..=position].rotate_right(1);
+
+        for _ in 0..position {
+            writer.write_bit(true)?;
+        }
+        writer.write_bit(false)?;
+    }
+
+    for (group, &selector) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+        let byte_map = &byte_maps[selector as usize];
+        for symbol in group {
+            let code = byte_map
+                .get(symbol)
+                .ok_or_else(|| corrupt_table("symbol has no code"))?;
+            writer.write_bits(code.bit_pattern, code.len)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks the tables and the table of each group.
+fn choose_tables(symbols: &[Symbol], max_code_length: u8) -> (Vec<ByteMap>, Vec<u8>) {
+    let table_count = match symbols.len() {
+        0..200 => 2,
+        200..600 => 3,
+        600..1200 => 4,
+        1200..2400 => 5,
+        _ => MAX_TABLES,
+    };
+
+    let mut frequencies = Frequencies::with_alphabet_size(ALPHABET_SIZE);
+    for &symbol in symbols {
+        frequencies[symbol as usize] += 1;
+    }
+
+    // Start with each table favouring its own slice of the alphabet,
+    // holding an equal share of the symbols.
+    let mut lengths = vec![[OUTSIDE_LENGTH; ALPHABET_SIZE]; table_count];
+    let mut start = 0;
+    let mut remaining = symbols.len() as u64;
+    for (table, lengths) in lengths.iter_mut().enumerate() {
+        let share = remaining / (table_count - table) as u64;
+        let mut taken = 0;
+
+        while start < ALPHABET_SIZE && (taken < share || table == table_count - 1) {
+            lengths[start] = 0;
+            taken += frequencies[start];
+            start += 1;
+        }
+
+        remaining -= taken;
+    }
+
+    let mut byte_maps = vec![];
+    let mut selectors = vec![];
+
+    for _ in 0..ITERATIONS {
+        selectors.clear();
+
+        // Every symbol in the block gets a code in every table, so that any
+        // group can use any table.
+        let mut table_frequencies: Vec<Frequencies> = (0..table_count)
+            .map(|_| {
+                let mut table = Frequencies::with_alphabet_size(ALPHABET_SIZE);
+                for (count, &total) in table.iter_mut().zip(frequencies.iter()) {
+                    *count = (total > 0) as u64;
+                }
+                table
+            })
+            .collect();
+
+        for group in symbols.chunks(GROUP_SIZE) {
+            let best = (0..table_count)
+                .min_by_key(|&table| {
+                    group
+                        .iter()
+                        .map(|&symbol| lengths[table][symbol as usize])
+                        .sum::<u32>()
+                })
+                .unwrap_or_default();
+
+            selectors.push(best as u8);
+            for &symbol in group {
+                table_frequencies[best][symbol as usize] += 1;
+            }
+        }
+
+        byte_maps = table_frequencies
+            .iter()
+            .map(|table| table.to_huff_tree().to_byte_map(max_code_length))
+            .collect();
+
+        for (lengths, byte_map) in lengths.iter_mut().zip(&byte_maps) {
+            for (&symbol, code) in byte_map.iter() {
+                lengths[symbol as usize] = code.len as u32;
+            }
+        }
+    }
+
+    (byte_maps, selectors)
+}
+
+/// Decodes `count` symbols written by `encode`.
+pub fn decode<R: Read>(reader: &mut BitReader<R>, count: usize) -> io::Result<Vec<Symbol>> {
+    let table_count = reader.read_bits(TABLE_COUNT_BITS)? as usize;
+    if !(1..=MAX_TABLES).contains(&table_count) {
+        return Err(corrupt_table("invalid number of Huffman tables"));
+    }
+
+    let mut tables = Vec::with_capacity(table_count);
+    for _ in 0..table_count {
+        let mut code_lengths: Vec<CodeLength> =
+            packed_table::read_code_lengths(reader, ALPHABET_SIZE)?;
+        tables.push(DecodeTable::new(&mut code_lengths)?);
+    }
+
+    let mut recent: Vec<usize> = (0..table_count).collect();
+    // `count` comes from the stream, so only trust it as far as a block goes.
+    let capacity = count.min(MAX_BLOCK_SIZE);
+    let mut selectors = Vec::with_capacity(capacity.div_ceil(GROUP_SIZE));
+    for _ in 0..count.div_ceil(GROUP_SIZE) {
+        let mut position = 0;
+        while reader.read_bits(1)? == 1 {
+            position += 1;
+            if position == table_count {
+                return Err(corrupt_table("table selector out of range"));
+            }
+        }
+
+        recent[..=position].rotate_right(1);
+        selectors.push(recent[0]);
+    }
+
+    let mut symbols = Vec::with_capacity(capacity);
+    for selector in selectors {
+        let table = &tables[selector];
+        let group_len = GROUP_SIZE.min(count - symbols.len());
+
+        for _ in 0..group_len {
+            symbols.push(table.decode(reader)?);
+        }
+    }
+
+    Ok(symbols)
+}
+
+fn corrupt_table(message: &str) -> io::Error {
+    Error::CorruptTable(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn round_trip(symbols: &[Symbol]) -> Vec<u8> {
+        let mut writer = BitWriter::new(vec![]);
+        encode(symbols, 15, &mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let coded = writer.writer;
+        let mut reader = BitReader::new(Cursor::new(&coded));
+        assert_eq!(decode(&mut reader, symbols.len()).unwrap(), symbols);
+
+        coded
+    }
+
+    #[test]
+    fn round_trips_symbol_sequences() {
+        round_trip(&[5]);
+        round_trip(&[0; 1000]);
+        round_trip(
+            &(0..ALPHABET_SIZE as Symbol)
+                .cycle()
+                .take(5000)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn groups_pick_tables_suited_to_them() {
+        // Two stretches with disjoint symbols: separate tables code each in
+        // about 3 bits a symbol, where one shared table would need 4.
+        let mut symbols: Vec<Symbol> = (0..8).cycle().take(5000).collect();
+        symbols.extend((100..108).cycle().take(5000));
+
+        let coded = round_trip(&symbols);
+
+        assert!(coded.len() < 10_000 * 7 / 2 / 8);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::Error;
+use std::io;
+
+/// The start of each suffix of `text`, in sorted order, a suffix that is a
+/// prefix of another sorting first.
+///
+/// Built by prefix doubling: suffixes are ranked by their first `k` bytes,
+/// then sorted by pairs of ranks for the first `2k`, until every rank is
+/// distinct.
+pub fn suffix_array(text: &[u8]) -> Vec<u32> {
+    let n = text.len();
+
+    // Rank 0 stands for "past the end", below every byte.
+    let mut rank: Vec<u32> = text.iter().map(|&byte| byte as u32 + 1).collect();
+    let mut entries: Vec<(u64, u32)> = Vec::with_capacity(n);
+    let mut k = 1;
+
+    loop {
+        entries.clear();
+        entries.extend((0..n).map(|i| {
+            let next = rank.get(i + k).copied().unwrap_or(0);
+            (((rank[i] as u64) << 32) | next as u64, i as u32)
+        }));
+        entries.sort_unstable();
+
+        let mut distinct = 0;
+        for (position, &(key, suffix)) in entries.iter().enumerate() {
+            if position == 0 || key != entries[position - 1].0 {
+                distinct += 1;
+            }
+            rank[suffix as usize] = distinct;
+        }
+
+        if distinct as usize == n || k >= n {
+            break;
+        }
+
+        k *= 2;
+    }
+
+    entries.into_iter().map(|(_, suffix)| suffix).collect()
+}
+
+/// The Burrows–Wheeler transform of `block`, taken over the sorted
+/// suffixes of `block` followed by an end marker that sorts first.
+///
+/// Returns the byte before each suffix, the marker excluded, and the row
+/// where the marker would have been, which `inverse` needs.
+pub fn forward(block: &[u8]) -> (Vec<u8>, usize) {
+    let Some(&last) = block.last() else {
+        return (vec![], 0);
+    };
+
+    let mut transformed = Vec::with_capacity(block.len());
+    let mut primary = 0;
+
+    // The row of the empty suffix, which is preceded by the last byte.
+    transformed.push(last);
+
+    for (row, &start) in suffix_array(block).iter().enumerate() {
+        if start == 0 {
+            primary = row + 1;
+        } else {
+            transformed.push(block[start as usize - 1]);
+        }
+    }
+
+    (transformed, primary)
+}
+
+/// Undoes `forward`.
+pub fn inverse(transformed: &[u8], primary: usize) -> io::Result<Vec<u8>> {
+    let n = transformed.len();
+    if n == 0 {
+        return Ok(vec![]);
+    }
+
+    if !(1..=n).contains(&primary) {
+        return Err(invalid_data("BWT primary index out of range"));
+    }
+
+    // Row `row` ends with this byte, or the marker at `primary`.
+    let byte_at = |row: usize| transformed[if row < primary { row } else { row - 1 }];
+
+    // The first row of each byte among the sorted rows; the marker's row
+    // comes first of all.
+    let mut first = [0usize; 256];
+    for &byte in transformed {
+        first[byte as usize] += 1;
+    }
+    let mut next = 1;
+    for slot in &mut first {
+        let count = *slot;
+        *slot = next;
+        next += count;
+    }
+
+    // The row that starts one byte earlier than each row.
+    let mut previous = vec![0u32; n + 1];
+    for (row, slot) in previous.iter_mut().enumerate() {
+        if row != primary {
+            let byte = byte_at(row) as usize;
+            *slot = first[byte] as u32;
+            first[byte] += 1;
+        }
+    }
+
+    // Walk back from the empty suffix, collecting bytes from the end.
+    let mut block = vec![0; n];
+    let mut row = 0;
+
+    for position in (0..n).rev() {
+        if row == primary {
+            return Err(invalid_data("BWT data does not form a single cycle"));
+        }
+
+        block[position] = byte_at(row);
+        row = previous[row] as usize;
+    }
+
+    if row != primary {
+        return Err(invalid_data("BWT data does not form a single cycle"));
+    }
+
+    Ok(block)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive_suffix_array(text: &[u8]) -> Vec<u32> {
+        let mut suffixes: Vec<u32> = (0..text.len() as u32).collect();
+        suffixes.sort_by_key(|&start| &text[start as usize..]);
+        suffixes
+    }
+
+    #[test]
+    fn suffix_array_matches_naive_sort() {
+        for text in [
+            &b"banana"[..],
+            b"mississippi",
+            b"aaaaaaaaaaaa",
+            b"abababababab",
+            b"a",
+            b"",
+            &(0..=255).rev().collect::<Vec<u8>>(),
+        ] {
+            assert_eq!(suffix_array(text), naive_suffix_array(text));
+        }
+    }
+
+    #[test]
+    fn transforms_banana() {
+        // Sorted suffixes of "banana$": $, a$, ana$, anana$, banana$, na$,
+        // nana$.
+        assert_eq!(forward(b"banana"), (b"annbaa".to_vec(), 4));
+    }
+
+    #[test]
+    fn inverse_undoes_forward() {
+        for block in [
+            &b"banana"[..],
+            b"a",
+            b"abracadabra abracadabra",
+            b"aaaaaaaaaaaa",
+            b"abababababab",
+            &(0..=255).cycle().take(3000).collect::<Vec<u8>>(),
+        ] {
+            let (transformed, primary) = forward(block);
+            assert_eq!(inverse(&transformed, primary).unwrap(), block);
+        }
+    }
+
+    #[test]
+    fn inverse_rejects_bad_primary_index() {
+        assert!(inverse(b"annbaa", 0).is_err());
+        assert!(inverse(b"annbaa", 7).is_err());
+        // A valid index that breaks the permutation into several cycles.
+        assert!(inverse(b"annbaa", 1).is_err());
+    }
+}
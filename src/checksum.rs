@@ -0,0 +1,351 @@
+use crate::error::Error;
+use std::io;
+
+/// Which checksum, if any, is stored in the trailer of a compressed stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumKind {
+    #[default]
+    None,
+    Crc32,
+    XxHash64,
+}
+
+impl ChecksumKind {
+    pub fn new_checksum(self) -> Checksum {
+        match self {
+            ChecksumKind::None => Checksum::None,
+            ChecksumKind::Crc32 => Checksum::Crc32(Crc32::new()),
+            ChecksumKind::XxHash64 => Checksum::XxHash64(XxHash64::new(0)),
+        }
+    }
+
+    /// Size of the checksum in the stream trailer, in bytes.
+    pub fn trailer_len(self) -> usize {
+        match self {
+            ChecksumKind::None => 0,
+            ChecksumKind::Crc32 => 4,
+            ChecksumKind::XxHash64 => 8,
+        }
+    }
+}
+
+/// A running checksum over the uncompressed data.
+pub enum Checksum {
+    None,
+    Crc32(Crc32),
+    XxHash64(XxHash64),
+}
+
+impl Checksum {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Checksum::None => {}
+            Checksum::Crc32(crc) => crc.update(bytes),
+            Checksum::XxHash64(hash) => hash.update(bytes),
+        }
+    }
+
+    /// The finished checksum as it is stored in the trailer (big-endian).
+    pub fn to_trailer(&self) -> Vec<u8> {
+        match self {
+            Checksum::None => vec![],
+            Checksum::Crc32(crc) => crc.finish().to_be_bytes().to_vec(),
+            Checksum::XxHash64(hash) => hash.finish().to_be_bytes().to_vec(),
+        }
+    }
+
+    pub fn verify(&self, trailer: &[u8]) -> io::Result<()> {
+        if self.to_trailer() != trailer {
+            return Err(Error::ChecksumMismatch.into());
+        }
+
+        Ok(())
+    }
+}
+
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// CRC-32 as used by zlib, gzip and PNG (reflected, polynomial `0x04C11DB7`).
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+const ADLER32_MODULUS: u32 = 65521;
+/// Most bytes that can be summed before `b` could overflow a `u32`.
+const ADLER32_CHUNK: usize = 5552;
+
+/// Adler-32, the checksum in the zlib trailer (RFC 1950).
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(ADLER32_CHUNK) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+
+            self.a %= ADLER32_MODULUS;
+            self.b %= ADLER32_MODULUS;
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+const XXH64_STRIPE: usize = 32;
+
+/// Streaming xxHash64.
+pub struct XxHash64 {
+    seed: u64,
+    lanes: [u64; 4],
+    stripe: [u8; XXH64_STRIPE],
+    stripe_len: usize,
+    total_len: u64,
+}
+
+impl XxHash64 {
+    pub fn new(seed: u64) -> Self {
+        XxHash64 {
+            seed,
+            lanes: [
+                seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+                seed.wrapping_add(PRIME64_2),
+                seed,
+                seed.wrapping_sub(PRIME64_1),
+            ],
+            stripe: [0; XXH64_STRIPE],
+            stripe_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.total_len += bytes.len() as u64;
+
+        if self.stripe_len > 0 {
+            let take = bytes.len().min(XXH64_STRIPE - self.stripe_len);
+            self.stripe[self.stripe_len..self.stripe_len + take].copy_from_slice(&bytes[..take]);
+            self.stripe_len += take;
+            bytes = &bytes[take..];
+
+            if self.stripe_len < XXH64_STRIPE {
+                return;
+            }
+
+            let stripe = self.stripe;
+            self.consume_stripe(&stripe);
+            self.stripe_len = 0;
+        }
+
+        let mut stripes = bytes.chunks_exact(XXH64_STRIPE);
+        for stripe in &mut stripes {
+            self.consume_stripe(stripe);
+        }
+
+        let rest = stripes.remainder();
+        self.stripe[..rest.len()].copy_from_slice(rest);
+        self.stripe_len = rest.len();
+    }
+
+    pub fn finish(&self) -> u64 {
+        let mut hash = if self.total_len >= XXH64_STRIPE as u64 {
+            let [v1, v2, v3, v4] = self.lanes;
+            let mut hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+
+            for lane in self.lanes {
+                hash = xxh64_merge_round(hash, lane);
+            }
+
+            hash
+        } else {
+            self.seed.wrapping_add(PRIME64_5)
+        };
+
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut rest = &self.stripe[..self.stripe_len];
+
+        while let Some((lane, tail)) = rest.split_first_chunk::<8>() {
+            let lane = u64::from_le_bytes(*lane);
+            hash ^= xxh64_round(0, lane);
+            hash = hash
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+            rest = tail;
+        }
+
+        if let Some((lane, tail)) = rest.split_first_chunk::<4>() {
+            let lane = u32::from_le_bytes(*lane) as u64;
+            hash ^= lane.wrapping_mul(PRIME64_1);
+            hash = hash
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            rest = tail;
+        }
+
+        for &byte in rest {
+            hash ^= (byte as u64).wrapping_mul(PRIME64_5);
+            hash = hash.rotate_left(11).wrapping_mul(PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^ (hash >> 32)
+    }
+
+    fn consume_stripe(&mut self, stripe: &[u8]) {
+        let (lanes, _) = stripe.as_chunks::<8>();
+        for (lane, &bytes) in self.lanes.iter_mut().zip(lanes) {
+            *lane = xxh64_round(*lane, u64::from_le_bytes(bytes));
+        }
+    }
+}
+
+fn xxh64_round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn xxh64_merge_round(hash: u64, lane: u64) -> u64 {
+    (hash ^ xxh64_round(0, lane))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(bytes);
+        crc.finish()
+    }
+
+    fn adler32(bytes: &[u8]) -> u32 {
+        let mut adler = Adler32::new();
+        adler.update(bytes);
+        adler.finish()
+    }
+
+    fn xxh64(bytes: &[u8]) -> u64 {
+        let mut hash = XxHash64::new(0);
+        hash.update(bytes);
+        hash.finish()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn adler32_reference_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // Long enough to need the modulus within a chunk.
+        assert_eq!(adler32(&[0xFF; 100_000]), 0x149A_302C);
+    }
+
+    #[test]
+    fn xxh64_reference_values() {
+        assert_eq!(xxh64(b""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxh64(b"a"), 0xD24E_C4F1_A98C_6E5B);
+        assert_eq!(xxh64(b"abc"), 0x44BC_2CF5_AD77_0999);
+        assert_eq!(
+            xxh64(b"Nobody inspects the spammish repetition"),
+            0xFBCE_A83C_8A37_8BF1
+        );
+    }
+
+    #[test]
+    fn xxh64_streaming_matches_one_shot() {
+        let input: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 256) as u8).collect();
+
+        for split in [0, 1, 7, 31, 32, 33, 100, 999] {
+            let mut hash = XxHash64::new(0);
+            hash.update(&input[..split]);
+            hash.update(&input[split..]);
+
+            assert_eq!(hash.finish(), xxh64(&input), "split at {split}");
+        }
+    }
+
+    #[test]
+    fn checksum_verify_detects_mismatch() {
+        let mut checksum = ChecksumKind::Crc32.new_checksum();
+        checksum.update(b"hello");
+
+        let trailer = checksum.to_trailer();
+        assert_eq!(trailer.len(), ChecksumKind::Crc32.trailer_len());
+        assert!(checksum.verify(&trailer).is_ok());
+
+        let error = checksum.verify(&[0, 0, 0, 0]).unwrap_err();
+        assert_eq!(error.to_string(), "checksum mismatch");
+    }
+}
@@ -0,0 +1,329 @@
+//! A common interface to the codecs, and the registry `decompress` searches
+//! for the codec named in a stream's header.
+//!
+//! Each codec's options type implements `Codec`, compressing with those
+//! options. Decompressing needs no options: everything the decoder needs is
+//! in the stream.
+
+use crate::container::{CodecId, Header};
+use crate::error::Error;
+use crate::{adaptive_huffman, ans, bwt, context_huffman, huffman, lz77, range_coder};
+use std::io::{BufReader, Read, Write};
+
+/// A compression method writing streams that start with a `Header`.
+pub trait Codec {
+    /// The name the command line knows the codec by.
+    fn name(&self) -> &'static str;
+
+    /// The id recorded in the header of the streams the codec writes.
+    fn id(&self) -> CodecId;
+
+    /// Compresses `input`, header included.
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error>;
+
+    /// Decodes the data that follows `header`, which was read from the start
+    /// of `input`.
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error>;
+}
+
+/// Every codec, with default options, in order of id.
+pub fn registry() -> Vec<Box<dyn Codec>> {
+    vec![
+        Box::new(huffman::Options::default()),
+        Box::new(lz77::Options::default()),
+        Box::new(adaptive_huffman::Options::default()),
+        Box::new(range_coder::Options::default()),
+        Box::new(ans::Options {
+            variant: ans::Variant::Rans,
+            ..ans::Options::default()
+        }),
+        Box::new(ans::Options {
+            variant: ans::Variant::Tans,
+            ..ans::Options::default()
+        }),
+        Box::new(context_huffman::Options::default()),
+        Box::new(bwt::Options::default()),
+    ]
+}
+
+/// The registered codec called `name`, with default options.
+pub fn codec_named(name: &str) -> Option<Box<dyn Codec>> {
+    registry().into_iter().find(|codec| codec.name() == name)
+}
+
+/// The registered codec that writes streams with `id`.
+pub fn codec_with_id(id: CodecId) -> Result<Box<dyn Codec>, Error> {
+    registry()
+        .into_iter()
+        .find(|codec| codec.id() == id)
+        .ok_or_else(|| Error::Unsupported(format!("no codec registered for {id:?} streams")))
+}
+
+impl Codec for huffman::Options {
+    fn name(&self) -> &'static str {
+        "huffman"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::Huffman
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(huffman::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(huffman::decompress(BufReader::new(input), output, header)?)
+    }
+}
+
+impl Codec for lz77::Options {
+    fn name(&self) -> &'static str {
+        "lz77"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::Lz77
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(lz77::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(lz77::decompress(BufReader::new(input), output, header)?)
+    }
+}
+
+impl Codec for adaptive_huffman::Options {
+    fn name(&self) -> &'static str {
+        "adaptive-huffman"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::AdaptiveHuffman
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(adaptive_huffman::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(adaptive_huffman::decompress(
+            BufReader::new(input),
+            output,
+            header,
+        )?)
+    }
+}
+
+impl Codec for range_coder::Options {
+    fn name(&self) -> &'static str {
+        "range-coder"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::RangeCoder
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(range_coder::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(range_coder::decompress(
+            BufReader::new(input),
+            output,
+            header,
+        )?)
+    }
+}
+
+/// rANS or tANS, as `variant` selects.
+impl Codec for ans::Options {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            ans::Variant::Rans => "rans",
+            ans::Variant::Tans => "tans",
+        }
+    }
+
+    fn id(&self) -> CodecId {
+        self.variant.codec()
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(ans::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        header.expect_codec(self.id())?;
+
+        Ok(ans::decompress(BufReader::new(input), output, header)?)
+    }
+}
+
+impl Codec for context_huffman::Options {
+    fn name(&self) -> &'static str {
+        "context-huffman"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::ContextHuffman
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(context_huffman::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(context_huffman::decompress(
+            BufReader::new(input),
+            output,
+            header,
+        )?)
+    }
+}
+
+impl Codec for bwt::Options {
+    fn name(&self) -> &'static str {
+        "bwt"
+    }
+
+    fn id(&self) -> CodecId {
+        CodecId::Bwt
+    }
+
+    fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+        Ok(bwt::compress(input, output, self)?)
+    }
+
+    fn decompress(
+        &self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        header: &Header,
+    ) -> Result<(), Error> {
+        Ok(bwt::decompress(BufReader::new(input), output, header)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompress;
+    use crate::test_support::compress_bytes;
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn every_codec_id_is_registered_once() {
+        let ids: Vec<u8> = registry().iter().map(|codec| codec.id() as u8).collect();
+
+        assert_eq!(ids, (1..=8).collect::<Vec<u8>>());
+        for id in ids {
+            assert!(codec_with_id(CodecId::try_from(id).unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn names_find_their_codec() {
+        for codec in registry() {
+            let found = codec_named(codec.name()).unwrap();
+
+            assert_eq!(found.id(), codec.id());
+        }
+
+        assert!(codec_named("zip").is_none());
+    }
+
+    #[test]
+    fn decompress_dispatches_on_header_codec() {
+        let input = b"the codec that wrote a stream is read back from its header ".repeat(50);
+
+        for codec in registry() {
+            let mut compressed = Vec::new();
+            codec
+                .compress(&mut Cursor::new(&input), &mut compressed)
+                .unwrap();
+
+            let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+            assert_eq!(header.codec, codec.id(), "{}", codec.name());
+
+            let mut output = Vec::new();
+            decompress(Cursor::new(&compressed), &mut output).unwrap();
+            assert_eq!(output, input, "{}", codec.name());
+        }
+    }
+
+    #[test]
+    fn codec_rejects_other_codecs_streams() {
+        let mut compressed = Vec::new();
+        codec_named("rans")
+            .unwrap()
+            .compress(&mut Cursor::new(b"abc"), &mut compressed)
+            .unwrap();
+
+        let mut reader = Cursor::new(&compressed);
+        let header = Header::read_from(&mut reader).unwrap();
+
+        for name in ["huffman", "tans"] {
+            let error = codec_named(name)
+                .unwrap()
+                .decompress(&mut reader, &mut Vec::new(), &header)
+                .unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn decompress_reports_what_is_wrong() {
+        let error = decompress(Cursor::new(b"not a stream"), Vec::new()).unwrap_err();
+        assert!(matches!(error, Error::BadMagic));
+
+        let options = huffman::Options {
+            checksum: crate::ChecksumKind::Crc32,
+            ..huffman::Options::default()
+        };
+        let mut compressed = compress_bytes(&options, b"abcabcabc");
+        *compressed.last_mut().unwrap() ^= 1;
+
+        let error = decompress(Cursor::new(compressed), Vec::new()).unwrap_err();
+        assert!(matches!(error, Error::ChecksumMismatch));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::checksum::ChecksumKind;
+use crate::error::Error;
+use crate::filter::{self, Filter};
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"CMPR";
+pub const FORMAT_VERSION: u8 = 2;
+
+const VARINT_MAX_BYTES: usize = 10;
+
+const FLAG_CRC32: u8 = 0b001;
+const FLAG_XXHASH64: u8 = 0b010;
+const FLAG_CHECKSUM_MASK: u8 = FLAG_CRC32 | FLAG_XXHASH64;
+const FLAG_UNKNOWN_LENGTH: u8 = 0b100;
+const FLAG_FILTERS: u8 = 0b1000;
+const KNOWN_FLAGS: u8 = FLAG_CHECKSUM_MASK | FLAG_UNKNOWN_LENGTH | FLAG_FILTERS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecId {
+    Huffman = 1,
+    Lz77 = 2,
+    AdaptiveHuffman = 3,
+    RangeCoder = 4,
+    Rans = 5,
+    Tans = 6,
+    ContextHuffman = 7,
+    Bwt = 8,
+}
+
+impl TryFrom<u8> for CodecId {
+    type Error = io::Error;
+
+    fn try_from(id: u8) -> io::Result<Self> {
+        match id {
+            1 => Ok(CodecId::Huffman),
+            2 => Ok(CodecId::Lz77),
+            3 => Ok(CodecId::AdaptiveHuffman),
+            4 => Ok(CodecId::RangeCoder),
+            5 => Ok(CodecId::Rans),
+            6 => Ok(CodecId::Tans),
+            7 => Ok(CodecId::ContextHuffman),
+            8 => Ok(CodecId::Bwt),
+            _ => Err(Error::Unsupported(format!("unknown codec id {id}")).into()),
+        }
+    }
+}
+
+/// Size of the header fields before the original length.
+pub const HEADER_FIXED_LEN: u64 = MAGIC.len() as u64 + 3;
+
+/// The header written at the start of every compressed stream.
+///
+/// ```text
+/// magic (4) | version (1) | codec id (1) | flags (1) | [filters]
+/// | original length (varint)
+/// ```
+///
+/// The flags select the checksum stored in the trailer after the codec's
+/// data, if any, mark streams whose input was filtered, in which case the
+/// chain of filters follows as `filter::write_filters` writes it, and mark
+/// streams whose length was not known up front, in which case the original
+/// length is omitted.
+///
+/// With filters, the codec codes, counts and checksums the filtered input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub codec: CodecId,
+    pub checksum: ChecksumKind,
+    /// Applied to the input in order before the codec saw it.
+    pub filters: Vec<Filter>,
+    pub original_length: Option<u64>,
+}
+
+impl Header {
+    pub fn new(codec: CodecId, checksum: ChecksumKind, original_length: Option<u64>) -> Self {
+        Header {
+            codec,
+            checksum,
+            filters: vec![],
+            original_length,
+        }
+    }
+
+    pub fn with_filters(self, filters: &[Filter]) -> Self {
+        Header {
+            filters: filters.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut flags = match self.checksum {
+            ChecksumKind::None => 0,
+            ChecksumKind::Crc32 => FLAG_CRC32,
+            ChecksumKind::XxHash64 => FLAG_XXHASH64,
+        };
+
+        if self.original_length.is_none() {
+            flags |= FLAG_UNKNOWN_LENGTH;
+        }
+
+        if !self.filters.is_empty() {
+            flags |= FLAG_FILTERS;
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION, self.codec as u8, flags])?;
+
+        if !self.filters.is_empty() {
+            filter::write_filters(writer, &self.filters)?;
+        }
+
+        match self.original_length {
+            Some(length) => write_varint(writer, length),
+            None => Ok(()),
+        }
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(not_compressed)?;
+
+        if magic != MAGIC {
+            return Err(Error::BadMagic.into());
+        }
+
+        let mut fields = [0; 3];
+        reader.read_exact(&mut fields)?;
+
+        let [version, codec, flags] = fields;
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version).into());
+        }
+
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(
+                Error::Unsupported(format!("unsupported header flags {flags:#04x}")).into(),
+            );
+        }
+
+        let checksum = match flags & FLAG_CHECKSUM_MASK {
+            0 => ChecksumKind::None,
+            FLAG_CRC32 => ChecksumKind::Crc32,
+            FLAG_XXHASH64 => ChecksumKind::XxHash64,
+            _ => return Err(invalid_data("conflicting checksum flags".to_string())),
+        };
+
+        let filters = match flags & FLAG_FILTERS {
+            0 => vec![],
+            _ => filter::read_filters(reader)?,
+        };
+
+        if flags & FLAG_FILTERS != 0 && filters.is_empty() {
+            return Err(invalid_data("empty filter chain".to_string()));
+        }
+
+        let original_length = match flags & FLAG_UNKNOWN_LENGTH {
+            0 => Some(read_varint(reader)?),
+            _ => None,
+        };
+
+        Ok(Header {
+            codec: CodecId::try_from(codec)?,
+            checksum,
+            filters,
+            original_length,
+        })
+    }
+
+    pub fn expect_codec(&self, codec: CodecId) -> io::Result<()> {
+        if self.codec != codec {
+            return Err(invalid_data(format!(
+                "expected a {codec:?} stream, found {:?}",
+                self.codec
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `value` as an unsigned LEB128 varint.
+pub fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut bytes = [0; VARINT_MAX_BYTES];
+    let mut len = 0;
+
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+
+    writer.write_all(&bytes[..len])
+}
+
+/// Number of bytes `write_varint` uses for `value`.
+pub fn varint_len(value: u64) -> u64 {
+    (u64::BITS - value.leading_zeros()).div_ceil(7).max(1) as u64
+}
+
+pub fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+
+    for i in 0..VARINT_MAX_BYTES {
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte)?;
+
+        let [byte] = byte;
+        let bits = (byte & 0x7F) as u64;
+        let shift = 7 * i as u32;
+
+        if bits.checked_shl(shift).map(|shifted| shifted >> shift) != Some(bits) {
+            return Err(Error::LimitExceeded("varint overflows u64".into()).into());
+        }
+
+        value |= bits << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(Error::LimitExceeded("varint is too long".into()).into())
+}
+
+fn not_compressed(error: io::Error) -> io::Error {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => Error::BadMagic.into(),
+        _ => error,
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    Error::CorruptData(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn round_trip_varint(value: u64) -> u64 {
+        let mut bytes = vec![];
+        write_varint(&mut bytes, value).unwrap();
+        read_varint(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn varint_encoding() {
+        let mut bytes = vec![];
+        write_varint(&mut bytes, 300).unwrap();
+
+        assert_eq!(bytes, vec![0b1010_1100, 0b0000_0010]);
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, u32::MAX as u64, u64::MAX] {
+            assert_eq!(round_trip_varint(value), value);
+        }
+    }
+
+    #[test]
+    fn varint_len_matches_encoding() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, u32::MAX as u64, u64::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value).unwrap();
+
+            assert_eq!(varint_len(value), bytes.len() as u64, "value {value}");
+        }
+    }
+
+    #[test]
+    fn varint_rejects_overflow() {
+        let bytes = [0xFF; 9].into_iter().chain([0x02]).collect::<Vec<_>>();
+
+        assert!(read_varint(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn header_layout() {
+        let mut bytes = vec![];
+        Header::new(CodecId::Huffman, ChecksumKind::None, Some(5))
+            .write_to(&mut bytes)
+            .unwrap();
+
+        assert_eq!(bytes, vec![b'C', b'M', b'P', b'R', 2, 1, 0, 5]);
+    }
+
+    #[test]
+    fn header_omits_unknown_length() {
+        let header = Header::new(CodecId::Huffman, ChecksumKind::None, None);
+
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+
+        assert_eq!(
+            bytes,
+            vec![b'C', b'M', b'P', b'R', 2, 1, FLAG_UNKNOWN_LENGTH]
+        );
+        assert_eq!(Header::read_from(&mut Cursor::new(bytes)).unwrap(), header);
+    }
+
+    #[test]
+    fn header_records_checksum_in_flags() {
+        let mut bytes = vec![];
+        Header::new(CodecId::Huffman, ChecksumKind::XxHash64, Some(5))
+            .write_to(&mut bytes)
+            .unwrap();
+
+        assert_eq!(bytes[6], FLAG_XXHASH64);
+    }
+
+    #[test]
+    fn header_records_filters_after_flags() {
+        let filters = [Filter::Delta { stride: 2 }, Filter::Rle];
+        let header =
+            Header::new(CodecId::Huffman, ChecksumKind::None, Some(5)).with_filters(&filters);
+
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+
+        let mut expected = b"CMPR\x02\x01".to_vec();
+        expected.push(FLAG_FILTERS);
+        filter::write_filters(&mut expected, &filters).unwrap();
+        expected.push(5);
+
+        assert_eq!(bytes, expected);
+        assert_eq!(Header::read_from(&mut Cursor::new(bytes)).unwrap(), header);
+    }
+
+    #[test]
+    fn header_rejects_empty_or_unknown_filters() {
+        for filters in [&[0][..], &[1, 0xEE]] {
+            let mut bytes = b"CMPR\x02\x01".to_vec();
+            bytes.push(FLAG_FILTERS);
+            bytes.extend(filters);
+            bytes.push(0);
+
+            let error = Header::read_from(&mut Cursor::new(bytes)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header::new(CodecId::Huffman, ChecksumKind::Crc32, Some(1 << 40));
+
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+
+        assert_eq!(Header::read_from(&mut Cursor::new(bytes)).unwrap(), header);
+    }
+
+    #[test]
+    fn header_rejects_bad_magic() {
+        let error = Header::read_from(&mut Cursor::new(b"PK\x03\x04\x02\x01\x00\x00")).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_rejects_short_input() {
+        let error = Header::read_from(&mut Cursor::new(b"CM")).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_rejects_unsupported_version() {
+        let error = Header::read_from(&mut Cursor::new(b"CMPR\x01\x01\x00\x00")).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_rejects_unknown_codec() {
+        let error = Header::read_from(&mut Cursor::new(b"CMPR\x02\xEE\x00\x00")).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_rejects_unknown_flags() {
+        for flags in [b"\x80", b"\x10", b"\x03"] {
+            let mut bytes = b"CMPR\x02\x01".to_vec();
+            bytes.extend_from_slice(flags);
+            bytes.push(0);
+
+            let error = Header::read_from(&mut Cursor::new(bytes)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+mod clustering;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{CodecId, Header};
+use crate::context_huffman::clustering::Clustering;
+use crate::error::Error;
+use crate::framing::{self, BlockType, Frame};
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::block::bits_to_bytes;
+use crate::huffman::byte_map::ByteMap;
+use crate::huffman::decode_table::DecodeTable;
+use crate::huffman::frequency::Frequencies;
+use crate::huffman::packed_table::{self, PackedTable};
+use crate::huffman::{self, DEFAULT_BLOCK_SIZE, DEFAULT_MAX_CODE_LENGTH, Symbol};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+const BYTE_ALPHABET_SIZE: usize = 256;
+
+pub const DEFAULT_MAX_TABLES: usize = 32;
+
+/// Bits of the table count, which is written less one.
+const TABLE_COUNT_BITS: u8 = 8;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and coded as one block.
+    pub block_size: usize,
+    /// Longest code, in bits, that a code table may assign to a byte.
+    pub max_code_length: u8,
+    /// Most code tables a block may have, from 1 to 256. Each previous byte
+    /// is given one of them.
+    pub max_tables: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_code_length: DEFAULT_MAX_CODE_LENGTH,
+            max_tables: DEFAULT_MAX_TABLES,
+        }
+    }
+}
+
+/// Compresses `input` with order-1 Huffman coding: each byte is coded with
+/// a table chosen by the byte before it, which captures much of the
+/// structure of text without string matching.
+///
+/// The previous bytes are grouped into clusters with similar histograms,
+/// each sharing one canonical code. Each block is written as `framing`
+/// describes, stored or coded. A coded block holds:
+///
+/// ```text
+/// table count - 1 (8 bits) | table of each previous byte (256 fields of
+/// ceil(log2(table count)) bits) | packed code tables | coded data
+/// ```
+///
+/// The first byte of the stream follows a zero byte, and later blocks carry
+/// on from the last byte of the one before.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    huffman::check_options(
+        options.block_size,
+        options.max_code_length,
+        BYTE_ALPHABET_SIZE,
+    )?;
+
+    if !(1..=BYTE_ALPHABET_SIZE).contains(&options.max_tables) {
+        return Err(Error::InvalidOptions(format!(
+            "max tables must be between 1 and {BYTE_ALPHABET_SIZE}"
+        ))
+        .into());
+    }
+
+    let frame = Frame {
+        codec: CodecId::ContextHuffman,
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+    let mut previous = 0;
+
+    framing::compress(input, output, &frame, |writer, block| {
+        encode_block(writer, block, previous, options)?;
+        previous = block.last().copied().unwrap_or(previous);
+
+        Ok(())
+    })
+}
+
+/// Writes the type and data of a block whose first byte follows `previous`.
+fn encode_block<W: Write>(
+    writer: &mut BitWriter<W>,
+    block: &[u8],
+    previous: u8,
+    options: &Options,
+) -> io::Result<()> {
+    let mut contexts: Vec<Frequencies> = (0..BYTE_ALPHABET_SIZE)
+        .map(|_| Frequencies::new())
+        .collect();
+
+    let mut context = previous;
+    for &byte in block {
+        contexts[context as usize][byte as usize] += 1;
+        context = byte;
+    }
+
+    let Clustering {
+        context_map,
+        clusters,
+    } = clustering::cluster(&contexts, options.max_tables);
+
+    let byte_maps: Vec<ByteMap> = clusters
+        .iter()
+        .map(|cluster| cluster.to_huff_tree().to_byte_map(options.max_code_length))
+        .collect();
+    let tables: Vec<PackedTable> = byte_maps
+        .iter()
+        .map(|byte_map| PackedTable::new(byte_map, BYTE_ALPHABET_SIZE))
+        .collect();
+
+    let map_bits = map_field_bits(clusters.len());
+    let mut coded_bits = TABLE_COUNT_BITS as u64 + BYTE_ALPHABET_SIZE as u64 * map_bits as u64;
+    coded_bits += tables.iter().map(PackedTable::bit_len).sum::<u64>();
+    for (context, histogram) in contexts.iter().enumerate() {
+        let byte_map = &byte_maps[context_map[context] as usize];
+        coded_bits += byte_map
+            .encoded_bits(histogram)
+            .ok_or_else(|| Error::CorruptTable("context byte has no code".into()))?;
+    }
+
+    if bits_to_bytes(coded_bits) >= block.len() as u64 {
+        writer.write_bytes(&[BlockType::Stored as u8])?;
+        return writer.write_bytes(block);
+    }
+
+    writer.write_bytes(&[BlockType::Coded as u8])?;
+    writer.write_bits(clusters.len() as u32 - 1, TABLE_COUNT_BITS)?;
+    for &cluster in &context_map {
+        writer.write_bits(cluster as u32, map_bits)?;
+    }
+    for table in &tables {
+        table.write_to(writer)?;
+    }
+
+    let mut context = previous;
+    for &byte in block {
+        let byte_map = &byte_maps[context_map[context as usize] as usize];
+        let code = byte_map
+            .get(&(byte as Symbol))
+            .ok_or_else(|| Error::CorruptTable("context byte has no code".into()))?;
+        writer.write_bits(code.bit_pattern, code.len)?;
+        context = byte;
+    }
+
+    writer.flush()
+}
+
+/// Bits needed to tell `tables` tables apart.
+fn map_field_bits(tables: usize) -> u8 {
+    tables.next_power_of_two().trailing_zeros() as u8
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::ContextHuffman)?;
+
+    let mut block = vec![];
+    let mut previous = 0;
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        let tables = match framing::read_block_type(reader)? {
+            BlockType::Stored => None,
+            BlockType::Coded => Some(read_tables(reader)?),
+        };
+
+        let mut remaining = length;
+        while remaining > 0 {
+            let chunk_len = remaining.min(DEFAULT_BLOCK_SIZE as u64) as usize;
+
+            match &tables {
+                None => {
+                    block.resize(chunk_len, 0);
+                    reader.read_bytes(&mut block)?;
+                }
+                Some((context_map, tables)) => {
+                    block.clear();
+                    for _ in 0..chunk_len {
+                        let table = &tables[context_map[previous as usize] as usize];
+                        previous = table.decode(reader)? as u8;
+                        block.push(previous);
+                    }
+                }
+            }
+
+            previous = block[chunk_len - 1];
+            output.write_all(&block)?;
+
+            remaining -= chunk_len as u64;
+        }
+
+        reader.align_to_byte();
+
+        Ok(())
+    })
+}
+
+/// Reads the context map and code tables of a coded block.
+fn read_tables<R: Read>(reader: &mut BitReader<R>) -> io::Result<(Vec<u8>, Vec<DecodeTable>)> {
+    let count = reader.read_bits(TABLE_COUNT_BITS)? as usize + 1;
+    let map_bits = map_field_bits(count);
+
+    let mut context_map = Vec::with_capacity(BYTE_ALPHABET_SIZE);
+    for _ in 0..BYTE_ALPHABET_SIZE {
+        let table = reader.read_bits(map_bits)?;
+        if table as usize >= count {
+            return Err(Error::CorruptTable("context map refers to a missing table".into()).into());
+        }
+
+        context_map.push(table as u8);
+    }
+
+    let mut tables = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut code_lengths = packed_table::read_code_lengths(reader, BYTE_ALPHABET_SIZE)?;
+        tables.push(DecodeTable::new(&mut code_lengths)?);
+    }
+
+    Ok((context_map, tables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::HuffmanOptions;
+    use crate::checksum::ChecksumKind;
+    use crate::container::{CodecId, Header};
+    use crate::framing::BlockType;
+    use crate::test_support::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_records_context_huffman_codec() {
+        let compressed = compress_bytes(&Options::default(), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.codec, CodecId::ContextHuffman);
+        assert_eq!(header.original_length, Some(3));
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let options = Options::default();
+
+        assert_round_trip(&options, b"");
+        assert_round_trip(&options, b"a");
+        assert_round_trip(&options, &[0; 10_000]);
+        assert_round_trip(&options, &(0..=255).collect::<Vec<u8>>());
+        assert_round_trip(&options, &text(100_000, 0));
+    }
+
+    #[test]
+    fn beats_order_0_huffman_on_text() {
+        let input = text(100_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+        let order_0 = compress_bytes(&HuffmanOptions::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+        assert!(compressed.len() < order_0.len() * 3 / 4);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn context_carries_over_between_blocks() {
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            block_size: 1000,
+            ..Options::default()
+        };
+
+        assert_round_trip(&options, &text(20_000, 0));
+    }
+
+    #[test]
+    fn single_table_round_trips() {
+        let options = Options {
+            max_tables: 1,
+            ..Options::default()
+        };
+
+        assert_round_trip(&options, &text(20_000, 0));
+    }
+
+    #[test]
+    fn random_input_is_stored() {
+        let input = random_bytes(10_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Stored);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        assert_rejects_corrupted_data(&options, &text(10_000, 0));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        for options in [
+            Options {
+                max_tables: 0,
+                ..Options::default()
+            },
+            Options {
+                max_tables: 257,
+                ..Options::default()
+            },
+            Options {
+                block_size: 0,
+                ..Options::default()
+            },
+        ] {
+            assert_rejects_options(&options);
+        }
+    }
+}
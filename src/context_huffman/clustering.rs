@@ -0,0 +1,147 @@
+use crate::huffman::frequency::Frequencies;
+
+/// Rough size in bits of a packed code table, per table and per symbol with
+/// a code, used to weigh a new table against a worse shared one.
+const TABLE_BITS: f64 = 40.0;
+const TABLE_BITS_PER_SYMBOL: f64 = 4.0;
+
+/// Contexts grouped so that each group shares one code table.
+#[derive(Debug)]
+pub struct Clustering {
+    /// The cluster of each context. Contexts that never occur are put in
+    /// cluster 0.
+    pub context_map: Vec<u8>,
+    /// The combined histogram of each cluster's contexts.
+    pub clusters: Vec<Frequencies>,
+}
+
+/// Groups the histograms of `contexts` into at most `max_clusters` clusters
+/// (at most 256), aiming for the smallest total of coded data and tables.
+///
+/// Contexts are taken from most to least frequent, each joining whichever
+/// cluster it makes the least more expensive or, if that would cost more,
+/// starting a new one while there is room.
+pub fn cluster(contexts: &[Frequencies], max_clusters: usize) -> Clustering {
+    let mut order: Vec<usize> = (0..contexts.len())
+        .filter(|&context| total(&contexts[context]) > 0)
+        .collect();
+    order.sort_by_key(|&context| std::cmp::Reverse(total(&contexts[context])));
+
+    let mut context_map = vec![0; contexts.len()];
+    let mut clusters: Vec<Frequencies> = vec![];
+    let mut costs: Vec<f64> = vec![];
+
+    for context in order {
+        let histogram = &contexts[context];
+
+        let mut best = None;
+        if clusters.len() < max_clusters {
+            best = Some((clusters.len(), cost(histogram.iter().copied())));
+        }
+
+        for (index, cluster) in clusters.iter().enumerate() {
+            let merged = cluster.iter().zip(histogram.iter()).map(|(a, b)| a + b);
+            let added = cost(merged) - costs[index];
+
+            if best.is_none_or(|(_, best_cost)| added < best_cost) {
+                best = Some((index, added));
+            }
+        }
+
+        // With no room for a cluster there is already at least one.
+        let Some((index, added)) = best else {
+            continue;
+        };
+
+        if index == clusters.len() {
+            clusters.push(Frequencies::with_alphabet_size(histogram.len()));
+            costs.push(0.0);
+        }
+
+        for (count, &more) in clusters[index].iter_mut().zip(histogram.iter()) {
+            *count += more;
+        }
+        costs[index] += added;
+        context_map[context] = index as u8;
+    }
+
+    Clustering {
+        context_map,
+        clusters,
+    }
+}
+
+fn total(histogram: &Frequencies) -> u64 {
+    histogram.iter().sum()
+}
+
+/// Estimated bits to code a histogram with its own table: its entropy plus
+/// the table.
+fn cost(counts: impl Iterator<Item = u64> + Clone) -> f64 {
+    let total: u64 = counts.clone().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let mut bits = TABLE_BITS;
+    for count in counts.filter(|&count| count > 0) {
+        bits += count as f64 * (total as f64 / count as f64).log2() + TABLE_BITS_PER_SYMBOL;
+    }
+
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(counts: &[(u8, u64)]) -> Frequencies {
+        let mut histogram = Frequencies::new();
+        for &(byte, count) in counts {
+            histogram[byte as usize] = count;
+        }
+
+        histogram
+    }
+
+    #[test]
+    fn similar_contexts_share_a_cluster() {
+        let contexts = vec![
+            histogram(&[(b'a', 1000), (b'b', 10)]),
+            histogram(&[(b'x', 1000), (b'y', 10)]),
+            histogram(&[(b'a', 900), (b'b', 20)]),
+            histogram(&[(b'x', 800), (b'y', 5)]),
+            Frequencies::new(),
+        ];
+
+        let clustering = cluster(&contexts, 16);
+
+        assert_eq!(clustering.clusters.len(), 2);
+        assert_eq!(clustering.context_map[0], clustering.context_map[2]);
+        assert_eq!(clustering.context_map[1], clustering.context_map[3]);
+        assert_ne!(clustering.context_map[0], clustering.context_map[1]);
+    }
+
+    #[test]
+    fn cluster_count_is_limited() {
+        let contexts: Vec<Frequencies> = (0..=255).map(|byte| histogram(&[(byte, 1000)])).collect();
+
+        let clustering = cluster(&contexts, 4);
+
+        assert_eq!(clustering.clusters.len(), 4);
+        let total: u64 = clustering.clusters.iter().map(super::total).sum();
+        assert_eq!(total, 256_000);
+    }
+
+    #[test]
+    fn rare_context_joins_an_existing_cluster() {
+        let contexts = vec![
+            histogram(&[(b'a', 1000), (b'b', 1000)]),
+            histogram(&[(b'b', 1)]),
+        ];
+
+        let clustering = cluster(&contexts, 16);
+
+        assert_eq!(clustering.clusters.len(), 1);
+    }
+}
@@ -0,0 +1,380 @@
+//! Raw DEFLATE streams (RFC 1951), readable by zlib and every other
+//! implementation of the format.
+
+pub(crate) mod bit_reader;
+mod bit_writer;
+mod encoder;
+mod inflater;
+
+use crate::deflate::bit_reader::BitReader;
+use crate::deflate::bit_writer::BitWriter;
+use crate::deflate::encoder::BlockEncoder;
+use crate::error::Error;
+use crate::huffman::packed_table::RepeatSymbols;
+use crate::huffman::{self, Symbol};
+use crate::lz77::DEFAULT_MAX_CHAIN_LENGTH;
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+
+pub(crate) use crate::deflate::inflater::inflate;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Literal bytes, the end-of-block code and the length codes. Symbols 286
+/// and 287 take part in the fixed code but never occur in valid data.
+const LITERAL_LENGTH_CODES: usize = 286;
+const FIXED_LITERAL_LENGTH_CODES: usize = 288;
+const DISTANCE_CODES: usize = 30;
+const FIXED_DISTANCE_CODES: usize = 32;
+
+const END_OF_BLOCK: Symbol = 256;
+
+const MAX_CODE_LENGTH: u8 = 15;
+/// Longest code in the code-length code, the most its 3-bit lengths can hold.
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+
+/// Repeats the previous length 3-6 times (2 extra bits).
+const REPEAT_PREVIOUS: u8 = 16;
+/// A run of 3-10 zero lengths (3 extra bits).
+const REPEAT_ZERO_SHORT: u8 = 17;
+/// A run of 11-138 zero lengths (7 extra bits).
+const REPEAT_ZERO_LONG: u8 = 18;
+
+const REPEAT_SYMBOLS: RepeatSymbols = RepeatSymbols {
+    previous: REPEAT_PREVIOUS,
+    zero_short: REPEAT_ZERO_SHORT,
+    zero_long: REPEAT_ZERO_LONG,
+};
+
+const CODE_LENGTH_CODES: usize = REPEAT_ZERO_LONG as usize + 1;
+
+/// Order in which the lengths of the code-length code are written.
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Longest block a stored block can hold.
+const MAX_STORED_LEN: usize = u16::MAX as usize;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Upper bound on the input bytes coded as one block.
+    pub block_size: usize,
+    /// Most earlier positions compared when looking for a match.
+    pub max_chain_length: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_chain_length: DEFAULT_MAX_CHAIN_LENGTH,
+        }
+    }
+}
+
+/// The BTYPE field of a block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Stored = 0,
+    /// Coded with the fixed code of RFC 1951, section 3.2.6.
+    Fixed = 1,
+    /// Coded with code tables sent at the start of the block.
+    Dynamic = 2,
+}
+
+impl TryFrom<u32> for BlockType {
+    type Error = io::Error;
+
+    fn try_from(value: u32) -> io::Result<Self> {
+        match value {
+            0 => Ok(BlockType::Stored),
+            1 => Ok(BlockType::Fixed),
+            2 => Ok(BlockType::Dynamic),
+            _ => Err(Error::CorruptData(format!("reserved block type {value}")).into()),
+        }
+    }
+}
+
+/// Code lengths of the fixed literal/length code.
+fn fixed_literal_length_lengths() -> Vec<u8> {
+    (0..FIXED_LITERAL_LENGTH_CODES)
+        .map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        })
+        .collect()
+}
+
+/// Code lengths of the fixed distance code.
+fn fixed_distance_lengths() -> Vec<u8> {
+    vec![5; FIXED_DISTANCE_CODES]
+}
+
+/// The canonical code of each symbol (RFC 1951, section 3.2.2), most
+/// significant bit first, or zero for symbols without a code.
+fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let mut counts = [0u32; MAX_CODE_LENGTH as usize + 1];
+    for &len in lengths {
+        counts[len as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next_code = [0u32; MAX_CODE_LENGTH as usize + 1];
+    let mut code = 0;
+    for len in 1..next_code.len() {
+        code = (code + counts[len - 1]) << 1;
+        next_code[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+
+            next_code[len as usize] += 1;
+            next_code[len as usize] - 1
+        })
+        .collect()
+}
+
+/// Compresses `input` into a raw DEFLATE stream, without zlib or gzip
+/// framing.
+///
+/// Matches are found like `lz77::compress` does, and each block is written
+/// stored, with the fixed code or with its own code tables, whichever is
+/// smallest.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    huffman::check_options(options.block_size, MAX_CODE_LENGTH, LITERAL_LENGTH_CODES)?;
+
+    let mut reader = BufReader::new(input);
+    let mut writer = BitWriter::new(BufWriter::new(output));
+    let mut block = Vec::with_capacity(options.block_size);
+
+    let mut encoder = BlockEncoder::new(&mut writer, options.max_chain_length.max(1));
+
+    loop {
+        huffman::read_block(&mut reader, &mut block, options.block_size)?;
+        let is_final = reader.fill_buf()?.is_empty();
+
+        encoder.encode(&block, is_final)?;
+
+        if is_final {
+            break;
+        }
+    }
+
+    writer.flush()?;
+    Ok(writer.writer.flush()?)
+}
+
+/// Decompresses a raw DEFLATE stream, rejecting any data after its final
+/// block.
+pub fn decompress<R, W>(input: R, output: W) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = BitReader::new(BufReader::new(input));
+    let mut writer = BufWriter::new(output);
+
+    inflate(&mut reader, &mut writer)?;
+
+    if !reader.is_at_end()? {
+        return Err(Error::CorruptData(
+            "unexpected data after end of stream".into(),
+        ));
+    }
+
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    // Streams written by zlib 1.3 (raw, window bits -15).
+
+    /// "a" as a single fixed block.
+    const FIXED_A: [u8; 3] = [0x4b, 0x04, 0x00];
+    /// The empty input: a final fixed block holding only the end code.
+    const FIXED_EMPTY: [u8; 2] = [0x03, 0x00];
+    /// "hello hello hello hello" at level 0.
+    const STORED_HELLO: &str = "011700e8ff68656c6c6f2068656c6c6f2068656c6c6f2068656c6c6f";
+    /// "hello hello hello hello" at level 6, one match.
+    const FIXED_HELLO: &str = "cb48cdc9c957c8402701";
+    /// `PANGRAMS` at level 9, a dynamic block.
+    const DYNAMIC_PANGRAMS: &str = "2d8dcb15c2300c045b591a481d1c39d0804d6447e058c4dfd8d5478fc779\
+        66679f1be1a8fcfac026e9114e4ebcebfecd904609457130736015bfe061d4db07ac4a9dcb06c78d144d8a\
+        087c5449baf579c15d3a1a9d1c7d18fffc6a5cc1249b4cfe1ddc2e";
+    const PANGRAMS: &[u8] = b"The quick brown fox jumps over the lazy dog. \
+        Pack my box with five dozen liquor jugs. How vexingly quick daft zebras jump!";
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn deflate_bytes(input: &[u8], options: &Options) -> Vec<u8> {
+        compress_with(input, |input, output| compress(input, output, options))
+    }
+
+    fn inflate_bytes(input: &[u8]) -> io::Result<Vec<u8>> {
+        decompress_with(input, |input, output| decompress(input, output))
+    }
+
+    fn first_block_type(compressed: &[u8]) -> BlockType {
+        BlockType::try_from((compressed[0] as u32 >> 1) & 0b11).unwrap()
+    }
+
+    #[test]
+    fn inflates_reference_streams() {
+        assert_eq!(inflate_bytes(&FIXED_A).unwrap(), b"a");
+        assert_eq!(inflate_bytes(&FIXED_EMPTY).unwrap(), b"");
+        assert_eq!(
+            inflate_bytes(&from_hex(STORED_HELLO)).unwrap(),
+            b"hello hello hello hello"
+        );
+        assert_eq!(
+            inflate_bytes(&from_hex(FIXED_HELLO)).unwrap(),
+            b"hello hello hello hello"
+        );
+        assert_eq!(
+            inflate_bytes(&from_hex(DYNAMIC_PANGRAMS)).unwrap(),
+            PANGRAMS
+        );
+    }
+
+    #[test]
+    fn inflates_stored_block_followed_by_fixed_block() {
+        // A non-final stored block holding "ab", then `FIXED_A`.
+        let mut stream = vec![0x00, 0x02, 0x00, 0xfd, 0xff, b'a', b'b'];
+        stream.extend_from_slice(&FIXED_A);
+
+        assert_eq!(inflate_bytes(&stream).unwrap(), b"aba");
+    }
+
+    #[test]
+    fn small_inputs_match_zlib() {
+        assert_eq!(deflate_bytes(b"a", &Options::default()), FIXED_A);
+        assert_eq!(deflate_bytes(b"", &Options::default()), FIXED_EMPTY);
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let inputs = [
+            b"abcabcabcabc".to_vec(),
+            vec![0; 100_000],
+            (0..=255).collect(),
+            PANGRAMS.to_vec(),
+            log_lines(5000),
+        ];
+
+        for input in inputs {
+            let compressed = deflate_bytes(&input, &Options::default());
+            assert_eq!(inflate_bytes(&compressed).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn picks_block_type_by_size() {
+        assert_eq!(
+            first_block_type(&deflate_bytes(b"abc", &Options::default())),
+            BlockType::Fixed
+        );
+        assert_eq!(
+            first_block_type(&deflate_bytes(&log_lines(200), &Options::default())),
+            BlockType::Dynamic
+        );
+
+        let random = random_bytes(10_000, 0);
+        let compressed = deflate_bytes(&random, &Options::default());
+
+        assert_eq!(first_block_type(&compressed), BlockType::Stored);
+        assert_eq!(compressed.len(), random.len() + 5);
+        assert_eq!(inflate_bytes(&compressed).unwrap(), random);
+    }
+
+    #[test]
+    fn long_stored_blocks_are_split() {
+        let random = random_bytes(3 * MAX_STORED_LEN, 0);
+        let options = Options {
+            block_size: random.len(),
+            ..Options::default()
+        };
+
+        let compressed = deflate_bytes(&random, &options);
+
+        assert_eq!(compressed.len(), random.len() + 3 * 5);
+        assert_eq!(inflate_bytes(&compressed).unwrap(), random);
+    }
+
+    #[test]
+    fn matches_span_blocks() {
+        let input = log_lines(500);
+        let options = Options {
+            block_size: 1000,
+            ..Options::default()
+        };
+
+        let compressed = deflate_bytes(&input, &options);
+
+        assert!(compressed.len() * 4 < input.len());
+        assert_eq!(inflate_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_reserved_block_type() {
+        let error = inflate_bytes(&[0b111]).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_stored_length_mismatch() {
+        let stream = [0x01, 0x02, 0x00, 0xfc, 0xff, b'a', b'b'];
+
+        assert!(inflate_bytes(&stream).is_err());
+    }
+
+    #[test]
+    fn rejects_match_before_start_of_stream() {
+        let mut writer = BitWriter::new(Vec::new());
+        // A final fixed block starting with length 3 (code 257, 0000001)
+        // at distance 1 (code 0, 00000).
+        writer.write_bits(1, 1).unwrap();
+        writer.write_bits(BlockType::Fixed as u32, 2).unwrap();
+        writer.write_bits(0b1000000, 7).unwrap();
+        writer.write_bits(0, 5).unwrap();
+        writer.write_bits(0, 7).unwrap();
+        writer.flush().unwrap();
+
+        let error = inflate_bytes(&writer.writer).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_and_trailing_data() {
+        let compressed = deflate_bytes(&log_lines(100), &Options::default());
+
+        let error = inflate_bytes(&compressed[..compressed.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut extended = compressed.clone();
+        extended.push(0);
+        assert!(inflate_bytes(&extended).is_err());
+    }
+}
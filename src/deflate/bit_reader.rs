@@ -0,0 +1,189 @@
+use crate::error::Error;
+use crate::huffman::decode_table::CodeReader;
+use std::io::{self, BufRead, Read};
+
+const U8_BITS: u8 = u8::BITS as u8;
+const MAX_PEEK_BITS: u8 = u32::BITS as u8;
+
+/// Reads bit fields least significant bit first, the counterpart of
+/// `deflate::bit_writer::BitWriter`.
+///
+/// Bytes are taken from `reader` as they are needed, except that looking
+/// ahead for a Huffman code may take a few past the end of a DEFLATE stream.
+/// Those stay buffered and are the first bytes read afterwards, so whatever
+/// follows the stream should be read through the `BitReader`.
+pub struct BitReader<T: Read> {
+    pub reader: T,
+    bit_buffer: u64,
+    bits_available: u8,
+}
+
+impl<T: Read> BitReader<T> {
+    pub fn new(reader: T) -> Self {
+        BitReader {
+            reader,
+
+            bit_buffer: 0,
+            bits_available: 0,
+        }
+    }
+
+    pub fn read_bits(&mut self, length: u8) -> io::Result<u32> {
+        while self.bits_available < length {
+            let mut byte = [0];
+            self.reader.read_exact(&mut byte).map_err(truncated)?;
+            self.push_byte(byte[0]);
+        }
+
+        let bits = self.bit_buffer & ((1 << length) - 1);
+        self.bit_buffer >>= length;
+        self.bits_available -= length;
+
+        Ok(bits as u32)
+    }
+
+    /// Skips the bits left in the current byte.
+    pub fn align_to_byte(&mut self) {
+        let skipped = self.bits_available % U8_BITS;
+        self.bit_buffer >>= skipped;
+        self.bits_available -= skipped;
+    }
+
+    /// Skips to the next byte boundary and fills `bytes`.
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        self.read_exact(bytes).map_err(truncated)
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.bit_buffer |= (byte as u64) << self.bits_available;
+        self.bits_available += U8_BITS;
+    }
+}
+
+/// Reads whole bytes from the next byte boundary on, those already buffered
+/// first.
+impl<T: Read> Read for BitReader<T> {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        self.align_to_byte();
+
+        if self.bits_available == 0 {
+            return self.reader.read(bytes);
+        }
+
+        let mut filled = 0;
+        while filled < bytes.len() && self.bits_available > 0 {
+            bytes[filled] = self.read_bits(U8_BITS)? as u8;
+            filled += 1;
+        }
+
+        Ok(filled)
+    }
+}
+
+impl<T: BufRead> BitReader<T> {
+    /// Whether nothing is left to read, once aligned to a byte.
+    pub fn is_at_end(&mut self) -> io::Result<bool> {
+        Ok(self.bits_available == 0 && self.reader.fill_buf()?.is_empty())
+    }
+}
+
+/// DEFLATE sends Huffman codes most significant bit first, so the bits
+/// peeked for one are reversed.
+impl<T: Read> CodeReader for BitReader<T> {
+    fn peek_bits(&mut self, length: u8) -> io::Result<u32> {
+        if length > MAX_PEEK_BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot peek more than 32 bits",
+            ));
+        }
+
+        if length == 0 {
+            return Ok(0);
+        }
+
+        while self.bits_available < length {
+            let mut byte = [0];
+
+            match self.reader.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => self.push_byte(byte[0]),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok((self.bit_buffer as u32).reverse_bits() >> (u32::BITS - length as u32))
+    }
+
+    fn consume(&mut self, length: u8) -> io::Result<()> {
+        self.read_bits(length).map(|_| ())
+    }
+}
+
+fn truncated(error: io::Error) -> io::Error {
+    if error.kind() != io::ErrorKind::UnexpectedEof {
+        return error;
+    }
+
+    Error::Truncated.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deflate::bit_writer::BitWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_back_bit_writer_output() {
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        writer.write_bits(1, 1).unwrap();
+        writer.write_bits(0x1234, 16).unwrap();
+        writer.write_bits(0b10, 2).unwrap();
+        writer.write_bytes(&[7, 8]).unwrap();
+
+        let mut reader = BitReader::new(Cursor::new(writer.writer.into_inner()));
+        assert_eq!(reader.read_bits(1).unwrap(), 1);
+        assert_eq!(reader.read_bits(16).unwrap(), 0x1234);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b10);
+
+        let mut bytes = [0; 2];
+        reader.read_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes, [7, 8]);
+    }
+
+    #[test]
+    fn stops_reading_at_the_last_byte_used() {
+        let mut input = Cursor::new(vec![0xFF, 0xAA, 0x55]);
+
+        let mut reader = BitReader::new(&mut input);
+        reader.read_bits(3).unwrap();
+        reader.align_to_byte();
+
+        assert_eq!(input.position(), 1);
+    }
+
+    #[test]
+    fn peeked_bytes_are_read_after_alignment() {
+        let mut reader = BitReader::new(Cursor::new(vec![0b1000_0001, 0xAA, 0x55]));
+
+        assert_eq!(reader.peek_bits(12).unwrap(), 0b1000_0001_0101);
+        reader.consume(3).unwrap();
+        reader.align_to_byte();
+
+        let mut bytes = [0; 2];
+        reader.read_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes, [0xAA, 0x55]);
+        assert!(reader.is_at_end().unwrap());
+    }
+
+    #[test]
+    fn read_past_end_is_truncation() {
+        let mut reader = BitReader::new(Cursor::new(vec![0xFF]));
+        reader.read_bits(6).unwrap();
+
+        let error = reader.read_bits(3).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
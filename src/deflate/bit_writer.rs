@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+
+const U8_BITS: u8 = u8::BITS as u8;
+
+/// Writes bit fields least significant bit first, the order DEFLATE packs
+/// them into bytes (RFC 1951, section 3.1.1).
+pub struct BitWriter<T: Write> {
+    pub writer: T,
+    bit_buffer: u64,
+    bits_filled: u8,
+}
+
+impl<T: Write> BitWriter<T> {
+    pub fn new(writer: T) -> Self {
+        BitWriter {
+            writer,
+
+            bit_buffer: 0,
+            bits_filled: 0,
+        }
+    }
+
+    /// Writes the low `length` bits of `bits`, lowest first.
+    pub fn write_bits(&mut self, bits: u32, length: u8) -> io::Result<()> {
+        let mask = (1u64 << length) - 1;
+        self.bit_buffer |= (bits as u64 & mask) << self.bits_filled;
+        self.bits_filled += length;
+
+        while self.bits_filled >= U8_BITS {
+            self.writer.write_all(&[self.bit_buffer as u8])?;
+            self.bit_buffer >>= U8_BITS;
+            self.bits_filled -= U8_BITS;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.flush()?;
+
+        self.writer.write_all(bytes)
+    }
+
+    /// Pads a partial byte with zeros and writes it.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.bits_filled > 0 {
+            self.writer.write_all(&[self.bit_buffer as u8])?;
+            self.bit_buffer = 0;
+            self.bits_filled = 0;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn fields_fill_bytes_from_the_low_end() {
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        writer.write_bits(1, 1).unwrap();
+        writer.write_bits(0b01, 2).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(writer.writer.into_inner(), vec![0b011]);
+    }
+
+    #[test]
+    fn fields_span_bytes() {
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        writer.write_bits(0b1111, 4).unwrap();
+        writer.write_bits(0xABC, 12).unwrap();
+
+        assert_eq!(writer.writer.into_inner(), vec![0xCF, 0xAB]);
+    }
+
+    #[test]
+    fn write_bytes_pads_to_byte_boundary() {
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bytes(&[0xFF]).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(writer.writer.into_inner(), vec![0b101, 0xFF]);
+    }
+}
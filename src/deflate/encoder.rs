@@ -0,0 +1,362 @@
+use crate::deflate::bit_writer::BitWriter;
+use crate::deflate::{
+    BlockType, CODE_LENGTH_CODES, CODE_LENGTH_ORDER, DISTANCE_CODES, END_OF_BLOCK,
+    LITERAL_LENGTH_CODES, MAX_CODE_LENGTH, MAX_CODE_LENGTH_CODE_LENGTH, MAX_STORED_LEN,
+    REPEAT_SYMBOLS, canonical_codes, fixed_distance_lengths, fixed_literal_length_lengths,
+};
+use crate::huffman::frequency::Frequencies;
+use crate::huffman::packed_table::run_length_code;
+use crate::lz77::codes::{self, Code};
+use crate::lz77::match_finder::{MatchFinder, Token};
+use std::io::{self, Write};
+
+/// Bits of a stored block besides its data, assuming the header starts a
+/// byte: the 3 header bits padded to a byte, then LEN and NLEN.
+const STORED_OVERHEAD_BITS: u64 = 8 + 32;
+
+/// Writes each block in whichever of the three block types is smallest.
+pub struct BlockEncoder<'a, W>
+where
+    W: Write,
+{
+    writer: &'a mut BitWriter<W>,
+    match_finder: MatchFinder,
+}
+
+impl<'a, W> BlockEncoder<'a, W>
+where
+    W: Write,
+{
+    pub fn new(writer: &'a mut BitWriter<W>, max_chain_length: usize) -> Self {
+        BlockEncoder {
+            writer,
+            match_finder: MatchFinder::new(max_chain_length),
+        }
+    }
+
+    pub fn encode(&mut self, block: &[u8], is_final: bool) -> io::Result<()> {
+        let mut codes: Vec<(Code, Option<Code>)> = self
+            .match_finder
+            .tokenize(block)
+            .into_iter()
+            .map(to_codes)
+            .collect();
+        codes.push((literal_code(END_OF_BLOCK), None));
+
+        let mut literal_lengths = Frequencies::with_alphabet_size(LITERAL_LENGTH_CODES);
+        let mut distances = Frequencies::with_alphabet_size(DISTANCE_CODES);
+        let mut extra_bits = 0;
+
+        for (literal_length, distance) in &codes {
+            literal_lengths[literal_length.symbol as usize] += 1;
+            extra_bits += literal_length.extra_bits as u64;
+
+            if let Some(distance) = distance {
+                distances[distance.symbol as usize] += 1;
+                extra_bits += distance.extra_bits as u64;
+            }
+        }
+
+        let fixed_literal_lengths = fixed_literal_length_lengths();
+        let fixed_distance_lengths = fixed_distance_lengths();
+        let fixed_bits = coded_bits(&literal_lengths, &fixed_literal_lengths)
+            + coded_bits(&distances, &fixed_distance_lengths);
+
+        let dynamic = DynamicHeader::new(&literal_lengths, &distances);
+        let dynamic_bits = dynamic.bit_len()
+            + coded_bits(&literal_lengths, &dynamic.literal_lengths)
+            + coded_bits(&distances, &dynamic.distance_lengths);
+
+        let stored_chunks = block.len().div_ceil(MAX_STORED_LEN).max(1);
+        let stored_bits = stored_chunks as u64 * STORED_OVERHEAD_BITS + 8 * block.len() as u64;
+
+        let block_type = [
+            (BlockType::Fixed, fixed_bits + extra_bits),
+            (BlockType::Dynamic, dynamic_bits + extra_bits),
+            (BlockType::Stored, stored_bits),
+        ]
+        .into_iter()
+        .min_by_key(|&(_, bits)| bits)
+        .map_or(BlockType::Stored, |(block_type, _)| block_type);
+
+        match block_type {
+            BlockType::Stored => self.write_stored(block, is_final),
+            BlockType::Fixed => {
+                self.write_header(BlockType::Fixed, is_final)?;
+                self.write_codes(&codes, &fixed_literal_lengths, &fixed_distance_lengths)
+            }
+            BlockType::Dynamic => {
+                self.write_header(BlockType::Dynamic, is_final)?;
+                dynamic.write_to(self.writer)?;
+                self.write_codes(&codes, &dynamic.literal_lengths, &dynamic.distance_lengths)
+            }
+        }
+    }
+
+    fn write_header(&mut self, block_type: BlockType, is_final: bool) -> io::Result<()> {
+        self.writer.write_bits(is_final as u32, 1)?;
+        self.writer.write_bits(block_type as u32, 2)
+    }
+
+    fn write_stored(&mut self, block: &[u8], is_final: bool) -> io::Result<()> {
+        let chunks = block.len().div_ceil(MAX_STORED_LEN).max(1);
+
+        for i in 0..chunks {
+            let start = i * MAX_STORED_LEN;
+            let chunk = &block[start..block.len().min(start + MAX_STORED_LEN)];
+
+            self.write_header(BlockType::Stored, is_final && i == chunks - 1)?;
+
+            let len = chunk.len() as u16;
+            self.writer.flush()?;
+            self.writer.write_bits(len.into(), 16)?;
+            self.writer.write_bits((!len).into(), 16)?;
+            self.writer.write_bytes(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_codes(
+        &mut self,
+        codes: &[(Code, Option<Code>)],
+        literal_lengths: &[u8],
+        distance_lengths: &[u8],
+    ) -> io::Result<()> {
+        let literal_codes = reversed_codes(literal_lengths);
+        let distance_codes = reversed_codes(distance_lengths);
+
+        for (literal_length, distance) in codes {
+            write_code(self.writer, &literal_codes, literal_lengths, literal_length)?;
+
+            if let Some(distance) = distance {
+                write_code(self.writer, &distance_codes, distance_lengths, distance)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The code tables at the start of a dynamic block: the literal/length and
+/// distance code lengths, run-length coded and then Huffman coded with the
+/// code-length code (RFC 1951, section 3.2.7).
+struct DynamicHeader {
+    literal_lengths: Vec<u8>,
+    distance_lengths: Vec<u8>,
+    code_length_lengths: Vec<u8>,
+    /// How many of `CODE_LENGTH_ORDER` are sent (HCLEN + 4).
+    code_length_count: usize,
+    runs: Vec<(u8, u32, u8)>,
+}
+
+impl DynamicHeader {
+    fn new(literal_lengths: &Frequencies, distances: &Frequencies) -> Self {
+        let literal_lengths = code_lengths(literal_lengths, MAX_CODE_LENGTH);
+        let distance_lengths = code_lengths(distances, MAX_CODE_LENGTH);
+
+        let literal_count = trimmed_len(&literal_lengths, 257);
+        let distance_count = trimmed_len(&distance_lengths, 1);
+
+        let mut lengths = literal_lengths[..literal_count].to_vec();
+        lengths.extend_from_slice(&distance_lengths[..distance_count]);
+        let runs = run_length_code(&lengths, &REPEAT_SYMBOLS);
+
+        let mut frequencies = Frequencies::with_alphabet_size(CODE_LENGTH_CODES);
+        for &(symbol, _, _) in &runs {
+            frequencies[symbol as usize] += 1;
+        }
+
+        // A lone code-length code would be incomplete, which decoders reject.
+        if frequencies.iter().filter(|&&count| count > 0).count() == 1
+            && let Some(unused) = frequencies.iter().position(|&count| count == 0)
+        {
+            frequencies[unused] = 1;
+        }
+
+        let code_length_lengths = code_lengths(&frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+        let code_length_count = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] > 0)
+            .map_or(0, |position| position + 1)
+            .max(4);
+
+        DynamicHeader {
+            literal_lengths: literal_lengths[..literal_count].to_vec(),
+            distance_lengths: distance_lengths[..distance_count].to_vec(),
+            code_length_lengths,
+            code_length_count,
+            runs,
+        }
+    }
+
+    /// Size of the header in bits.
+    fn bit_len(&self) -> u64 {
+        let runs: u64 = self
+            .runs
+            .iter()
+            .map(|&(symbol, _, extra_bits)| {
+                (self.code_length_lengths[symbol as usize] + extra_bits) as u64
+            })
+            .sum();
+
+        5 + 5 + 4 + 3 * self.code_length_count as u64 + runs
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut BitWriter<W>) -> io::Result<()> {
+        writer.write_bits(self.literal_lengths.len() as u32 - 257, 5)?;
+        writer.write_bits(self.distance_lengths.len() as u32 - 1, 5)?;
+        writer.write_bits(self.code_length_count as u32 - 4, 4)?;
+
+        for &symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            writer.write_bits(self.code_length_lengths[symbol].into(), 3)?;
+        }
+
+        let codes = reversed_codes(&self.code_length_lengths);
+
+        for &(symbol, extra, extra_bits) in &self.runs {
+            writer.write_bits(
+                codes[symbol as usize],
+                self.code_length_lengths[symbol as usize],
+            )?;
+            writer.write_bits(extra, extra_bits)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn literal_code(symbol: u16) -> Code {
+    Code {
+        symbol,
+        extra: 0,
+        extra_bits: 0,
+    }
+}
+
+/// The literal/length code of a token and, for a match, its distance code.
+fn to_codes(token: Token) -> (Code, Option<Code>) {
+    match token {
+        Token::Literal(byte) => (literal_code(byte.into()), None),
+        Token::Match { length, distance } => {
+            // DEFLATE puts the end-of-block code at 256, one below its first
+            // length code.
+            let mut length = codes::length_code(length);
+            length.symbol += 1;
+
+            (length, Some(codes::distance_code(distance)))
+        }
+    }
+}
+
+fn write_code<W: Write>(
+    writer: &mut BitWriter<W>,
+    codes: &[u32],
+    lengths: &[u8],
+    code: &Code,
+) -> io::Result<()> {
+    let symbol = code.symbol as usize;
+    writer.write_bits(codes[symbol], lengths[symbol])?;
+    writer.write_bits(code.extra, code.extra_bits)
+}
+
+/// Bits taken by the symbols counted in `frequencies` under a code with the
+/// given lengths.
+fn coded_bits(frequencies: &Frequencies, lengths: &[u8]) -> u64 {
+    frequencies
+        .iter()
+        .zip(lengths)
+        .map(|(&count, &len)| count * len as u64)
+        .sum()
+}
+
+/// Length-limited Huffman code lengths for every symbol of the alphabet,
+/// zero for symbols that do not occur.
+fn code_lengths(frequencies: &Frequencies, max_code_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0; frequencies.len()];
+
+    if frequencies.iter().all(|&count| count == 0) {
+        return lengths;
+    }
+
+    for (&symbol, code) in frequencies
+        .to_huff_tree()
+        .to_byte_map(max_code_length)
+        .iter()
+    {
+        lengths[symbol as usize] = code.len;
+    }
+
+    lengths
+}
+
+/// Length of `lengths` without trailing zeros, but at least `min`.
+fn trimmed_len(lengths: &[u8], min: usize) -> usize {
+    lengths
+        .iter()
+        .rposition(|&len| len > 0)
+        .map_or(0, |position| position + 1)
+        .max(min)
+}
+
+/// The canonical code of each symbol, bit reversed so that `BitWriter`
+/// sends its most significant bit first.
+fn reversed_codes(lengths: &[u8]) -> Vec<u32> {
+    canonical_codes(lengths)
+        .into_iter()
+        .zip(lengths)
+        .map(|(code, &len)| match len {
+            0 => 0,
+            _ => code.reverse_bits() >> (u32::BITS - len as u32),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_match_rfc_example_reversed() {
+        // RFC 1951, section 3.2.2: codes 010, 011, 100, 101, 110, 00, 1110
+        // and 1111, reversed.
+        let codes = reversed_codes(&[3, 3, 3, 3, 3, 2, 4, 4]);
+
+        assert_eq!(
+            codes,
+            [0b010, 0b110, 0b001, 0b101, 0b011, 0b00, 0b0111, 0b1111]
+        );
+    }
+
+    #[test]
+    fn run_length_code_uses_deflate_symbols() {
+        let mut lengths = [0; 30];
+        lengths[..5].fill(7);
+
+        assert_eq!(
+            run_length_code(&lengths, &REPEAT_SYMBOLS),
+            vec![(7, 0, 0), (16, 1, 2), (18, 14, 7)]
+        );
+    }
+
+    #[test]
+    fn header_trims_unused_codes() {
+        let mut literal_lengths = Frequencies::with_alphabet_size(LITERAL_LENGTH_CODES);
+        literal_lengths[b'a' as usize] = 3;
+        literal_lengths[END_OF_BLOCK as usize] = 1;
+        let distances = Frequencies::with_alphabet_size(DISTANCE_CODES);
+
+        let header = DynamicHeader::new(&literal_lengths, &distances);
+
+        assert_eq!(header.literal_lengths.len(), 257);
+        assert_eq!(header.distance_lengths, [0]);
+        assert!(
+            header
+                .code_length_lengths
+                .iter()
+                .filter(|&&len| len > 0)
+                .count()
+                >= 2
+        );
+    }
+}
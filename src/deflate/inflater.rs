@@ -0,0 +1,337 @@
+use crate::deflate::bit_reader::BitReader;
+use crate::deflate::{
+    BlockType, CODE_LENGTH_CODES, CODE_LENGTH_ORDER, DISTANCE_CODES, END_OF_BLOCK,
+    LITERAL_LENGTH_CODES, MAX_CODE_LENGTH, REPEAT_PREVIOUS, REPEAT_ZERO_LONG, REPEAT_ZERO_SHORT,
+    canonical_codes, fixed_distance_lengths, fixed_literal_length_lengths,
+};
+use crate::error::Error;
+use crate::huffman::Symbol;
+use crate::huffman::decode_table::DecodeTable;
+use crate::lz77::codes::{self, WINDOW_SIZE};
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+
+/// Output is written once this much has been decoded past the window.
+const FLUSH_THRESHOLD: usize = 2 * WINDOW_SIZE;
+
+/// Decodes one DEFLATE stream from `reader` into `writer`, leaving `reader`
+/// aligned to the byte after its final block.
+pub fn inflate<R, W>(reader: &mut BitReader<R>, writer: &mut W) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let mut inflater = Inflater {
+        reader,
+        writer,
+        window: Vec::with_capacity(FLUSH_THRESHOLD + WINDOW_SIZE),
+        written: 0,
+    };
+
+    loop {
+        let is_final = inflater.reader.read_bits(1)? == 1;
+
+        match BlockType::try_from(inflater.reader.read_bits(2)?)? {
+            BlockType::Stored => inflater.inflate_stored()?,
+            BlockType::Fixed => {
+                let (literal_lengths, distances) = fixed_codes();
+                inflater.inflate_codes(literal_lengths, distances)?;
+            }
+            BlockType::Dynamic => {
+                let (literal_lengths, distances) = inflater.read_dynamic_codes()?;
+                inflater.inflate_codes(&literal_lengths, &distances)?;
+            }
+        }
+
+        if is_final {
+            break;
+        }
+    }
+
+    inflater.reader.align_to_byte();
+    inflater.write_output()
+}
+
+/// The fixed literal/length and distance codes, built on first use.
+fn fixed_codes() -> &'static (Code, Code) {
+    static FIXED_CODES: OnceLock<(Code, Code)> = OnceLock::new();
+
+    FIXED_CODES.get_or_init(|| {
+        (
+            Code::new(&fixed_literal_length_lengths()).expect("the fixed code is valid"),
+            Code::new(&fixed_distance_lengths()).expect("the fixed code is valid"),
+        )
+    })
+}
+
+struct Inflater<'a, R, W>
+where
+    R: Read,
+    W: Write,
+{
+    reader: &'a mut BitReader<R>,
+    writer: &'a mut W,
+    /// The last `WINDOW_SIZE` written bytes, then those not yet written.
+    window: Vec<u8>,
+    written: usize,
+}
+
+impl<R, W> Inflater<'_, R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn inflate_stored(&mut self) -> io::Result<()> {
+        let mut header = [0; 4];
+        self.reader.read_bytes(&mut header)?;
+
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let complement = u16::from_le_bytes([header[2], header[3]]);
+        if len != !complement {
+            return Err(invalid_data(
+                "stored block length does not match its complement",
+            ));
+        }
+
+        let start = self.window.len();
+        self.window.resize(start + len as usize, 0);
+        self.reader.read_bytes(&mut self.window[start..])?;
+
+        self.flush_if_full()
+    }
+
+    fn read_dynamic_codes(&mut self) -> io::Result<(Code, Code)> {
+        let literal_count = self.reader.read_bits(5)? as usize + 257;
+        let distance_count = self.reader.read_bits(5)? as usize + 1;
+        let code_length_count = self.reader.read_bits(4)? as usize + 4;
+
+        if literal_count > LITERAL_LENGTH_CODES || distance_count > DISTANCE_CODES {
+            return Err(Error::CorruptTable("too many length or distance codes".into()).into());
+        }
+
+        let mut code_length_lengths = [0; CODE_LENGTH_CODES];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_length_lengths[symbol] = self.reader.read_bits(3)? as u8;
+        }
+
+        let code_length_code = Code::new(&code_length_lengths)?;
+        if !code_length_code.is_complete() {
+            return Err(Error::CorruptTable("code-length code is incomplete".into()).into());
+        }
+
+        let total = literal_count + distance_count;
+        let mut lengths = Vec::with_capacity(total);
+
+        while lengths.len() < total {
+            let symbol = code_length_code.table.decode(self.reader)? as u8;
+
+            let (len, repeat) = match symbol {
+                REPEAT_PREVIOUS => {
+                    let &previous = lengths.last().ok_or_else(|| {
+                        Error::CorruptTable("code length repeat without a previous length".into())
+                    })?;
+                    (previous, 3 + self.reader.read_bits(2)?)
+                }
+                REPEAT_ZERO_SHORT => (0, 3 + self.reader.read_bits(3)?),
+                REPEAT_ZERO_LONG => (0, 11 + self.reader.read_bits(7)?),
+                _ => (symbol, 1),
+            };
+
+            if lengths.len() + repeat as usize > total {
+                return Err(
+                    Error::CorruptTable("code lengths overflow the alphabet".into()).into(),
+                );
+            }
+
+            lengths.extend(std::iter::repeat_n(len, repeat as usize));
+        }
+
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err(
+                Error::CorruptTable("literal/length code has no end-of-block code".into()).into(),
+            );
+        }
+
+        let literal_lengths = Code::new(&lengths[..literal_count])?;
+        let distances = Code::new(&lengths[literal_count..])?;
+
+        // RFC 1951 only allows a single one-bit code to leave code space
+        // unused.
+        if !literal_lengths.is_complete() && !literal_lengths.is_single() {
+            return Err(Error::CorruptTable("literal/length code is incomplete".into()).into());
+        }
+
+        if !distances.is_complete() && !distances.is_single() {
+            return Err(Error::CorruptTable("distance code is incomplete".into()).into());
+        }
+
+        Ok((literal_lengths, distances))
+    }
+
+    fn inflate_codes(&mut self, literal_lengths: &Code, distances: &Code) -> io::Result<()> {
+        loop {
+            let symbol = literal_lengths.table.decode(self.reader)?;
+
+            match symbol {
+                0..END_OF_BLOCK => self.window.push(symbol as u8),
+                END_OF_BLOCK => return Ok(()),
+                257..286 => {
+                    // The end-of-block code sits below the first length code.
+                    let (base, extra_bits) = codes::length_range(symbol - 1);
+                    let length = base + self.reader.read_bits(extra_bits)? as usize;
+
+                    let symbol = distances.table.decode(self.reader)?;
+                    if symbol as usize >= DISTANCE_CODES {
+                        return Err(invalid_data("invalid distance code"));
+                    }
+
+                    let (base, extra_bits) = codes::distance_range(symbol);
+                    let distance = base + self.reader.read_bits(extra_bits)? as usize;
+
+                    if distance > self.window.len() {
+                        return Err(invalid_data(
+                            "match reaches back before the start of the stream",
+                        ));
+                    }
+
+                    // The source may overlap the bytes being written, as in a run.
+                    let from = self.window.len() - distance;
+                    for i in from..from + length {
+                        self.window.push(self.window[i]);
+                    }
+                }
+                _ => return Err(invalid_data("invalid literal/length code")),
+            }
+
+            self.flush_if_full()?;
+        }
+    }
+
+    fn flush_if_full(&mut self) -> io::Result<()> {
+        if self.window.len() < FLUSH_THRESHOLD {
+            return Ok(());
+        }
+
+        self.write_output()?;
+
+        let keep = self.window.len().min(WINDOW_SIZE);
+        self.window.drain(..self.window.len() - keep);
+        self.written = self.window.len();
+
+        Ok(())
+    }
+
+    fn write_output(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.window[self.written..])?;
+        self.written = self.window.len();
+
+        Ok(())
+    }
+}
+
+/// A canonical Huffman code, with what is needed to check that it uses the
+/// code space the way RFC 1951 requires.
+struct Code {
+    /// The number of codes of each length.
+    counts: [u16; MAX_CODE_LENGTH as usize + 1],
+    /// Code space left unused, in units of the longest code.
+    unused: i32,
+    table: DecodeTable,
+}
+
+impl Code {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_CODE_LENGTH as usize + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut unused = 1i32;
+        for &count in &counts[1..] {
+            unused = (unused << 1) - count as i32;
+
+            if unused < 0 {
+                return Err(Error::CorruptTable(
+                    "code lengths oversubscribe the code space".into(),
+                )
+                .into());
+            }
+        }
+
+        let codes: Vec<(Symbol, u32, u8)> = canonical_codes(lengths)
+            .into_iter()
+            .zip(lengths)
+            .enumerate()
+            .filter(|&(_, (_, &len))| len > 0)
+            .map(|(symbol, (code, &len))| (symbol as Symbol, code, len))
+            .collect();
+
+        Ok(Code {
+            counts,
+            unused,
+            table: DecodeTable::from_codes(&codes),
+        })
+    }
+
+    fn is_complete(&self) -> bool {
+        self.unused == 0
+    }
+
+    /// Whether the code is at most one code of one bit.
+    fn is_single(&self) -> bool {
+        let codes: u16 = self.counts.iter().sum();
+        codes == self.counts[1] && codes <= 1
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deflate::bit_writer::BitWriter;
+    use std::io::Cursor;
+
+    fn reader_for(fields: &[(u32, u8)]) -> BitReader<Cursor<Vec<u8>>> {
+        let mut writer = BitWriter::new(Vec::new());
+        for &(bits, len) in fields {
+            writer.write_bits(bits, len).unwrap();
+        }
+        writer.flush().unwrap();
+
+        BitReader::new(Cursor::new(writer.writer))
+    }
+
+    #[test]
+    fn decodes_rfc_example_code() {
+        // Codes 010, 011, 100, 101, 110, 00, 1110 and 1111, sent most
+        // significant bit first.
+        let code = Code::new(&[3, 3, 3, 3, 3, 2, 4, 4]).unwrap();
+        let mut reader = reader_for(&[(0b00, 2), (0b0111, 4), (0b1111, 4), (0b010, 3)]);
+
+        assert!(code.is_complete());
+        assert_eq!(code.table.decode(&mut reader).unwrap(), 5);
+        assert_eq!(code.table.decode(&mut reader).unwrap(), 6);
+        assert_eq!(code.table.decode(&mut reader).unwrap(), 7);
+        assert_eq!(code.table.decode(&mut reader).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_oversubscribed_lengths() {
+        assert!(Code::new(&[1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn single_code_is_incomplete() {
+        let code = Code::new(&[0, 1, 0]).unwrap();
+        let mut reader = reader_for(&[(0, 1), (1, 1)]);
+
+        assert!(!code.is_complete());
+        assert!(code.is_single());
+        assert_eq!(code.table.decode(&mut reader).unwrap(), 1);
+        assert!(code.table.decode(&mut reader).is_err());
+    }
+}
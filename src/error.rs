@@ -0,0 +1,131 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Why compressing or decompressing failed.
+///
+/// Errors raised while reading or writing through the `Read` and `Write`
+/// adapters travel inside an `io::Error`; converting that back with
+/// `Error::from` recovers them.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading the input or writing the output failed.
+    Io(io::Error),
+    /// The input does not start like a stream of the expected format.
+    BadMagic,
+    /// The stream was written in a version of the format this one does not
+    /// read.
+    UnsupportedVersion(u8),
+    /// The stream uses a codec, filter or feature this version does not
+    /// know.
+    Unsupported(String),
+    /// A code table or frequency table in the stream is malformed.
+    CorruptTable(String),
+    /// The stream is malformed outside its tables.
+    CorruptData(String),
+    /// The stream ends early.
+    Truncated,
+    /// The decompressed data does not match the checksum stored with it.
+    ChecksumMismatch,
+    /// A length or count is beyond what the format can represent.
+    LimitExceeded(String),
+    /// The options are out of range.
+    InvalidOptions(String),
+}
+
+impl Error {
+    /// The `io::ErrorKind` the error takes when passed on as an `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Io(error) => error.kind(),
+            Error::Truncated => io::ErrorKind::UnexpectedEof,
+            Error::InvalidOptions(_) => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => error.fmt(f),
+            Error::BadMagic => f.write_str("not a compressed stream (bad magic)"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Error::Truncated => f.write_str("stream is truncated"),
+            Error::ChecksumMismatch => f.write_str("checksum mismatch"),
+            Error::Unsupported(message)
+            | Error::CorruptTable(message)
+            | Error::CorruptData(message)
+            | Error::LimitExceeded(message)
+            | Error::InvalidOptions(message) => f.write_str(message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Unwraps an `Error` carried by an `io::Error`, and treats any other
+/// unexpected end of input as a truncated stream.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Error::Truncated,
+            _ => Error::Io(error),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_a_trip_through_io_error() {
+        let error = io::Error::from(Error::CorruptTable("code count out of range".into()));
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "code count out of range");
+        assert!(matches!(Error::from(error), Error::CorruptTable(_)));
+    }
+
+    #[test]
+    fn wraps_plain_io_errors() {
+        let error = Error::from(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+
+        assert!(matches!(error, Error::Io(_)));
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn unexpected_end_of_input_is_truncation() {
+        let error = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+
+        assert!(matches!(error, Error::Truncated));
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,407 @@
+mod delta;
+mod rle;
+mod shuffle;
+
+use crate::error::Error;
+use std::io::{self, BufWriter, Read, Write};
+
+/// A reversible transform applied to the input before a codec sees it, and
+/// recorded in the header so that `decompress` undoes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Shortens runs of a repeated byte, such as the zeros that fill
+    /// sparse files and disk images, to a few bytes each.
+    Rle,
+    /// Replaces each byte with its difference from the byte `stride` bytes
+    /// earlier, which turns slowly changing integers `stride` bytes wide
+    /// into small numbers.
+    Delta { stride: u8 },
+    /// Replaces each byte with its XOR with the byte `stride` bytes
+    /// earlier, which clears the sign, exponent and leading mantissa bits
+    /// that a float `stride` bytes wide shares with the one before.
+    Xor { stride: u8 },
+    /// Gathers the first byte of every `width`-byte element, then the
+    /// second and so on, like Blosc's shuffle, so that the high bytes that
+    /// barely change sit together.
+    Shuffle { width: u8 },
+}
+
+impl Filter {
+    fn id(self) -> u8 {
+        match self {
+            Filter::Rle => 1,
+            Filter::Delta { .. } => 2,
+            Filter::Xor { .. } => 3,
+            Filter::Shuffle { .. } => 4,
+        }
+    }
+
+    /// The stride or element width of the filter, if it has one.
+    fn parameter(self) -> Option<u8> {
+        match self {
+            Filter::Rle => None,
+            Filter::Delta { stride } | Filter::Xor { stride } => Some(stride),
+            Filter::Shuffle { width } => Some(width),
+        }
+    }
+
+    fn encoder(self) -> Box<dyn Transform> {
+        match self {
+            Filter::Rle => Box::new(rle::Encoder::default()),
+            Filter::Delta { stride } => {
+                Box::new(delta::Encoder::new(delta::Operation::Subtract, stride))
+            }
+            Filter::Xor { stride } => Box::new(delta::Encoder::new(delta::Operation::Xor, stride)),
+            Filter::Shuffle { width } => Box::new(shuffle::Shuffler::new(width, false)),
+        }
+    }
+
+    fn decoder(self) -> Box<dyn Transform> {
+        match self {
+            Filter::Rle => Box::new(rle::Decoder::default()),
+            Filter::Delta { stride } => {
+                Box::new(delta::Decoder::new(delta::Operation::Subtract, stride))
+            }
+            Filter::Xor { stride } => Box::new(delta::Decoder::new(delta::Operation::Xor, stride)),
+            Filter::Shuffle { width } => Box::new(shuffle::Shuffler::new(width, true)),
+        }
+    }
+
+    /// Upper bound on the filtered length of an input of `input_len` bytes.
+    fn max_filtered_len(self, input_len: u64) -> u64 {
+        match self {
+            Filter::Rle => rle::max_encoded_len(input_len),
+            _ => input_len,
+        }
+    }
+}
+
+/// Rejects a chain too long to record, or a zero stride or width.
+pub fn check_filters(filters: &[Filter]) -> io::Result<()> {
+    if filters.len() > u8::MAX as usize {
+        return Err(
+            Error::InvalidOptions(format!("at most {} filters can be chained", u8::MAX)).into(),
+        );
+    }
+
+    if filters.iter().any(|filter| filter.parameter() == Some(0)) {
+        return Err(Error::InvalidOptions(
+            "filter stride and width must be at least one byte".into(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Upper bound on the length of an input of `input_len` bytes after
+/// `filters`.
+pub fn max_filtered_len(filters: &[Filter], input_len: u64) -> u64 {
+    filters
+        .iter()
+        .fold(input_len, |len, filter| filter.max_filtered_len(len))
+}
+
+/// Number of bytes `write_filters` uses for `filters`.
+pub fn filters_len(filters: &[Filter]) -> u64 {
+    let parameters = filters.iter().filter(|filter| filter.parameter().is_some());
+
+    1 + filters.len() as u64 + parameters.count() as u64
+}
+
+/// Writes the number of filters, then each filter's id and parameter, if
+/// it has one.
+pub fn write_filters<W: Write>(writer: &mut W, filters: &[Filter]) -> io::Result<()> {
+    writer.write_all(&[filters.len() as u8])?;
+
+    for filter in filters {
+        writer.write_all(&[filter.id()])?;
+        if let Some(parameter) = filter.parameter() {
+            writer.write_all(&[parameter])?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_filters<R: Read>(reader: &mut R) -> io::Result<Vec<Filter>> {
+    let mut count = [0];
+    reader.read_exact(&mut count)?;
+
+    let mut filters = Vec::with_capacity(count[0] as usize);
+    for _ in 0..count[0] {
+        let mut id = [0];
+        reader.read_exact(&mut id)?;
+
+        let mut read_parameter = || -> io::Result<u8> {
+            let mut parameter = [0];
+            reader.read_exact(&mut parameter)?;
+
+            match parameter[0] {
+                0 => Err(invalid_data("filter stride or width of zero")),
+                parameter => Ok(parameter),
+            }
+        };
+
+        filters.push(match id[0] {
+            1 => Filter::Rle,
+            2 => Filter::Delta {
+                stride: read_parameter()?,
+            },
+            3 => Filter::Xor {
+                stride: read_parameter()?,
+            },
+            4 => Filter::Shuffle {
+                width: read_parameter()?,
+            },
+            id => return Err(Error::Unsupported(format!("unknown filter id {id}")).into()),
+        });
+    }
+
+    Ok(filters)
+}
+
+/// One direction of a filter, applied to a stream a piece at a time.
+trait Transform {
+    /// Transforms `input`, writing what can already be written to `output`.
+    fn push(&mut self, input: &[u8], output: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes whatever is held back at the end of the stream.
+    fn finish(&mut self, output: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Feeds what is written to it through the first of `transforms`, whose
+/// output goes through the rest in turn and then to `writer`.
+struct Chain<'a, W: Write> {
+    transforms: &'a mut [Box<dyn Transform>],
+    writer: &'a mut W,
+}
+
+impl<W: Write> Chain<'_, W> {
+    /// Finishes each transform in turn, passing what it held back through
+    /// the ones after it.
+    fn finish(self) -> io::Result<()> {
+        for start in 0..self.transforms.len() {
+            if let [transform, rest @ ..] = &mut self.transforms[start..] {
+                let mut chain = Chain {
+                    transforms: rest,
+                    writer: &mut *self.writer,
+                };
+
+                transform.finish(&mut chain)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Chain<'_, W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self.transforms.split_first_mut() {
+            None => self.writer.write_all(bytes)?,
+            Some((transform, rest)) => {
+                let mut chain = Chain {
+                    transforms: rest,
+                    writer: &mut *self.writer,
+                };
+
+                transform.push(bytes, &mut chain)?;
+            }
+        }
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A chain of filters applied to a stream a piece at a time.
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    /// Applies `filters` in order.
+    pub fn encoder(filters: &[Filter]) -> Self {
+        Pipeline {
+            transforms: filters.iter().map(|filter| filter.encoder()).collect(),
+        }
+    }
+
+    /// Undoes `filters`, last filter first.
+    pub fn decoder(filters: &[Filter]) -> Self {
+        Pipeline {
+            transforms: filters
+                .iter()
+                .rev()
+                .map(|filter| filter.decoder())
+                .collect(),
+        }
+    }
+
+    /// Passes `input` through the chain, writing what can already be written
+    /// to `output`.
+    pub fn push<W: Write>(&mut self, input: &[u8], output: &mut W) -> io::Result<()> {
+        Chain {
+            transforms: &mut self.transforms,
+            writer: output,
+        }
+        .write_all(input)
+    }
+
+    /// Writes out what the filters held back, rejecting filtered data that
+    /// ends where it cannot.
+    pub fn finish<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        Chain {
+            transforms: &mut self.transforms,
+            writer: output,
+        }
+        .finish()
+    }
+}
+
+/// Undoes a chain of filters on what is written to it, last filter first.
+pub struct Decoder<W: Write> {
+    writer: BufWriter<W>,
+    pipeline: Pipeline,
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(writer: W, filters: &[Filter]) -> Self {
+        Decoder {
+            writer: BufWriter::new(writer),
+            pipeline: Pipeline::decoder(filters),
+        }
+    }
+
+    /// Writes out what the filters held back, rejecting filtered data that
+    /// ends where it cannot, and flushes the output.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.pipeline.finish(&mut self.writer)?;
+
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.pipeline.push(bytes, &mut self.writer)?;
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const ALL_FILTERS: [Filter; 4] = [
+        Filter::Rle,
+        Filter::Delta { stride: 4 },
+        Filter::Xor { stride: 8 },
+        Filter::Shuffle { width: 4 },
+    ];
+
+    fn encode(input: &[u8], filters: &[Filter]) -> Vec<u8> {
+        let mut pipeline = Pipeline::encoder(filters);
+        let mut output = Vec::new();
+        pipeline.push(input, &mut output).unwrap();
+        pipeline.finish(&mut output).unwrap();
+        output
+    }
+
+    /// Decodes `input` written in pieces of `piece_len` bytes.
+    fn decode(input: &[u8], filters: &[Filter], piece_len: usize) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut decoder = Decoder::new(&mut output, filters);
+        for piece in input.chunks(piece_len) {
+            decoder.write_all(piece)?;
+        }
+        decoder.finish()?;
+        Ok(output)
+    }
+
+    /// Little-endian `u32` readings that climb slowly, with zero padding
+    /// between records.
+    fn sensor_dump() -> Vec<u8> {
+        let mut dump = Vec::new();
+        for record in 0..500u32 {
+            for reading in 0..64 {
+                dump.extend((100_000 + record * 37 + reading * (record % 5)).to_le_bytes());
+            }
+            dump.extend([0; 40]);
+        }
+        dump
+    }
+
+    #[test]
+    fn chains_round_trip_in_any_order() {
+        let input = sensor_dump();
+
+        for first in ALL_FILTERS {
+            for second in ALL_FILTERS {
+                let filters = [first, second];
+                let encoded = encode(&input, &filters);
+
+                assert!(encoded.len() as u64 <= max_filtered_len(&filters, input.len() as u64));
+                for piece_len in [1, 1000, encoded.len().max(1)] {
+                    assert_eq!(decode(&encoded, &filters, piece_len).unwrap(), input);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_chain_passes_bytes_through() {
+        let input = sensor_dump();
+
+        assert_eq!(encode(&input, &[]), input);
+        assert_eq!(decode(&input, &[], 777).unwrap(), input);
+    }
+
+    #[test]
+    fn round_trips_empty_and_short_inputs() {
+        for input in [&b""[..], b"a", b"abc", b"abcdefg"] {
+            let encoded = encode(input, &ALL_FILTERS);
+
+            assert_eq!(decode(&encoded, &ALL_FILTERS, 1).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn chain_is_written_with_parameters() {
+        let mut bytes = vec![];
+        write_filters(&mut bytes, &ALL_FILTERS).unwrap();
+
+        assert_eq!(bytes, [4, 1, 2, 4, 3, 8, 4, 4]);
+        assert_eq!(filters_len(&ALL_FILTERS), bytes.len() as u64);
+        assert_eq!(read_filters(&mut Cursor::new(bytes)).unwrap(), ALL_FILTERS);
+    }
+
+    #[test]
+    fn read_filters_rejects_unknown_id_and_zero_parameter() {
+        for bytes in [&[1, 0][..], &[1, 9], &[1, 2, 0], &[2, 1]] {
+            assert!(read_filters(&mut Cursor::new(bytes)).is_err());
+        }
+    }
+
+    #[test]
+    fn check_filters_rejects_zero_stride() {
+        let error = check_filters(&[Filter::Rle, Filter::Xor { stride: 0 }]).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(check_filters(&ALL_FILTERS).is_ok());
+    }
+}
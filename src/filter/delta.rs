@@ -0,0 +1,168 @@
+use crate::filter::Transform;
+use std::io::{self, Write};
+
+/// How a byte is combined with the byte `stride` bytes before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Subtract,
+    Xor,
+}
+
+/// The last `stride` bytes of the unfiltered data, as a ring; zeros before
+/// the start of the stream.
+struct History {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl History {
+    fn new(stride: u8) -> Self {
+        History {
+            bytes: vec![0; stride as usize],
+            position: 0,
+        }
+    }
+
+    /// Returns the byte `stride` bytes before `byte`, and adds `byte`.
+    fn replace(&mut self, byte: u8) -> u8 {
+        let previous = std::mem::replace(&mut self.bytes[self.position], byte);
+        self.position = (self.position + 1) % self.bytes.len();
+
+        previous
+    }
+}
+
+/// Replaces each byte with the result of `operation` on it and the byte
+/// `stride` bytes before it.
+pub struct Encoder {
+    operation: Operation,
+    history: History,
+    output: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(operation: Operation, stride: u8) -> Self {
+        Encoder {
+            operation,
+            history: History::new(stride),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Transform for Encoder {
+    fn push(&mut self, input: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        self.output.clear();
+        self.output.extend(input.iter().map(|&byte| {
+            let previous = self.history.replace(byte);
+
+            match self.operation {
+                Operation::Subtract => byte.wrapping_sub(previous),
+                Operation::Xor => byte ^ previous,
+            }
+        }));
+
+        output.write_all(&self.output)
+    }
+
+    fn finish(&mut self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Undoes `Encoder`.
+pub struct Decoder {
+    operation: Operation,
+    history: History,
+    output: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(operation: Operation, stride: u8) -> Self {
+        Decoder {
+            operation,
+            history: History::new(stride),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Transform for Decoder {
+    fn push(&mut self, input: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        self.output.clear();
+
+        for &byte in input {
+            let previous = self.history.bytes[self.history.position];
+            let decoded = match self.operation {
+                Operation::Subtract => byte.wrapping_add(previous),
+                Operation::Xor => byte ^ previous,
+            };
+
+            self.history.replace(decoded);
+            self.output.push(decoded);
+        }
+
+        output.write_all(&self.output)
+    }
+
+    fn finish(&mut self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(input: &[u8], operation: Operation, stride: u8) -> Vec<u8> {
+        let mut output = Vec::new();
+        Encoder::new(operation, stride)
+            .push(input, &mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn subtracts_byte_a_stride_earlier() {
+        let input = [10, 20, 11, 22, 12, 24];
+
+        assert_eq!(
+            encode(&input, Operation::Subtract, 1),
+            [10, 10, 247, 11, 246, 12]
+        );
+        assert_eq!(encode(&input, Operation::Subtract, 2), [10, 20, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn xor_clears_shared_float_bits() {
+        let input: Vec<u8> = [1.5f32, 1.5, 3.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+
+        let encoded = encode(&input, Operation::Xor, 4);
+
+        assert_eq!(encoded[4..8], [0; 4]);
+        // Only the exponent bits differ between 1.5 and 3.0.
+        assert_eq!(encoded[8..12], [0, 0, 0x80, 0x7F]);
+    }
+
+    #[test]
+    fn decoder_undoes_encoder_across_pieces() {
+        let input: Vec<u8> = (0..1000u32).flat_map(|i| (i * i).to_le_bytes()).collect();
+
+        for operation in [Operation::Subtract, Operation::Xor] {
+            for stride in [1, 3, 4, 8] {
+                let encoded = encode(&input, operation, stride);
+
+                let mut decoder = Decoder::new(operation, stride);
+                let mut output = Vec::new();
+                for piece in encoded.chunks(7) {
+                    decoder.push(piece, &mut output).unwrap();
+                }
+
+                assert_eq!(output, input);
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::container;
+use crate::error::Error;
+use crate::filter::{Transform, invalid_data};
+use crate::framing::MAX_BLOCK_SIZE;
+use std::io::{self, Write};
+
+/// Identical bytes in a row after which the run's remaining length follows,
+/// as in bzip2's first stage.
+const RUN_THRESHOLD: u8 = 4;
+
+/// Most repeats one count may stand for, so that a few bytes of a stream
+/// cannot make the decoder write without end. Longer runs are split.
+const MAX_REPEATS: u64 = MAX_BLOCK_SIZE as u64;
+
+/// Every group of `RUN_THRESHOLD` identical bytes gains a count of at least
+/// one byte, and is followed by a different byte.
+pub fn max_encoded_len(input_len: u64) -> u64 {
+    input_len + input_len / RUN_THRESHOLD as u64
+}
+
+/// Tracks the run of identical bytes at the end of the data so far.
+#[derive(Default)]
+struct Run {
+    byte: u8,
+    len: u8,
+}
+
+impl Run {
+    /// Adds `byte`, returning whether the run has just reached
+    /// `RUN_THRESHOLD`, so that its count comes next.
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len > 0 && byte == self.byte {
+            self.len += 1;
+        } else {
+            self.byte = byte;
+            self.len = 1;
+        }
+
+        self.len == RUN_THRESHOLD
+    }
+}
+
+/// Copies bytes, except that `RUN_THRESHOLD` identical bytes are followed
+/// by a varint of the number of times the byte repeats after them, and
+/// those repeats are left out.
+#[derive(Default)]
+pub struct Encoder {
+    run: Run,
+    /// Repeats counted since the run reached `RUN_THRESHOLD`, if it has.
+    repeats: Option<u64>,
+    output: Vec<u8>,
+}
+
+impl Transform for Encoder {
+    fn push(&mut self, input: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        self.output.clear();
+
+        for &byte in input {
+            if let Some(repeats) = &mut self.repeats {
+                if byte == self.run.byte && *repeats < MAX_REPEATS {
+                    *repeats += 1;
+                    continue;
+                }
+
+                container::write_varint(&mut self.output, *repeats)?;
+                self.repeats = None;
+                self.run = Run::default();
+            }
+
+            self.output.push(byte);
+            if self.run.push(byte) {
+                self.repeats = Some(0);
+            }
+        }
+
+        output.write_all(&self.output)
+    }
+
+    fn finish(&mut self, mut output: &mut dyn Write) -> io::Result<()> {
+        match self.repeats.take() {
+            Some(repeats) => container::write_varint(&mut output, repeats),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Undoes `Encoder`.
+#[derive(Default)]
+pub struct Decoder {
+    run: Run,
+    /// The count read so far and its length in bits, once a run has
+    /// reached `RUN_THRESHOLD`.
+    count: Option<(u64, u32)>,
+}
+
+impl Transform for Decoder {
+    fn push(&mut self, input: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        // The start of the bytes copied since the last count.
+        let mut copied = 0;
+
+        for (position, &byte) in input.iter().enumerate() {
+            let Some((repeats, shift)) = &mut self.count else {
+                if self.run.push(byte) {
+                    output.write_all(&input[copied..=position])?;
+                    self.count = Some((0, 0));
+                }
+
+                continue;
+            };
+
+            let bits = (byte & 0x7F) as u64;
+            if bits.checked_shl(*shift).map(|shifted| shifted >> *shift) != Some(bits) {
+                return Err(Error::LimitExceeded("run length overflows u64".into()).into());
+            }
+
+            *repeats |= bits << *shift;
+            *shift += 7;
+
+            if byte & 0x80 == 0 {
+                if *repeats > MAX_REPEATS {
+                    return Err(Error::LimitExceeded(
+                        "run is longer than the largest block size".into(),
+                    )
+                    .into());
+                }
+
+                write_repeats(output, self.run.byte, *repeats)?;
+                self.count = None;
+                self.run = Run::default();
+                copied = position + 1;
+            }
+        }
+
+        if self.count.is_none() {
+            output.write_all(&input[copied..])?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut dyn Write) -> io::Result<()> {
+        match self.count {
+            Some(_) => Err(invalid_data("run-length data ends before a run's count")),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_repeats(output: &mut dyn Write, byte: u8, mut repeats: u64) -> io::Result<()> {
+    let chunk = [byte; 256];
+
+    while repeats > 0 {
+        let len = repeats.min(chunk.len() as u64) as usize;
+        output.write_all(&chunk[..len])?;
+        repeats -= len as u64;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(input: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        let mut output = Vec::new();
+        encoder.push(input, &mut output).unwrap();
+        encoder.finish(&mut output).unwrap();
+        output
+    }
+
+    fn decode(input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoder = Decoder::default();
+        let mut output = Vec::new();
+        decoder.push(input, &mut output)?;
+        decoder.finish(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn counts_repeats_after_four_identical_bytes() {
+        assert_eq!(encode(b"abbbcccc"), b"abbbcccc\0");
+        assert_eq!(encode(b"aaaaaaab"), b"aaaa\x03b");
+    }
+
+    #[test]
+    fn long_runs_shrink_to_a_few_bytes() {
+        let encoded = encode(&[0; 1 << 20]);
+
+        assert_eq!(encoded.len(), 4 + 3);
+        assert_eq!(decode(&encoded).unwrap(), vec![0; 1 << 20]);
+    }
+
+    #[test]
+    fn decode_undoes_encode() {
+        let mut input = b"xyz".repeat(100);
+        input.extend([7; 5000]);
+        input.extend(b"aaaabbbbbccccccdddd");
+        input.extend((0..=255).cycle().take(1000));
+
+        let encoded = encode(&input);
+
+        assert!(encoded.len() as u64 <= max_encoded_len(input.len() as u64));
+        assert_eq!(decode(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn splits_runs_longer_than_the_limit() {
+        let input = vec![0; MAX_REPEATS as usize + 10];
+        let encoded = encode(&input);
+
+        // A run of the most repeats, then a run of the 6 bytes left.
+        assert_eq!(encoded.len(), 4 + 4 + 4 + 1);
+        assert_eq!(decode(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_count_over_the_limit() {
+        let mut encoded = b"aaaa".to_vec();
+        container::write_varint(&mut encoded, MAX_REPEATS + 1).unwrap();
+
+        let error = decode(&encoded).unwrap_err();
+        assert!(matches!(Error::from(error), Error::LimitExceeded(_)));
+    }
+
+    #[test]
+    fn rejects_missing_count() {
+        assert!(decode(b"aaaa").is_err());
+        assert!(decode(b"aaaa\x80").is_err());
+    }
+}
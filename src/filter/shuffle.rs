@@ -0,0 +1,115 @@
+use crate::filter::Transform;
+use std::io::{self, Write};
+
+/// Elements shuffled together. The last block of a stream may be shorter,
+/// and any bytes after its last whole element are left in place.
+const BLOCK_ELEMENTS: usize = 16 * 1024;
+
+/// Shuffles, or with `unshuffle` restores, blocks of `width`-byte elements.
+pub struct Shuffler {
+    width: usize,
+    unshuffle: bool,
+    block: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Shuffler {
+    pub fn new(width: u8, unshuffle: bool) -> Self {
+        Shuffler {
+            width: width as usize,
+            unshuffle,
+            block: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    fn block_len(&self) -> usize {
+        self.width * BLOCK_ELEMENTS
+    }
+
+    /// Writes the first `len` bytes of `block`, shuffled or restored.
+    fn write_block(&mut self, len: usize, output: &mut dyn Write) -> io::Result<()> {
+        let elements = len / self.width;
+        let block = &self.block[..elements * self.width];
+
+        self.output.resize(block.len(), 0);
+        for byte in 0..self.width {
+            for element in 0..elements {
+                let (shuffled, unshuffled) =
+                    (byte * elements + element, element * self.width + byte);
+
+                if self.unshuffle {
+                    self.output[unshuffled] = block[shuffled];
+                } else {
+                    self.output[shuffled] = block[unshuffled];
+                }
+            }
+        }
+
+        output.write_all(&self.output)?;
+        output.write_all(&self.block[block.len()..len])
+    }
+}
+
+impl Transform for Shuffler {
+    fn push(&mut self, mut input: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        let block_len = self.block_len();
+
+        while !input.is_empty() {
+            let len = input.len().min(block_len - self.block.len());
+            self.block.extend_from_slice(&input[..len]);
+            input = &input[len..];
+
+            if self.block.len() == block_len {
+                self.write_block(block_len, output)?;
+                self.block.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        self.write_block(self.block.len(), output)?;
+        self.block.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &[u8], width: u8, unshuffle: bool) -> Vec<u8> {
+        let mut shuffler = Shuffler::new(width, unshuffle);
+        let mut output = Vec::new();
+        shuffler.push(input, &mut output).unwrap();
+        shuffler.finish(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn gathers_bytes_of_each_place() {
+        let input = [1, 2, 3, 4, 11, 12, 13, 14, 21, 22, 23, 24, 99];
+
+        assert_eq!(
+            run(&input, 4, false),
+            [1, 11, 21, 2, 12, 22, 3, 13, 23, 4, 14, 24, 99]
+        );
+    }
+
+    #[test]
+    fn unshuffle_undoes_shuffle_over_several_blocks() {
+        let input: Vec<u8> = (0..3 * BLOCK_ELEMENTS as u64 + 5)
+            .flat_map(|i| (i * 7919).to_le_bytes())
+            .take(3 * 8 * BLOCK_ELEMENTS + 13)
+            .collect();
+
+        for width in [1, 2, 3, 4, 8, 16] {
+            let shuffled = run(&input, width, false);
+
+            assert_eq!(run(&shuffled, width, true), input);
+        }
+    }
+}
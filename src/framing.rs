@@ -0,0 +1,433 @@
+//! The block framing shared by the codecs that code each block on its own
+//! or store it:
+//!
+//! ```text
+//! header | (block length varint | block) ... | 0 | checksum trailer
+//! ```
+//!
+//! Each codec supplies only how it codes and decodes a block.
+
+use crate::checksum::{Checksum, ChecksumKind};
+use crate::container::{self, CodecId, Header};
+use crate::error::Error;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::{self, block::write_block_length};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::{panic, thread};
+
+/// Largest block a stream may have. Some codecs can code a block in a few
+/// bytes whatever its length, so decoders need a limit to trust the length.
+pub const MAX_BLOCK_SIZE: usize = 16 << 20;
+
+/// Decoded bytes are checksummed and written in chunks of this size, so a
+/// long block is never held in memory whole.
+const OUTPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How the data of a block is represented, written after its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    /// The block's bytes, copied verbatim.
+    Stored = 0,
+    /// The block in the codec's coded form.
+    Coded = 1,
+}
+
+impl TryFrom<u8> for BlockType {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(BlockType::Stored),
+            1 => Ok(BlockType::Coded),
+            _ => Err(Error::CorruptData(format!("unknown block type {value}")).into()),
+        }
+    }
+}
+
+/// What the header of a stream records, and how much input each block
+/// takes.
+pub struct Frame {
+    pub codec: CodecId,
+    pub checksum: ChecksumKind,
+    pub block_size: usize,
+}
+
+/// Writes `input` as a framed stream, calling `encode` to write what
+/// follows the length of each block.
+///
+/// An input that fits in one block has its length recorded in the header.
+pub fn compress<R, W, F>(input: R, output: W, frame: &Frame, mut encode: F) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut BitWriter<BufWriter<W>>, &[u8]) -> io::Result<()>,
+{
+    write_stream(input, output, frame, 1, |writer, blocks| {
+        for block in blocks {
+            write_block_length(writer, block.len() as u64)?;
+            encode(writer, block)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Like `compress` for codecs whose blocks do not depend on each other:
+/// `code` returns the coded form of a block, and up to `threads` blocks are
+/// coded at once. Each block is written as `write_smaller` picks, in order,
+/// so the stream is the same whatever the thread count.
+pub fn compress_parallel<R, W, F>(
+    input: R,
+    output: W,
+    frame: &Frame,
+    threads: usize,
+    code: F,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: Fn(&[u8]) -> io::Result<Vec<u8>> + Sync,
+{
+    if threads == 0 {
+        return Err(Error::InvalidOptions("thread count must be at least 1".into()).into());
+    }
+
+    write_stream(input, output, frame, threads, |writer, blocks| {
+        for (block, coded) in blocks.iter().zip(code_blocks(blocks, &code)?) {
+            write_block_length(writer, block.len() as u64)?;
+            write_smaller(writer, block, &coded)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Reads `input` in batches of up to `batch_size` blocks, and writes the
+/// header, each batch with `write_batch`, and the end of the stream.
+fn write_stream<R, W, F>(
+    input: R,
+    output: W,
+    frame: &Frame,
+    batch_size: usize,
+    mut write_batch: F,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut BitWriter<BufWriter<W>>, &[Vec<u8>]) -> io::Result<()>,
+{
+    if !(1..=MAX_BLOCK_SIZE).contains(&frame.block_size) {
+        return Err(Error::InvalidOptions(format!(
+            "block size must be between 1 and {MAX_BLOCK_SIZE} bytes"
+        ))
+        .into());
+    }
+
+    let mut reader = BufReader::new(input);
+    let mut writer = BitWriter::new(BufWriter::new(output));
+    let mut checksum = frame.checksum.new_checksum();
+    let mut blocks = Vec::with_capacity(batch_size);
+
+    read_blocks(&mut reader, &mut blocks, frame.block_size, batch_size)?;
+
+    let original_length = match &blocks[..] {
+        [] => Some(0),
+        [block] if reader.fill_buf()?.is_empty() => Some(block.len() as u64),
+        _ => None,
+    };
+    Header::new(frame.codec, frame.checksum, original_length).write_to(&mut writer.writer)?;
+
+    while !blocks.is_empty() {
+        for block in &blocks {
+            checksum.update(block);
+        }
+
+        write_batch(&mut writer, &blocks)?;
+
+        read_blocks(&mut reader, &mut blocks, frame.block_size, batch_size)?;
+    }
+
+    write_block_length(&mut writer, 0)?;
+    writer.write_bytes(&checksum.to_trailer())?;
+
+    writer.writer.flush()
+}
+
+/// Replaces `blocks` with up to `count` blocks read from `reader`, stopping
+/// at the end of the input.
+fn read_blocks<R: Read>(
+    reader: &mut R,
+    blocks: &mut Vec<Vec<u8>>,
+    block_size: usize,
+    count: usize,
+) -> io::Result<()> {
+    blocks.clear();
+
+    while blocks.len() < count {
+        let mut block = Vec::with_capacity(block_size);
+        huffman::read_block(reader, &mut block, block_size)?;
+
+        if block.is_empty() {
+            break;
+        }
+
+        let is_last = block.len() < block_size;
+        blocks.push(block);
+        if is_last {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Codes each of `blocks` with `code`, on a thread of its own if there is
+/// more than one.
+fn code_blocks<F>(blocks: &[Vec<u8>], code: &F) -> io::Result<Vec<Vec<u8>>>
+where
+    F: Fn(&[u8]) -> io::Result<Vec<u8>> + Sync,
+{
+    if let [block] = blocks {
+        return Ok(vec![code(block)?]);
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = blocks
+            .iter()
+            .map(|block| scope.spawn(|| code(block)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Writes `coded` as a `Coded` block, or `block` stored if that is no
+/// larger.
+pub fn write_smaller<W: Write>(
+    writer: &mut BitWriter<W>,
+    block: &[u8],
+    coded: &[u8],
+) -> io::Result<()> {
+    if coded.len() < block.len() {
+        writer.write_bytes(&[BlockType::Coded as u8])?;
+        writer.write_bytes(coded)
+    } else {
+        writer.write_bytes(&[BlockType::Stored as u8])?;
+        writer.write_bytes(block)
+    }
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`. `decode` is given each block's length, reads
+/// what follows it and writes that many bytes to the `Output`.
+pub fn decompress<R, W, F>(
+    reader: BufReader<R>,
+    output: W,
+    header: &Header,
+    mut decode: F,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut BitReader<BufReader<R>>, u64, &mut Output<W>) -> io::Result<()>,
+{
+    let mut reader = BitReader::new(reader);
+    let mut output = Output {
+        chunk: Vec::new(),
+        checksum: header.checksum.new_checksum(),
+        writer: BufWriter::new(output),
+    };
+    let mut total_length = 0u64;
+
+    loop {
+        let block_length = container::read_varint(&mut reader)?;
+        if block_length == 0 {
+            break;
+        }
+
+        if block_length > MAX_BLOCK_SIZE as u64 {
+            return Err(
+                Error::LimitExceeded("block is longer than the largest block size".into()).into(),
+            );
+        }
+
+        if let Some(length) = header.original_length
+            && block_length > length.saturating_sub(total_length)
+        {
+            return Err(Error::LimitExceeded(
+                "blocks are longer than the stream length in the header".into(),
+            )
+            .into());
+        }
+
+        decode(&mut reader, block_length, &mut output)?;
+        output.flush_chunk()?;
+
+        total_length = total_length
+            .checked_add(block_length)
+            .ok_or_else(|| Error::LimitExceeded("stream length overflows u64".into()))?;
+    }
+
+    if header
+        .original_length
+        .is_some_and(|length| length != total_length)
+    {
+        return Err(invalid_data("stream length does not match the header"));
+    }
+
+    let mut trailer = vec![0; header.checksum.trailer_len()];
+    reader.read_bytes(&mut trailer)?;
+    output.checksum.verify(&trailer)?;
+
+    if reader.remaining_bits()? != Some(0) {
+        return Err(invalid_data("unexpected data after end of stream"));
+    }
+
+    output.writer.flush()
+}
+
+/// Reads the `BlockType` that follows a block's length.
+pub fn read_block_type<R: Read>(reader: &mut BitReader<R>) -> io::Result<BlockType> {
+    let mut block_type = [0];
+    reader.read_bytes(&mut block_type)?;
+
+    BlockType::try_from(block_type[0])
+}
+
+/// Where decoded bytes go: checksummed, then written.
+pub struct Output<W: Write> {
+    /// Bytes pushed but not yet written.
+    chunk: Vec<u8>,
+    checksum: Checksum,
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn push(&mut self, byte: u8) -> io::Result<()> {
+        self.chunk.push(byte);
+
+        if self.chunk.len() == OUTPUT_CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+
+        Ok(())
+    }
+
+    /// Pushes `length` bytes, each returned by `decode`.
+    pub fn write_decoded(
+        &mut self,
+        length: u64,
+        mut decode: impl FnMut() -> io::Result<u8>,
+    ) -> io::Result<()> {
+        for _ in 0..length {
+            self.push(decode()?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.flush_chunk()?;
+        self.checksum.update(bytes);
+        self.writer.write_all(bytes)
+    }
+
+    /// Copies a stored block of `length` bytes from `reader`.
+    pub fn copy_stored<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        length: u64,
+    ) -> io::Result<()> {
+        self.flush_chunk()?;
+
+        let mut remaining = length;
+        while remaining > 0 {
+            let chunk_len = remaining.min(OUTPUT_CHUNK_SIZE as u64) as usize;
+
+            self.chunk.resize(chunk_len, 0);
+            reader.read_bytes(&mut self.chunk)?;
+            self.flush_chunk()?;
+
+            remaining -= chunk_len as u64;
+        }
+
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        self.checksum.update(&self.chunk);
+        self.writer.write_all(&self.chunk)?;
+        self.chunk.clear();
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_smaller_bytes(block: &[u8], coded: &[u8]) -> Vec<u8> {
+        let mut writer = BitWriter::new(Vec::new());
+        write_smaller(&mut writer, block, coded).unwrap();
+        writer.writer
+    }
+
+    #[test]
+    fn codes_block_only_when_smaller() {
+        assert_eq!(write_smaller_bytes(b"aaaa", b"a4"), [1, b'a', b'4']);
+        assert_eq!(write_smaller_bytes(b"ab", b"ab"), [0, b'a', b'b']);
+    }
+
+    #[test]
+    fn parallel_stream_matches_serial_one() {
+        let input: Vec<u8> = (0..10_000u32).map(|i| (i % 7 * i % 13) as u8).collect();
+        let frame = Frame {
+            codec: CodecId::RangeCoder,
+            checksum: ChecksumKind::Crc32,
+            block_size: 1000,
+        };
+        // Blocks whose first byte is even are coded to a single byte.
+        let code = |block: &[u8]| -> io::Result<Vec<u8>> {
+            Ok(if block[0].is_multiple_of(2) {
+                vec![0]
+            } else {
+                block.to_vec()
+            })
+        };
+
+        let mut serial = Vec::new();
+        compress(&input[..], &mut serial, &frame, |writer, block| {
+            write_smaller(writer, block, &code(block)?)
+        })
+        .unwrap();
+
+        for threads in [1, 3, 16] {
+            let mut parallel = Vec::new();
+            compress_parallel(&input[..], &mut parallel, &frame, threads, code).unwrap();
+            assert_eq!(parallel, serial, "{threads} threads");
+        }
+
+        let error = compress_parallel(&input[..], Vec::new(), &frame, 0, code).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn block_type_from_byte() {
+        assert_eq!(BlockType::try_from(1).unwrap(), BlockType::Coded);
+        assert!(BlockType::try_from(2).is_err());
+    }
+}
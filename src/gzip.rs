@@ -0,0 +1,480 @@
+//! gzip (RFC 1952) and zlib (RFC 1950) framing around DEFLATE streams.
+
+use crate::checksum::{Adler32, Crc32};
+use crate::deflate;
+use crate::deflate::bit_reader::BitReader;
+use crate::error::Error;
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The compression method of both formats: DEFLATE.
+const METHOD_DEFLATE: u8 = 8;
+/// Written as the gzip OS field.
+const OS_UNKNOWN: u8 = 255;
+
+const FLAG_TEXT: u8 = 0b0000_0001;
+const FLAG_HEADER_CRC: u8 = 0b0000_0010;
+const FLAG_EXTRA: u8 = 0b0000_0100;
+const FLAG_NAME: u8 = 0b0000_1000;
+const FLAG_COMMENT: u8 = 0b0001_0000;
+const KNOWN_FLAGS: u8 = FLAG_TEXT | FLAG_HEADER_CRC | FLAG_EXTRA | FLAG_NAME | FLAG_COMMENT;
+
+/// CMF byte of a zlib stream: DEFLATE with a 32 KiB window.
+const ZLIB_CMF: u8 = (7 << 4) | METHOD_DEFLATE;
+/// The FLEVEL value for the default compression level.
+const ZLIB_DEFAULT_LEVEL: u8 = 2 << 6;
+const ZLIB_FLAG_DICTIONARY: u8 = 0b0010_0000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Gzip,
+    Zlib,
+}
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    pub format: Format,
+    pub deflate: deflate::Options,
+    /// Name of the original file, stored in a gzip header.
+    pub file_name: Option<String>,
+    /// Modification time of the original file in seconds since the Unix
+    /// epoch, stored in a gzip header; zero if unknown.
+    pub modification_time: u32,
+}
+
+/// Compresses `input` into a gzip member or a zlib stream.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    let mut writer = BufWriter::new(output);
+
+    match options.format {
+        Format::Gzip => {
+            write_gzip_header(&mut writer, options)?;
+
+            let mut input = Checked::new(input, Check::Crc32(Crc32::new()));
+            deflate::compress(&mut input, &mut writer, &options.deflate)?;
+
+            writer.write_all(&input.check.finish().to_le_bytes())?;
+            writer.write_all(&(input.len as u32).to_le_bytes())?;
+        }
+        Format::Zlib => {
+            let check = 31 - (u16::from_be_bytes([ZLIB_CMF, ZLIB_DEFAULT_LEVEL]) % 31) as u8;
+            writer.write_all(&[ZLIB_CMF, ZLIB_DEFAULT_LEVEL | check])?;
+
+            let mut input = Checked::new(input, Check::Adler32(Adler32::new()));
+            deflate::compress(&mut input, &mut writer, &options.deflate)?;
+
+            writer.write_all(&input.check.finish().to_be_bytes())?;
+        }
+    }
+
+    Ok(writer.flush()?)
+}
+
+/// Decompresses a zlib stream, or a gzip file of one or more members whose
+/// contents are concatenated.
+pub fn decompress<R, W>(input: R, output: W, format: Format) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    // Headers and trailers are read through the DEFLATE bit reader, which
+    // may have read ahead into them.
+    let mut reader = BitReader::new(BufReader::new(input));
+    let mut writer = BufWriter::new(output);
+
+    match format {
+        Format::Gzip => loop {
+            decompress_gzip_member(&mut reader, &mut writer)?;
+
+            if reader.is_at_end()? {
+                break;
+            }
+        },
+        Format::Zlib => {
+            decompress_zlib(&mut reader, &mut writer)?;
+
+            if !reader.is_at_end()? {
+                return Err(Error::CorruptData(
+                    "unexpected data after end of stream".into(),
+                ));
+            }
+        }
+    }
+
+    Ok(writer.flush()?)
+}
+
+fn write_gzip_header<W: Write>(writer: &mut W, options: &Options) -> io::Result<()> {
+    let mut flags = 0;
+
+    if let Some(name) = &options.file_name {
+        if name.contains('\0') {
+            return Err(Error::InvalidOptions("file name must not contain NUL".into()).into());
+        }
+
+        flags |= FLAG_NAME;
+    }
+
+    writer.write_all(&GZIP_MAGIC)?;
+    writer.write_all(&[METHOD_DEFLATE, flags])?;
+    writer.write_all(&options.modification_time.to_le_bytes())?;
+    writer.write_all(&[0, OS_UNKNOWN])?;
+
+    if let Some(name) = &options.file_name {
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(&[0])?;
+    }
+
+    Ok(())
+}
+
+fn decompress_gzip_member<R, W>(reader: &mut BitReader<R>, writer: &mut W) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    // The header is checksummed as it is read, for FLAG_HEADER_CRC.
+    let mut header = Checked::new(&mut *reader, Check::Crc32(Crc32::new()));
+
+    let mut fixed = [0; 10];
+    header.read_exact(&mut fixed).map_err(truncated)?;
+
+    if fixed[..2] != GZIP_MAGIC {
+        return Err(Error::BadMagic.into());
+    }
+
+    if fixed[2] != METHOD_DEFLATE {
+        return Err(
+            Error::Unsupported(format!("unsupported compression method {}", fixed[2])).into(),
+        );
+    }
+
+    let flags = fixed[3];
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(Error::Unsupported(format!("reserved gzip flags {flags:#04x}")).into());
+    }
+
+    if flags & FLAG_EXTRA != 0 {
+        let mut len = [0; 2];
+        header.read_exact(&mut len).map_err(truncated)?;
+
+        let len = u16::from_le_bytes(len) as u64;
+        let skipped = io::copy(&mut (&mut header).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+        }
+    }
+
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            skip_zero_terminated(&mut header)?;
+        }
+    }
+
+    if flags & FLAG_HEADER_CRC != 0 {
+        let expected = header.check.finish() as u16;
+
+        let mut crc = [0; 2];
+        reader.read_exact(&mut crc).map_err(truncated)?;
+
+        if u16::from_le_bytes(crc) != expected {
+            return Err(invalid_data("gzip header checksum mismatch"));
+        }
+    }
+
+    let mut output = Checked::new(&mut *writer, Check::Crc32(Crc32::new()));
+    deflate::inflate(reader, &mut output)?;
+
+    let mut trailer = [[0; 4]; 2];
+    reader
+        .read_exact(trailer.as_flattened_mut())
+        .map_err(truncated)?;
+
+    let [crc, len] = trailer.map(u32::from_le_bytes);
+
+    if crc != output.check.finish() {
+        return Err(Error::ChecksumMismatch.into());
+    }
+
+    if len != output.len as u32 {
+        return Err(invalid_data("stream length does not match the trailer"));
+    }
+
+    Ok(())
+}
+
+fn decompress_zlib<R, W>(reader: &mut BitReader<R>, writer: &mut W) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).map_err(truncated)?;
+    let [cmf, flags] = header;
+
+    if u16::from_be_bytes(header) % 31 != 0 {
+        return Err(invalid_data("zlib header check failed"));
+    }
+
+    if cmf & 0x0F != METHOD_DEFLATE {
+        return Err(
+            Error::Unsupported(format!("unsupported compression method {}", cmf & 0x0F)).into(),
+        );
+    }
+
+    if cmf >> 4 > 7 {
+        return Err(Error::Unsupported("zlib window is larger than 32 KiB".into()).into());
+    }
+
+    if flags & ZLIB_FLAG_DICTIONARY != 0 {
+        return Err(Error::Unsupported("preset dictionaries are not supported".into()).into());
+    }
+
+    let mut output = Checked::new(writer, Check::Adler32(Adler32::new()));
+    deflate::inflate(reader, &mut output)?;
+
+    let mut trailer = [0; 4];
+    reader.read_exact(&mut trailer).map_err(truncated)?;
+
+    if u32::from_be_bytes(trailer) != output.check.finish() {
+        return Err(Error::ChecksumMismatch.into());
+    }
+
+    Ok(())
+}
+
+fn skip_zero_terminated<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut byte = [0xFF];
+
+    while byte[0] != 0 {
+        reader.read_exact(&mut byte).map_err(truncated)?;
+    }
+
+    Ok(())
+}
+
+/// The checksum in a gzip or zlib trailer.
+enum Check {
+    Crc32(Crc32),
+    Adler32(Adler32),
+}
+
+impl Check {
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Check::Crc32(crc) => crc.update(bytes),
+            Check::Adler32(adler) => adler.update(bytes),
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        match self {
+            Check::Crc32(crc) => crc.finish(),
+            Check::Adler32(adler) => adler.finish(),
+        }
+    }
+}
+
+/// Passes reads or writes through, checksumming and counting the bytes.
+struct Checked<T> {
+    inner: T,
+    check: Check,
+    len: u64,
+}
+
+impl<T> Checked<T> {
+    fn new(inner: T, check: Check) -> Self {
+        Checked {
+            inner,
+            check,
+            len: 0,
+        }
+    }
+}
+
+impl<T: Read> Read for Checked<T> {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(bytes)?;
+        self.check.update(&bytes[..read]);
+        self.len += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for Checked<T> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+        self.check.update(&bytes[..written]);
+        self.len += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+fn truncated(error: io::Error) -> io::Error {
+    if error.kind() != io::ErrorKind::UnexpectedEof {
+        return error;
+    }
+
+    Error::Truncated.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{compress_with, decompress_with};
+
+    // Written by Python's gzip and zlib modules.
+
+    /// "hello" with no file name and a zero modification time.
+    const GZIP_HELLO: &str = "1f8b0800000000000203cb48cdc9c9070086a6103605000000";
+    /// "hello" named hello.txt.
+    const GZIP_HELLO_NAMED: &str =
+        "1f8b080800f1536502ff68656c6c6f2e74787400cb48cdc9c9070086a6103605000000";
+    /// "hello" with an extra field, a comment and a header checksum.
+    const GZIP_HELLO_ALL_FIELDS: &str = "1f8b08160000000000ff0400414200006120636f6d6d656e740089a3\
+        cb48cdc9c9070086a6103605000000";
+    const ZLIB_HELLO: &str = "789ccb48cdc9c90700062c0215";
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn pack(input: &[u8], options: &Options) -> Vec<u8> {
+        compress_with(input, |input, output| compress(input, output, options))
+    }
+
+    fn unpack(input: &[u8], format: Format) -> io::Result<Vec<u8>> {
+        decompress_with(input, |input, output| decompress(input, output, format))
+    }
+
+    fn zlib_options() -> Options {
+        Options {
+            format: Format::Zlib,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn decompresses_reference_streams() {
+        for gzip in [GZIP_HELLO, GZIP_HELLO_NAMED, GZIP_HELLO_ALL_FIELDS] {
+            assert_eq!(unpack(&from_hex(gzip), Format::Gzip).unwrap(), b"hello");
+        }
+
+        assert_eq!(
+            unpack(&from_hex(ZLIB_HELLO), Format::Zlib).unwrap(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn zlib_output_matches_reference() {
+        assert_eq!(pack(b"hello", &zlib_options()), from_hex(ZLIB_HELLO));
+    }
+
+    #[test]
+    fn gzip_header_records_name_and_time() {
+        let options = Options {
+            file_name: Some("hello.txt".to_string()),
+            modification_time: 1_700_000_000,
+            ..Options::default()
+        };
+
+        let compressed = pack(b"hello", &options);
+        let reference = from_hex(GZIP_HELLO_NAMED);
+
+        // Only the XFL byte differs from Python's header.
+        assert_eq!(compressed[..8], reference[..8]);
+        assert_eq!(compressed[9..], reference[9..]);
+    }
+
+    #[test]
+    fn round_trips_both_formats() {
+        let input: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| format!("{} ", i % 97).into_bytes())
+            .collect();
+
+        for options in [Options::default(), zlib_options()] {
+            let compressed = pack(&input, &options);
+
+            assert!(compressed.len() < input.len() / 4);
+            assert_eq!(unpack(&compressed, options.format).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn concatenated_members_decompress_to_concatenated_contents() {
+        let mut compressed = pack(b"first ", &Options::default());
+        compressed.extend(pack(b"", &Options::default()));
+        compressed.extend(from_hex(GZIP_HELLO));
+
+        assert_eq!(unpack(&compressed, Format::Gzip).unwrap(), b"first hello");
+    }
+
+    #[test]
+    fn rejects_trailing_garbage_after_member() {
+        let mut compressed = from_hex(GZIP_HELLO);
+        compressed.extend_from_slice(b"junk");
+
+        assert!(unpack(&compressed, Format::Gzip).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupted_trailers() {
+        let mut gzip = from_hex(GZIP_HELLO);
+        let crc = gzip.len() - 8;
+        gzip[crc] ^= 1;
+        assert!(unpack(&gzip, Format::Gzip).is_err());
+
+        let mut gzip = from_hex(GZIP_HELLO);
+        let len = gzip.len() - 4;
+        gzip[len] ^= 1;
+        assert!(unpack(&gzip, Format::Gzip).is_err());
+
+        let mut zlib = from_hex(ZLIB_HELLO);
+        let last = zlib.len() - 1;
+        zlib[last] ^= 1;
+        assert!(unpack(&zlib, Format::Zlib).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut header_crc = from_hex(GZIP_HELLO_ALL_FIELDS);
+        header_crc[27] ^= 1;
+        assert!(unpack(&header_crc, Format::Gzip).is_err());
+
+        let mut zlib = from_hex(ZLIB_HELLO);
+        zlib[1] ^= 1;
+        assert!(unpack(&zlib, Format::Zlib).is_err());
+
+        assert!(unpack(&from_hex(ZLIB_HELLO), Format::Gzip).is_err());
+    }
+
+    #[test]
+    fn truncated_member_is_unexpected_eof() {
+        let gzip = from_hex(GZIP_HELLO);
+
+        let error = unpack(&gzip[..gzip.len() - 2], Format::Gzip).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,99 +1,250 @@
-mod bit_writer;
-mod byte_map;
+pub(crate) mod bit_reader;
+pub(crate) mod bit_writer;
+pub(crate) mod block;
+pub(crate) mod byte_map;
+pub(crate) mod decode_table;
+mod decoder;
 mod encoder;
-mod frequency;
+pub(crate) mod frequency;
+mod package_merge;
+pub(crate) mod packed_table;
+mod reader;
 mod tree;
+mod writer;
 
-use crate::huffman::bit_writer::BitWriter;
-use frequency::Frequencies;
+pub use reader::HuffmanReader;
+pub use writer::HuffmanWriter;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{self, CodecId, Header};
+use crate::error::Error;
+use crate::filter::{self, Filter};
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::decoder::Decoder;
 use std::io::prelude::*;
-use std::io::{self, BufReader, BufWriter, SeekFrom};
+use std::io::{self, BufReader, BufWriter};
 
 const BYTE_ALPHABET_SIZE: usize = 256;
 
-pub fn compress<R, W>(input: R, output: W) -> io::Result<()>
+/// A symbol of a Huffman code: a byte, or a token from a larger alphabet.
+pub type Symbol = u16;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+pub const DEFAULT_MAX_CODE_LENGTH: u8 = 15;
+
+/// Longest code that fits in a `CodeEntry` bit pattern.
+const MAX_MAX_CODE_LENGTH: u8 = u32::BITS as u8;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and coded as one block.
+    pub block_size: usize,
+    /// Longest code, in bits, that a code table may assign to a byte.
+    pub max_code_length: u8,
+    /// Transforms applied in order to the input before its bytes are
+    /// counted and coded.
+    pub filters: Vec<Filter>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_code_length: DEFAULT_MAX_CODE_LENGTH,
+            filters: vec![],
+        }
+    }
+}
+
+/// Compresses `input` one block at a time, so it only needs to be read once.
+///
+/// Each block is written as its length (a varint) and a `BlockType`, followed
+/// by its own code table (a list of codes or a packed code-length vector,
+/// whichever is smaller) and data, data coded with the previous block's
+/// table, or the raw bytes. A zero length marks the end of the stream.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
 where
-    R: Read + Seek,
+    R: Read,
     W: Write,
 {
-    let mut reader = BufReader::new(input);
-    let mut writer = BitWriter::new(BufWriter::new(output));
+    let mut input = input;
+    let mut writer = HuffmanWriter::new(output, options.clone())?;
 
-    let frequencies = Frequencies::from_input(&mut reader)?;
+    io::copy(&mut input, &mut writer)?;
+    writer.finish()?.flush()
+}
 
-    if frequencies.is_empty() {
-        return Ok(());
+/// Upper bound on the size of the output of `compress` for an input of
+/// `input_len` bytes.
+///
+/// Any block that Huffman coding would expand is stored instead, so beyond
+/// the input itself the output only holds the header, two or more bytes of
+/// framing per block, the end marker and the checksum trailer. Filters
+/// add their chain to the header, and may lengthen the input they pass on.
+pub fn max_compressed_len(input_len: u64, options: &Options) -> u64 {
+    let input_len = filter::max_filtered_len(&options.filters, input_len);
+    let block_size = options.block_size.max(1) as u64;
+    let blocks = input_len.div_ceil(block_size);
+    let block_framing = container::varint_len(block_size) + 1;
+    let filters_len = match options.filters.is_empty() {
+        true => 0,
+        false => filter::filters_len(&options.filters),
+    };
+
+    container::HEADER_FIXED_LEN
+        + filters_len
+        + container::varint_len(input_len)
+        + input_len
+        + blocks * block_framing
+        + 1
+        + options.checksum.trailer_len() as u64
+}
+
+/// Rejects a zero block size, or a code length limit that is too long for a
+/// `CodeEntry` or too short to give each of `alphabet_size` symbols a code.
+pub(crate) fn check_options(
+    block_size: usize,
+    max_code_length: u8,
+    alphabet_size: usize,
+) -> io::Result<()> {
+    if block_size == 0 {
+        return Err(Error::InvalidOptions("block size must be at least one byte".into()).into());
     }
 
-    reader.seek(SeekFrom::Start(0))?;
+    let min_code_length = alphabet_size.next_power_of_two().trailing_zeros() as u8;
 
-    frequencies
-        .to_huff_tree()
-        .to_byte_map()
-        .encode(&mut reader, &mut writer)
+    if !(min_code_length..=MAX_MAX_CODE_LENGTH).contains(&max_code_length) {
+        return Err(Error::InvalidOptions(format!(
+            "max code length must be between {min_code_length} and {MAX_MAX_CODE_LENGTH} bits"
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_block<R: Read>(
+    reader: &mut R,
+    block: &mut Vec<u8>,
+    block_size: usize,
+) -> io::Result<()> {
+    block.clear();
+    reader.take(block_size as u64).read_to_end(block)?;
+
+    Ok(())
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::Huffman)?;
+
+    let mut writer = BufWriter::new(output);
+
+    Decoder::new(BitReader::new(reader), header).decode(&mut writer)?;
+
+    writer.flush()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::compress;
-    use std::io::Cursor;
+    use super::{DEFAULT_BLOCK_SIZE, Options, compress, max_compressed_len};
+    use crate::checksum::ChecksumKind;
+    use crate::container::{self, CodecId, Header};
+    use crate::decompress;
+    use crate::filter::Filter;
+    use crate::huffman::block::BlockType;
+    use crate::test_support::*;
+    use std::io::{self, Cursor, Read};
+
+    fn compress_with_checksum(input: &[u8], checksum: ChecksumKind) -> Vec<u8> {
+        let options = Options {
+            checksum,
+            ..Options::default()
+        };
+
+        compress_bytes(&options, input)
+    }
 
-    fn compress_bytes(input: &[u8]) -> Vec<u8> {
+    /// A stream holding `payload` as its only block.
+    fn single_block(original_length: u8, block_type: BlockType, payload: &[u8]) -> Vec<u8> {
         let mut output = Vec::new();
-        compress(Cursor::new(input), &mut output).expect("compression should succeed");
+        Header::new(
+            CodecId::Huffman,
+            ChecksumKind::None,
+            Some(original_length as u64),
+        )
+        .write_to(&mut output)
+        .unwrap();
+
+        if original_length > 0 {
+            output.push(original_length);
+            output.push(block_type as u8);
+            output.extend_from_slice(payload);
+        }
+
+        output.push(0); // end of stream
         output
     }
 
+    /// Splits a stream into its header, first block length and type, and the
+    /// rest.
+    fn split_first_block(output: &[u8]) -> (Header, u64, BlockType, &[u8]) {
+        let mut cursor = Cursor::new(output);
+        let header = Header::read_from(&mut cursor).expect("output should start with a header");
+        let block_length = container::read_varint(&mut cursor).expect("output should have a block");
+
+        let mut block_type = [0];
+        cursor.read_exact(&mut block_type).unwrap();
+        let block_type = BlockType::try_from(block_type[0]).unwrap();
+
+        (
+            header,
+            block_length,
+            block_type,
+            &output[cursor.position() as usize..],
+        )
+    }
+
     #[test]
     fn compress_empty_input() {
-        let output = compress_bytes(b"");
+        let output = compress_bytes(&Options::default(), b"");
 
-        assert!(output.is_empty());
+        assert_eq!(output, single_block(0, BlockType::Stored, &[]));
     }
 
     #[test]
     fn compress_single_byte_input() {
-        let output = compress_bytes(b"a");
+        let output = compress_bytes(&Options::default(), b"a");
 
-        let expected = vec![
-            0,
-            1, // number of codes
-            b'a',
-            1,           // code table entry
-            0b1000_0000, // encoded data (padded)
-        ];
+        // A code table costs more than the byte itself.
+        let expected = single_block(1, BlockType::Stored, b"a");
 
         assert_eq!(output, expected);
     }
 
     #[test]
     fn compress_single_repeated_symbol() {
-        let output = compress_bytes(b"aaaa");
+        let output = compress_bytes(&Options::default(), b"aaaa");
 
-        let expected = vec![
-            0,
-            1, // number of codes
-            b'a',
-            1,           // code table entry
-            0b1111_0000, // 4 bits of '1' padded
-        ];
+        let expected = single_block(4, BlockType::Stored, b"aaaa");
 
         assert_eq!(output, expected);
     }
 
     #[test]
     fn compress_two_symbols_equal_frequency() {
-        let output = compress_bytes(b"abab");
-
-        let expected = vec![
-            0,
-            2, // number of codes
-            b'a',
-            1,
-            b'b',
-            1,
-            0b0101_0000,
-        ];
+        let output = compress_bytes(&Options::default(), b"abab");
+
+        let expected = single_block(4, BlockType::Stored, b"abab");
 
         assert_eq!(output, expected);
     }
@@ -102,8 +253,8 @@ mod tests {
     fn compress_is_deterministic() {
         let input = b"the quick brown fox jumps over the lazy dog";
 
-        let out1 = compress_bytes(input);
-        let out2 = compress_bytes(input);
+        let out1 = compress_bytes(&Options::default(), input);
+        let out2 = compress_bytes(&Options::default(), input);
 
         assert_eq!(out1, out2, "compression must be deterministic");
     }
@@ -112,7 +263,11 @@ mod tests {
     fn compress_many_symbols_skewed_distribution() {
         let input = b"aaaaaaaaaabbbccd";
 
-        let output = compress_bytes(input);
+        let compressed = compress_bytes(&Options::default(), input);
+        let (header, block_length, block_type, output) = split_first_block(&compressed);
+        assert_eq!(header.original_length, Some(input.len() as u64));
+        assert_eq!(block_length, input.len() as u64);
+        assert_eq!(block_type, BlockType::Table);
 
         assert!(output.len() > 3, "output should contain header + data");
 
@@ -139,11 +294,386 @@ mod tests {
     #[test]
     fn compress_all_unique_bytes() {
         let input: Vec<u8> = (0u8..=255u8).collect();
-        let output = compress_bytes(&input);
+        let compressed = compress_bytes(&Options::default(), &input);
+        let (header, block_length, block_type, output) = split_first_block(&compressed);
+        assert_eq!(header.original_length, Some(256));
+        assert_eq!(block_length, 256);
+
+        // 256 codes of 8 bits would only add a table to the raw bytes.
+        assert_eq!(block_type, BlockType::Stored);
+        assert_eq!(output[..256], input[..]);
+    }
 
-        assert!(!output.is_empty(), "output should not be empty");
+    #[test]
+    fn round_trip_test_vectors() {
+        assert_round_trip(&Options::default(), b"");
+        assert_round_trip(&Options::default(), b"a");
+        assert_round_trip(&Options::default(), b"aaaa");
+        assert_round_trip(&Options::default(), b"abab");
+        assert_round_trip(
+            &Options::default(),
+            b"the quick brown fox jumps over the lazy dog",
+        );
+        assert_round_trip(&Options::default(), b"aaaaaaaaaabbbccd");
+        assert_round_trip(&Options::default(), &(0u8..=255u8).collect::<Vec<_>>());
+    }
 
-        let num_codes = u16::from_be_bytes([output[0], output[1]]) as usize;
-        assert_eq!(num_codes, 256, "all unique bytes should produce 256 codes");
+    #[test]
+    fn round_trip_trailing_zero_codes() {
+        // 'a' gets the all-zero code, so without the original length the
+        // flush padding would decode as extra 'a's.
+        assert_round_trip(&Options::default(), b"aaab");
+        assert_round_trip(&Options::default(), b"ba");
+    }
+
+    #[test]
+    fn round_trip_large_input() {
+        let input: Vec<u8> = (0..50_000u32).map(|i| (i * i % 251) as u8).collect();
+
+        assert_round_trip(&Options::default(), &input);
+    }
+
+    #[test]
+    fn decompress_rejects_truncated_stream() {
+        let compressed = compress_bytes(
+            &Options::default(),
+            b"the quick brown fox jumps over the lazy dog",
+        );
+
+        let mut output = Vec::new();
+        let result = decompress(
+            Cursor::new(&compressed[..compressed.len() / 2]),
+            &mut output,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decompress_rejects_invalid_code_count() {
+        let mut output = Vec::new();
+        let input = single_block(1, BlockType::Table, &[0x01, 0x01, b'a', 1, 0]);
+        let result = decompress(Cursor::new(input), &mut output);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decompress_rejects_foreign_data() {
+        let mut output = Vec::new();
+        let result = decompress(Cursor::new(b"\x1f\x8b\x08\x00not ours"), &mut output);
+
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn decompress_rejects_headerless_stream() {
+        let mut output = Vec::new();
+        let result = decompress(
+            Cursor::new([1, 1, 0, 1, b'a', 1, 0b1000_0000, 0]),
+            &mut output,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn round_trip_with_checksums() {
+        let input = b"the quick brown fox jumps over the lazy dog";
+
+        for checksum in [ChecksumKind::Crc32, ChecksumKind::XxHash64] {
+            let compressed = compress_with_checksum(input, checksum);
+            assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+
+            let compressed = compress_with_checksum(b"", checksum);
+            assert_eq!(decompress_bytes(&compressed).unwrap(), b"");
+        }
+    }
+
+    #[test]
+    fn checksum_is_stored_in_trailer() {
+        let plain = compress_bytes(&Options::default(), b"abab");
+        let checked = compress_with_checksum(b"abab", ChecksumKind::XxHash64);
+
+        assert_eq!(checked.len(), plain.len() + 8);
+    }
+
+    #[test]
+    fn decompress_detects_corrupted_data() {
+        // Both symbols get one-bit codes, so any flipped data bit still
+        // decodes cleanly and only the checksum can catch it.
+        let input = b"ab".repeat(1_000);
+        let mut compressed = compress_with_checksum(&input, ChecksumKind::Crc32);
+
+        let corrupt = compressed.len() - 100;
+        compressed[corrupt] ^= 0b0001_0000;
+
+        let mut output = Vec::new();
+        let error = decompress(Cursor::new(compressed), &mut output).unwrap_err();
+        assert_eq!(error.to_string(), "checksum mismatch");
+    }
+
+    #[test]
+    fn compress_non_seekable_input() {
+        let input = b"the quick brown fox jumps over the lazy dog".repeat(100);
+
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            ..Options::default()
+        };
+
+        let mut compressed = Vec::new();
+        compress(Pipe(&input), &mut compressed, &options).unwrap();
+
+        let mut output = Vec::new();
+        decompress(Pipe(&compressed), &mut output).unwrap();
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn compress_splits_input_into_blocks() {
+        // A text first block and a binary second block.
+        let mut input = b"lorem ipsum dolor sit amet ".repeat(DEFAULT_BLOCK_SIZE / 27 + 1);
+        input.truncate(DEFAULT_BLOCK_SIZE);
+        input.extend((0..DEFAULT_BLOCK_SIZE / 2).map(|i| (i * 31 % 256) as u8));
+
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        let mut compressed = Vec::new();
+        compress(Pipe(&input), &mut compressed, &options).unwrap();
+
+        let (header, block_length, block_type, _) = split_first_block(&compressed);
+        assert_eq!(header.original_length, None);
+        assert_eq!(block_length, DEFAULT_BLOCK_SIZE as u64);
+        assert_eq!(block_type, BlockType::PackedTable);
+
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_input_of_exactly_one_block() {
+        let input = vec![b'z'; DEFAULT_BLOCK_SIZE];
+
+        let compressed = compress_bytes(&Options::default(), &input);
+        let (header, _, _, _) = split_first_block(&compressed);
+
+        assert_eq!(header.original_length, Some(DEFAULT_BLOCK_SIZE as u64));
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_with_small_blocks() {
+        let input = b"the quick brown fox jumps over the lazy dog. ".repeat(200);
+        let options = Options {
+            block_size: 1000,
+            ..Options::default()
+        };
+
+        let compressed = compress_bytes(&options, &input);
+
+        // Later blocks share the first block's statistics, so most of them
+        // reuse its table and the output stays close to one big block's.
+        let single = compress_bytes(&Options::default(), &input);
+        assert!(compressed.len() < single.len() + 100);
+
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_rejects_zero_block_size() {
+        let options = Options {
+            block_size: 0,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let error = compress(Cursor::new(b"abc"), &mut output, &options).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    /// Input where byte `i` occurs `fib(i + 1)` times, the worst case for
+    /// code lengths.
+    fn fibonacci_input(symbols: u8) -> Vec<u8> {
+        let (mut a, mut b) = (1usize, 1usize);
+        let mut input = Vec::new();
+
+        for byte in 0..symbols {
+            input.extend(std::iter::repeat_n(byte, a));
+            (a, b) = (b, a + b);
+        }
+
+        input
+    }
+
+    #[test]
+    fn compress_limits_code_lengths_of_fibonacci_input() {
+        let input = fibonacci_input(25);
+        let options = Options {
+            block_size: input.len(),
+            ..Options::default()
+        };
+
+        let compressed = compress_bytes(&options, &input);
+        let (_, _, block_type, _) = split_first_block(&compressed);
+
+        assert_eq!(block_type, BlockType::PackedTable);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_with_short_max_code_length() {
+        let input = fibonacci_input(20);
+        let options = Options {
+            max_code_length: 8,
+            ..Options::default()
+        };
+
+        let compressed = compress_bytes(&options, &input);
+
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_rejects_unusable_max_code_length() {
+        for max_code_length in [0, 7, 33] {
+            let options = Options {
+                max_code_length,
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            let error = compress(Cursor::new(b"abc"), &mut output, &options).unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    /// Deterministic bytes that no Huffman code can shrink.
+    #[test]
+    fn compress_random_input_is_stored() {
+        let input = random_bytes(10_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+        let (_, _, block_type, payload) = split_first_block(&compressed);
+
+        assert_eq!(block_type, BlockType::Stored);
+        assert_eq!(payload[..input.len()], input[..]);
+        assert_eq!(compressed.len(), input.len() + 13);
+    }
+
+    #[test]
+    fn compress_never_exceeds_max_compressed_len() {
+        for len in [0, 1, 255, 4096, 3 * 1000 + 1] {
+            let input = random_bytes(len, 0);
+
+            for options in [
+                Options::default(),
+                Options {
+                    checksum: ChecksumKind::XxHash64,
+                    block_size: 1000,
+                    ..Options::default()
+                },
+                Options {
+                    filters: vec![Filter::Shuffle { width: 3 }, Filter::Rle],
+                    ..Options::default()
+                },
+            ] {
+                let compressed = compress_bytes(&options, &input);
+                let bound = max_compressed_len(len as u64, &options);
+
+                assert!(
+                    compressed.len() as u64 <= bound,
+                    "{len} bytes compressed to {}, over the bound of {bound}",
+                    compressed.len()
+                );
+                assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+            }
+        }
+    }
+
+    #[test]
+    fn rle_filter_is_recorded_and_undone() {
+        let options = Options {
+            filters: vec![Filter::Rle],
+            ..Options::default()
+        };
+        let input = b"aaaaaaaaaabcccccd".repeat(100);
+
+        let compressed = compress_bytes(&options, &input);
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.filters, [Filter::Rle]);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn rle_filter_codes_zero_runs_in_under_a_bit_per_byte() {
+        // A sparse file: a few bytes of data every 64 KiB.
+        let mut input = vec![0; 1 << 20];
+        for (i, chunk) in input.chunks_mut(64 * 1024).enumerate() {
+            chunk[..16].copy_from_slice(&random_bytes(16, i as u64));
+        }
+
+        let plain = compress_bytes(&Options::default(), &input);
+        let filtered = compress_bytes(
+            &Options {
+                filters: vec![Filter::Rle],
+                ..Options::default()
+            },
+            &input,
+        );
+
+        assert!(plain.len() > input.len() / 8);
+        assert!(filtered.len() < 1000);
+        assert_eq!(decompress_bytes(&filtered).unwrap(), input);
+    }
+
+    #[test]
+    fn delta_and_shuffle_filters_shrink_integer_arrays() {
+        // Little-endian readings that drift up and down by small steps.
+        let mut reading = 1_000_000u32;
+        let input: Vec<u8> = random_bytes(100_000, 0)
+            .iter()
+            .flat_map(|&noise| {
+                reading = reading.wrapping_add(noise as u32 % 16).wrapping_sub(7);
+                reading.to_le_bytes()
+            })
+            .collect();
+
+        let plain = compress_bytes(&Options::default(), &input);
+        let filtered = compress_bytes(
+            &Options {
+                filters: vec![Filter::Delta { stride: 4 }, Filter::Shuffle { width: 4 }],
+                ..Options::default()
+            },
+            &input,
+        );
+
+        assert!(filtered.len() < plain.len() * 2 / 3);
+        assert_eq!(decompress_bytes(&filtered).unwrap(), input);
+    }
+
+    #[test]
+    fn compress_rejects_zero_filter_stride() {
+        let options = Options {
+            filters: vec![Filter::Delta { stride: 0 }],
+            ..Options::default()
+        };
+
+        assert_rejects_options(&options);
+    }
+
+    #[test]
+    fn max_compressed_len_is_a_few_bytes_over_input() {
+        let options = Options::default();
+
+        assert_eq!(max_compressed_len(0, &options), 9);
+        assert_eq!(max_compressed_len(10_000, &options), 10_000 + 14);
     }
 }
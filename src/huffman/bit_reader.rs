@@ -0,0 +1,281 @@
+use std::io::{self, Read};
+
+const U8_BITS: u8 = u8::BITS as u8;
+const BUFFER_BITS: u8 = u64::BITS as u8;
+const MAX_READ_BITS: u8 = u32::BITS as u8;
+
+pub struct BitReader<T: Read> {
+    pub reader: T,
+    bit_buffer: u64,
+    bits_available: u8,
+    exhausted: bool,
+}
+
+impl<T: Read> BitReader<T> {
+    pub fn new(reader: T) -> Self {
+        BitReader {
+            reader,
+
+            bit_buffer: 0,
+            bits_available: 0,
+            exhausted: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    pub fn read_bits(&mut self, length: u8) -> io::Result<u32> {
+        let bits = self.peek_bits(length)?;
+        self.consume(length)?;
+
+        Ok(bits)
+    }
+
+    /// Returns the next `length` bits without consuming them. Near the end of
+    /// the stream any bits past the end are returned as zeros.
+    pub fn peek_bits(&mut self, length: u8) -> io::Result<u32> {
+        if length > MAX_READ_BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot peek more than 32 bits",
+            ));
+        }
+
+        if length == 0 {
+            return Ok(0);
+        }
+
+        if self.bits_available < length {
+            self.refill()?;
+        }
+
+        Ok((self.bit_buffer >> (BUFFER_BITS - length)) as u32)
+    }
+
+    /// Discards `length` bits that were previously peeked.
+    pub fn consume(&mut self, length: u8) -> io::Result<()> {
+        if self.bits_available < length {
+            self.refill()?;
+        }
+
+        if self.bits_available < length {
+            return Err(truncated());
+        }
+
+        self.bit_buffer = self.bit_buffer.checked_shl(length as u32).unwrap_or(0);
+        self.bits_available -= length;
+
+        Ok(())
+    }
+
+    /// Skips the rest of the current byte, returning the number of bits
+    /// that were discarded.
+    pub fn align_to_byte(&mut self) -> u8 {
+        let padding = self.bits_available % U8_BITS;
+
+        self.bit_buffer <<= padding;
+        self.bits_available -= padding;
+
+        padding
+    }
+
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        self.read_exact(bytes).map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => truncated(),
+            _ => error,
+        })
+    }
+
+    /// Returns the exact number of unread bits once the underlying reader is
+    /// exhausted, or `None` while more input may follow. The count is always
+    /// known once fewer than 57 bits remain, which lets decoders tell the
+    /// padding at the end of a stream apart from a truncated one.
+    pub fn remaining_bits(&mut self) -> io::Result<Option<u8>> {
+        self.refill()?;
+
+        Ok(self.exhausted.then_some(self.bits_available))
+    }
+
+    fn refill(&mut self) -> io::Result<()> {
+        while !self.exhausted && self.bits_available <= BUFFER_BITS - U8_BITS {
+            let wanted = ((BUFFER_BITS - self.bits_available) / U8_BITS) as usize;
+            let mut bytes = [0; (BUFFER_BITS / U8_BITS) as usize];
+
+            let read = match self.reader.read(&mut bytes[..wanted]) {
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+
+            if read == 0 {
+                self.exhausted = true;
+            }
+
+            for &byte in &bytes[..read] {
+                let shift = BUFFER_BITS - U8_BITS - self.bits_available;
+                self.bit_buffer |= (byte as u64) << shift;
+                self.bits_available += U8_BITS;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reading bytes skips to the next byte boundary first, like `read_bytes`.
+impl<T: Read> Read for BitReader<T> {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        self.align_to_byte();
+
+        if self.bits_available == 0 {
+            return self.reader.read(bytes);
+        }
+
+        let mut filled = 0;
+        while filled < bytes.len() && self.bits_available > 0 {
+            bytes[filled] = (self.bit_buffer >> (BUFFER_BITS - U8_BITS)) as u8;
+            self.bit_buffer <<= U8_BITS;
+            self.bits_available -= U8_BITS;
+            filled += 1;
+        }
+
+        Ok(filled)
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "bit stream is truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::huffman::bit_writer::BitWriter;
+
+    #[test]
+    fn read_single_bit() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![128]));
+
+        assert!(reader.read_bit().unwrap());
+        assert!(!reader.read_bit().unwrap());
+    }
+
+    #[test]
+    fn read_bits_across_bytes() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![1, 0]));
+
+        assert_eq!(reader.read_bits(16).unwrap(), 256);
+    }
+
+    #[test]
+    fn read_bits_across_refills() {
+        let bytes: Vec<u8> = (0..32).collect();
+        let mut reader = BitReader::new(io::Cursor::new(bytes.clone()));
+
+        for chunk in bytes.chunks(4) {
+            let expected = u32::from_be_bytes(chunk.try_into().unwrap());
+            assert_eq!(reader.read_bits(32).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0b1010_0000]));
+
+        assert_eq!(reader.peek_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(5).unwrap(), 0);
+    }
+
+    #[test]
+    fn peek_past_end_pads_with_zeros() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0xFF]));
+
+        assert_eq!(reader.peek_bits(12).unwrap(), 0xFF0);
+    }
+
+    #[test]
+    fn read_past_end_is_truncation() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0xFF]));
+        reader.read_bits(6).unwrap();
+
+        let error = reader.read_bits(3).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn align_to_byte_skips_padding() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0b1100_0000, 42]));
+        reader.read_bits(2).unwrap();
+
+        assert_eq!(reader.align_to_byte(), 6);
+        assert_eq!(reader.align_to_byte(), 0);
+        assert_eq!(reader.read_bits(8).unwrap(), 42);
+    }
+
+    #[test]
+    fn read_bytes_after_bits() {
+        let bytes: Vec<u8> = (0..20).collect();
+        let mut reader = BitReader::new(io::Cursor::new(bytes));
+        reader.read_bits(4).unwrap();
+
+        let mut buffer = [0; 19];
+        reader.read_bytes(&mut buffer).unwrap();
+
+        let expected: Vec<u8> = (1..20).collect();
+        assert_eq!(buffer.to_vec(), expected);
+        assert_eq!(reader.remaining_bits().unwrap(), Some(0));
+    }
+
+    #[test]
+    fn peeking_more_than_32_bits_is_an_error() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0; 8]));
+
+        let error = reader.peek_bits(33).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(reader.read_bits(32).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_bytes_past_end_is_truncation() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![1, 2]));
+
+        let mut buffer = [0; 3];
+        let error = reader.read_bytes(&mut buffer).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn remaining_bits_known_only_at_end() {
+        let mut reader = BitReader::new(io::Cursor::new(vec![0; 16]));
+        assert_eq!(reader.remaining_bits().unwrap(), None);
+
+        reader.read_bits(32).unwrap();
+        reader.read_bits(32).unwrap();
+        reader.read_bits(3).unwrap();
+        assert_eq!(reader.remaining_bits().unwrap(), None);
+
+        reader.read_bits(8).unwrap();
+        assert_eq!(reader.remaining_bits().unwrap(), Some(53));
+    }
+
+    #[test]
+    fn reads_back_bit_writer_output() {
+        let mut writer = BitWriter::new(io::Cursor::new(vec![]));
+        writer.write_bits(0b101, 3).unwrap();
+        writer.write_bits(0xABCDE, 20).unwrap();
+        writer.write_bit(true).unwrap();
+        writer.flush().unwrap();
+
+        let mut reader = BitReader::new(io::Cursor::new(writer.writer.into_inner()));
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(20).unwrap(), 0xABCDE);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(reader.remaining_bits().unwrap(), Some(0));
+    }
+}
@@ -61,6 +61,8 @@ impl<T: Write> BitWriter<T> {
         if self.bits_filled > 0 {
             self.byte_buffer <<= U8_BITS - self.bits_filled;
             self.writer.write_all(&[self.byte_buffer])?;
+            self.byte_buffer = 0;
+            self.bits_filled = 0;
         }
 
         Ok(())
@@ -107,6 +109,16 @@ mod tests {
         assert_eq!(writer.writer.into_inner(), expected);
     }
 
+    #[test]
+    fn flush_twice_writes_partial_byte_once() {
+        let mut writer = BitWriter::new(io::Cursor::new(vec![]));
+        writer.write_bits(0b101, 3).unwrap();
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(writer.writer.into_inner(), vec![0b1010_0000]);
+    }
+
     #[test]
     fn write_bits_overflow_byte() {
         let mut writer = BitWriter::new(io::Cursor::new(vec![]));
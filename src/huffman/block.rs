@@ -0,0 +1,259 @@
+use crate::checksum::Checksum;
+use crate::container;
+use crate::error::Error;
+use crate::huffman::BYTE_ALPHABET_SIZE;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::byte_map::ByteMap;
+use crate::huffman::frequency::Frequencies;
+use crate::huffman::packed_table::PackedTable;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+
+/// How the data of a block is represented, written after its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    /// The block's bytes, copied verbatim.
+    Stored = 0,
+    /// A new code table followed by the coded data.
+    Table = 1,
+    /// Coded data using the table of the most recent `Table` or
+    /// `PackedTable` block.
+    Reuse = 2,
+    /// A new code table in the compact form written by `PackedTable`,
+    /// followed by the coded data.
+    PackedTable = 3,
+}
+
+impl TryFrom<u8> for BlockType {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(BlockType::Stored),
+            1 => Ok(BlockType::Table),
+            2 => Ok(BlockType::Reuse),
+            3 => Ok(BlockType::PackedTable),
+            _ => Err(Error::CorruptData(format!("unknown block type {value}")).into()),
+        }
+    }
+}
+
+/// Writes blocks, picking whichever representation is smallest for each.
+pub struct BlockEncoder<W>
+where
+    W: Write,
+{
+    writer: BitWriter<BufWriter<W>>,
+    checksum: Checksum,
+    max_code_length: u8,
+    previous: Option<ByteMap>,
+}
+
+impl<W> BlockEncoder<W>
+where
+    W: Write,
+{
+    pub fn new(writer: BitWriter<BufWriter<W>>, checksum: Checksum, max_code_length: u8) -> Self {
+        BlockEncoder {
+            writer,
+            checksum,
+            max_code_length,
+            previous: None,
+        }
+    }
+
+    pub fn encode(&mut self, block: &[u8]) -> io::Result<()> {
+        let frequencies = Frequencies::from_input(&mut &block[..])?;
+        let byte_map = frequencies.to_huff_tree().to_byte_map(self.max_code_length);
+
+        let data_bits = byte_map.encoded_bits(&frequencies);
+        let packed_table = PackedTable::new(&byte_map, BYTE_ALPHABET_SIZE);
+
+        let table_len = data_bits.map(|bits| byte_map.table_len() + bits_to_bytes(bits));
+        let packed_len = data_bits.map(|bits| bits_to_bytes(packed_table.bit_len() + bits));
+        let reuse_len = self
+            .previous
+            .as_ref()
+            .and_then(|previous| previous.encoded_bits(&frequencies))
+            .map(bits_to_bytes);
+        let stored_len = Some(block.len() as u64);
+
+        // On a tie the earlier entry wins, favouring the smaller header.
+        let block_type = [
+            (BlockType::Reuse, reuse_len),
+            (BlockType::Table, table_len),
+            (BlockType::PackedTable, packed_len),
+            (BlockType::Stored, stored_len),
+        ]
+        .into_iter()
+        .filter_map(|(block_type, len)| len.map(|len| (block_type, len)))
+        .min_by_key(|&(_, len)| len)
+        .map_or(BlockType::Stored, |(block_type, _)| block_type);
+
+        write_block_length(&mut self.writer, block.len() as u64)?;
+        self.writer.write_bytes(&[block_type as u8])?;
+
+        match block_type {
+            BlockType::Stored => {
+                self.checksum.update(block);
+                self.writer.write_bytes(block)
+            }
+            BlockType::Table => {
+                byte_map.encode(&mut &block[..], &mut self.writer, &mut self.checksum)?;
+                self.previous = Some(byte_map);
+                Ok(())
+            }
+            BlockType::PackedTable => {
+                packed_table.write_to(&mut self.writer)?;
+                byte_map.encode_data(&mut &block[..], &mut self.writer, &mut self.checksum)?;
+                self.previous = Some(byte_map);
+                Ok(())
+            }
+            BlockType::Reuse => self
+                .previous
+                .as_ref()
+                .ok_or_else(|| Error::CorruptTable("no previous table to reuse".into()))?
+                .encode_data(&mut &block[..], &mut self.writer, &mut self.checksum),
+        }
+    }
+
+    /// Flushes what has been written so far, so that all the blocks coded
+    /// can be decoded.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.writer.flush()
+    }
+
+    /// Writes the zero length that marks the end of the blocks, then the
+    /// checksum trailer, and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        write_block_length(&mut self.writer, 0)?;
+        self.writer.write_bytes(&self.checksum.to_trailer())?;
+
+        self.writer
+            .writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+    }
+}
+
+pub fn bits_to_bytes(bits: u64) -> u64 {
+    bits.div_ceil(u8::BITS as u64)
+}
+
+pub fn write_block_length<W: Write>(writer: &mut BitWriter<W>, length: u64) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    container::write_varint(&mut bytes, length)?;
+
+    writer.write_bytes(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::ChecksumKind;
+    use crate::huffman::DEFAULT_MAX_CODE_LENGTH;
+    use std::io::Cursor;
+
+    fn encode_blocks(blocks: &[&[u8]]) -> Vec<u8> {
+        let writer = BitWriter::new(BufWriter::new(Cursor::new(vec![])));
+        let checksum = ChecksumKind::None.new_checksum();
+
+        let mut encoder = BlockEncoder::new(writer, checksum, DEFAULT_MAX_CODE_LENGTH);
+        for block in blocks {
+            encoder.encode(block).unwrap();
+        }
+
+        encoder.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn small_block_is_stored() {
+        let output = encode_blocks(&[b"abab"]);
+
+        assert_eq!(
+            output,
+            [4, BlockType::Stored as u8, b'a', b'b', b'a', b'b', 0]
+        );
+    }
+
+    #[test]
+    fn skewed_block_gets_a_table() {
+        let mut input = vec![b'a'; 15];
+        input.push(b'b');
+
+        let output = encode_blocks(&[&input]);
+
+        let expected = [
+            16,
+            BlockType::Table as u8,
+            0,
+            2, // number of codes
+            b'a',
+            1,
+            b'b',
+            1,
+            0b0000_0000,
+            0b0000_0001,
+            0, // end of stream
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn similar_block_reuses_previous_table() {
+        let mut first = vec![b'a'; 15];
+        first.push(b'b');
+        let mut second = vec![b'b'; 15];
+        second.push(b'a');
+
+        let output = encode_blocks(&[&first, &second]);
+
+        assert_eq!(output[1], BlockType::Table as u8);
+        assert_eq!(
+            output[10..],
+            [16, BlockType::Reuse as u8, 0b1111_1111, 0b1111_1110, 0]
+        );
+    }
+
+    #[test]
+    fn reuse_needs_every_symbol_in_previous_table() {
+        let mut first = vec![b'a'; 15];
+        first.push(b'b');
+        let mut second = vec![b'a'; 15];
+        second.push(b'c');
+
+        let output = encode_blocks(&[&first, &second]);
+
+        assert_eq!(output[11], BlockType::Table as u8);
+    }
+
+    #[test]
+    fn block_with_many_codes_gets_a_packed_table() {
+        let input: Vec<u8> = (0..40u8)
+            .flat_map(|byte| [byte; 2])
+            .chain([0; 80])
+            .collect();
+
+        let output = encode_blocks(&[&input]);
+
+        assert_eq!(output[..2], [160, 1]);
+        assert_eq!(output[2], BlockType::PackedTable as u8);
+    }
+
+    #[test]
+    fn random_block_is_stored() {
+        let input: Vec<u8> = (0u8..=255u8).collect();
+
+        let output = encode_blocks(&[&input]);
+
+        assert_eq!(output[..3], [0x80, 0x02, BlockType::Stored as u8]);
+        assert_eq!(output[3..259], input[..]);
+    }
+
+    #[test]
+    fn block_type_from_byte() {
+        assert_eq!(BlockType::try_from(2).unwrap(), BlockType::Reuse);
+        assert_eq!(BlockType::try_from(3).unwrap(), BlockType::PackedTable);
+        assert!(BlockType::try_from(4).is_err());
+    }
+}
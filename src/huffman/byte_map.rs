@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 use std::io::prelude::*;
-use std::io::{self, BufReader, BufWriter};
+use std::io::{self, BufWriter};
 use std::ops;
 
+use crate::checksum::Checksum;
+use crate::huffman::Symbol;
 use crate::huffman::bit_writer::BitWriter;
 use crate::huffman::encoder::Encoder;
+use crate::huffman::frequency::Frequencies;
 
 pub struct CodeLength {
-    pub byte: u8,
+    pub symbol: Symbol,
     pub len: u8,
 }
 
@@ -18,18 +21,17 @@ pub struct CodeEntry {
 }
 
 #[derive(Debug)]
-pub struct ByteMap(HashMap<u8, CodeEntry>);
+pub struct ByteMap(HashMap<Symbol, CodeEntry>);
 
 impl ByteMap {
     pub fn new(code_lengths: &mut [CodeLength]) -> Self {
-        code_lengths.sort_by(|a, b| a.len.cmp(&b.len).then(a.byte.cmp(&b.byte)));
+        code_lengths.sort_by(|a, b| a.len.cmp(&b.len).then(a.symbol.cmp(&b.symbol)));
 
         let mut byte_map = ByteMap(HashMap::new());
 
-        if code_lengths.len() == 1 {
-            let code_len = code_lengths.first().unwrap();
+        if let [code_len] = code_lengths {
             byte_map.insert(
-                code_len.byte,
+                code_len.symbol,
                 CodeEntry {
                     bit_pattern: 1,
                     len: 1,
@@ -39,16 +41,19 @@ impl ByteMap {
             return byte_map;
         }
 
-        let mut bit_pattern = 0;
+        let mut bit_pattern: u32 = 0;
         let mut prev_len = 0;
 
         for code in code_lengths {
             if code.len > prev_len {
-                bit_pattern <<= code.len - prev_len
+                // A first code of 32 bits shifts the (zero) pattern by 32.
+                bit_pattern = bit_pattern
+                    .checked_shl((code.len - prev_len) as u32)
+                    .unwrap_or(0);
             }
 
             byte_map.insert(
-                code.byte,
+                code.symbol,
                 CodeEntry {
                     bit_pattern,
                     len: code.len,
@@ -66,20 +71,56 @@ impl ByteMap {
 impl ByteMap {
     pub fn encode<R, W>(
         &self,
-        reader: &mut BufReader<R>,
+        reader: &mut R,
         output: &mut BitWriter<BufWriter<W>>,
+        checksum: &mut Checksum,
     ) -> io::Result<()>
     where
-        R: Read + Seek,
+        R: BufRead,
         W: Write,
     {
-        let encoder = Encoder::new(reader, output, self);
+        let encoder = Encoder::new(reader, output, self, checksum);
         encoder.encode()
     }
+
+    /// Like `encode`, but without the code table, for a decoder that already
+    /// has it.
+    pub fn encode_data<R, W>(
+        &self,
+        reader: &mut R,
+        output: &mut BitWriter<BufWriter<W>>,
+        checksum: &mut Checksum,
+    ) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let encoder = Encoder::new(reader, output, self, checksum);
+        encoder.encode_data()
+    }
+
+    /// Size in bytes of the code table `encode` writes.
+    pub fn table_len(&self) -> u64 {
+        2 + 2 * self.len() as u64
+    }
+
+    /// Size in bits of the data `encode` writes for input with the given
+    /// frequencies, or `None` if some byte has no code in this map.
+    pub fn encoded_bits(&self, frequencies: &Frequencies) -> Option<u64> {
+        let mut bits = 0;
+
+        for (symbol, &count) in frequencies.iter().enumerate() {
+            if count > 0 {
+                bits += count * self.get(&(symbol as Symbol))?.len as u64;
+            }
+        }
+
+        Some(bits)
+    }
 }
 
 impl ops::Deref for ByteMap {
-    type Target = HashMap<u8, CodeEntry>;
+    type Target = HashMap<Symbol, CodeEntry>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -98,11 +139,14 @@ mod tests {
 
     #[test]
     fn single_code_length_produces_len_1_code() {
-        let mut codes = vec![CodeLength { byte: b'a', len: 5 }];
+        let mut codes = vec![CodeLength {
+            symbol: b'a'.into(),
+            len: 5,
+        }];
 
         let map = ByteMap::new(&mut codes);
 
-        let entry = map.get(&b'a').expect("entry should exist");
+        let entry = map.get(&Symbol::from(b'a')).expect("entry should exist");
         assert_eq!(entry.len, 1);
         assert_eq!(entry.bit_pattern, 1);
     }
@@ -110,52 +154,106 @@ mod tests {
     #[test]
     fn canonical_codes_with_same_length_are_sequential() {
         let mut codes = vec![
-            CodeLength { byte: b'a', len: 2 },
-            CodeLength { byte: b'b', len: 2 },
-            CodeLength { byte: b'c', len: 2 },
+            CodeLength {
+                symbol: b'a'.into(),
+                len: 2,
+            },
+            CodeLength {
+                symbol: b'b'.into(),
+                len: 2,
+            },
+            CodeLength {
+                symbol: b'c'.into(),
+                len: 2,
+            },
         ];
 
         let map = ByteMap::new(&mut codes);
 
-        assert_eq!(map[&b'a'].bit_pattern, 0b00);
-        assert_eq!(map[&b'b'].bit_pattern, 0b01);
-        assert_eq!(map[&b'c'].bit_pattern, 0b10);
+        assert_eq!(map[&Symbol::from(b'a')].bit_pattern, 0b00);
+        assert_eq!(map[&Symbol::from(b'b')].bit_pattern, 0b01);
+        assert_eq!(map[&Symbol::from(b'c')].bit_pattern, 0b10);
 
-        assert_eq!(map[&b'a'].len, 2);
-        assert_eq!(map[&b'b'].len, 2);
-        assert_eq!(map[&b'c'].len, 2);
+        assert_eq!(map[&Symbol::from(b'a')].len, 2);
+        assert_eq!(map[&Symbol::from(b'b')].len, 2);
+        assert_eq!(map[&Symbol::from(b'c')].len, 2);
     }
 
     #[test]
     fn codes_are_sorted_by_length_then_byte() {
         let mut codes = vec![
-            CodeLength { byte: b'c', len: 3 },
-            CodeLength { byte: b'a', len: 2 },
-            CodeLength { byte: b'b', len: 2 },
+            CodeLength {
+                symbol: b'c'.into(),
+                len: 3,
+            },
+            CodeLength {
+                symbol: b'a'.into(),
+                len: 2,
+            },
+            CodeLength {
+                symbol: b'b'.into(),
+                len: 2,
+            },
         ];
 
         let map = ByteMap::new(&mut codes);
 
-        assert_eq!(map[&b'a'].bit_pattern, 0b00);
-        assert_eq!(map[&b'b'].bit_pattern, 0b01);
+        assert_eq!(map[&Symbol::from(b'a')].bit_pattern, 0b00);
+        assert_eq!(map[&Symbol::from(b'b')].bit_pattern, 0b01);
 
-        assert_eq!(map[&b'c'].bit_pattern, 0b100);
-        assert_eq!(map[&b'c'].len, 3);
+        assert_eq!(map[&Symbol::from(b'c')].bit_pattern, 0b100);
+        assert_eq!(map[&Symbol::from(b'c')].len, 3);
     }
 
     #[test]
     fn bit_pattern_shifts_when_length_increases() {
         let mut codes = vec![
-            CodeLength { byte: b'a', len: 1 },
-            CodeLength { byte: b'b', len: 3 },
+            CodeLength {
+                symbol: b'a'.into(),
+                len: 1,
+            },
+            CodeLength {
+                symbol: b'b'.into(),
+                len: 3,
+            },
         ];
 
         let map = ByteMap::new(&mut codes);
 
-        assert_eq!(map[&b'a'].bit_pattern, 0b0);
-        assert_eq!(map[&b'a'].len, 1);
+        assert_eq!(map[&Symbol::from(b'a')].bit_pattern, 0b0);
+        assert_eq!(map[&Symbol::from(b'a')].len, 1);
+
+        assert_eq!(map[&Symbol::from(b'b')].bit_pattern, 0b100);
+        assert_eq!(map[&Symbol::from(b'b')].len, 3);
+    }
+
+    #[test]
+    fn encoded_bits_sums_code_lengths() {
+        let mut codes = vec![
+            CodeLength {
+                symbol: b'a'.into(),
+                len: 1,
+            },
+            CodeLength {
+                symbol: b'b'.into(),
+                len: 2,
+            },
+            CodeLength {
+                symbol: b'c'.into(),
+                len: 2,
+            },
+        ];
+        let map = ByteMap::new(&mut codes);
+
+        let mut frequencies = Frequencies::new();
+        frequencies[b'a' as usize] = 4;
+        frequencies[b'b' as usize] = 1;
+        frequencies[b'c' as usize] = 2;
+
+        assert_eq!(map.table_len(), 8);
+        assert_eq!(map.encoded_bits(&frequencies), Some(10));
 
-        assert_eq!(map[&b'b'].bit_pattern, 0b100);
-        assert_eq!(map[&b'b'].len, 3);
+        frequencies[b'd' as usize] = 1;
+        assert_eq!(map.encoded_bits(&frequencies), None);
     }
 }
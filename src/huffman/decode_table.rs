@@ -0,0 +1,387 @@
+use crate::error::Error;
+use crate::huffman::Symbol;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::byte_map::{ByteMap, CodeLength};
+use std::io::{self, Read};
+
+/// Most bits looked up at once in one table. Longer codes continue in
+/// secondary tables, sized for the longest code sharing their prefix up to
+/// the same limit.
+const TABLE_BITS: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    /// No code starts with these bits.
+    Empty,
+    /// A complete code of `len` bits for `symbol`.
+    Symbol { symbol: Symbol, len: u8 },
+    /// The code continues in the `bits`-bit secondary table at `offset`.
+    Subtable { offset: u32, bits: u8 },
+}
+
+/// A reader of codes sent most significant bit first, which `DecodeTable`
+/// looks ahead in.
+pub trait CodeReader {
+    /// Returns the next `length` bits without consuming them, the first
+    /// one most significant. Bits past the end of the stream are zeros.
+    fn peek_bits(&mut self, length: u8) -> io::Result<u32>;
+
+    /// Discards `length` bits that were previously peeked.
+    fn consume(&mut self, length: u8) -> io::Result<()>;
+}
+
+impl<R: Read> CodeReader for BitReader<R> {
+    fn peek_bits(&mut self, length: u8) -> io::Result<u32> {
+        BitReader::peek_bits(self, length)
+    }
+
+    fn consume(&mut self, length: u8) -> io::Result<()> {
+        BitReader::consume(self, length)
+    }
+}
+
+/// Decodes canonical Huffman codes by table lookup instead of bit by bit.
+///
+/// The next `primary_bits` bits of the stream index the primary table, which
+/// directly holds every code of at most that length; longer codes are found
+/// through one or more secondary tables. Since the `BitReader`
+/// keeps up to 64 bits buffered, several symbols are decoded per refill.
+pub struct DecodeTable {
+    entries: Vec<Entry>,
+    primary_bits: u8,
+}
+
+impl DecodeTable {
+    /// Builds the table for the canonical codes of `code_lengths`, rejecting
+    /// lengths that do not form a prefix code.
+    pub fn new(code_lengths: &mut [CodeLength]) -> io::Result<Self> {
+        if let Some(code) = code_lengths
+            .iter()
+            .find(|code| !(1..=u32::BITS as u8).contains(&code.len))
+        {
+            return Err(Error::CorruptTable(format!(
+                "code length {} is not between 1 and {}",
+                code.len,
+                u32::BITS
+            ))
+            .into());
+        }
+
+        let code_space: u64 = code_lengths
+            .iter()
+            .map(|code| 1u64 << (u32::BITS - code.len as u32))
+            .sum();
+
+        if code_space > 1 << u32::BITS {
+            return Err(
+                Error::CorruptTable("code lengths oversubscribe the code space".into()).into(),
+            );
+        }
+
+        let codes: Vec<(Symbol, u32, u8)> = ByteMap::new(code_lengths)
+            .iter()
+            .map(|(&symbol, code)| (symbol, code.bit_pattern, code.len))
+            .collect();
+
+        Ok(DecodeTable::from_codes(&codes))
+    }
+
+    /// Builds the table for `codes`, given as `(symbol, bit_pattern, len)`,
+    /// which must form a prefix code.
+    pub fn from_codes(codes: &[(Symbol, u32, u8)]) -> Self {
+        let primary_bits = table_bits(codes, 0);
+        let mut table = DecodeTable {
+            entries: vec![],
+            primary_bits,
+        };
+
+        table.add_table(codes, 0, primary_bits);
+        table
+    }
+
+    /// Appends a table indexed by the `bits` bits after the first `depth`,
+    /// which all of `codes` share, and returns its offset.
+    fn add_table(&mut self, codes: &[(Symbol, u32, u8)], depth: u8, bits: u8) -> usize {
+        let offset = self.entries.len();
+        self.entries
+            .extend(std::iter::repeat_n(Entry::Empty, 1 << bits));
+
+        let index_of = |bit_pattern: u32, len: u8| {
+            let below = len - depth - bits;
+            ((bit_pattern >> below) as usize) & ((1 << bits) - 1)
+        };
+
+        let mut long_codes: Vec<(usize, (Symbol, u32, u8))> = vec![];
+
+        for &(symbol, bit_pattern, len) in codes {
+            if len > depth + bits {
+                long_codes.push((index_of(bit_pattern, len), (symbol, bit_pattern, len)));
+                continue;
+            }
+
+            let shift = depth + bits - len;
+            let suffix = (bit_pattern as usize) & ((1 << (len - depth)) - 1);
+            let start = offset + (suffix << shift);
+            self.entries[start..start + (1 << shift)].fill(Entry::Symbol { symbol, len });
+        }
+
+        long_codes.sort_by_key(|&(index, _)| index);
+
+        for group in long_codes.chunk_by(|(a, _), (b, _)| a == b) {
+            let index = group[0].0;
+            let group: Vec<_> = group.iter().map(|&(_, code)| code).collect();
+
+            let sub_bits = table_bits(&group, depth + bits);
+            let sub_offset = self.add_table(&group, depth + bits, sub_bits);
+
+            self.entries[offset + index] = Entry::Subtable {
+                offset: sub_offset as u32,
+                bits: sub_bits,
+            };
+        }
+
+        offset
+    }
+
+    pub fn decode<R: CodeReader>(&self, reader: &mut R) -> io::Result<Symbol> {
+        let (mut offset, mut depth, mut bits) = (0, 0, self.primary_bits);
+
+        loop {
+            let code = reader.peek_bits(depth + bits)? as usize;
+
+            match self.entries[offset + (code & ((1 << bits) - 1))] {
+                Entry::Symbol { symbol, len } => {
+                    reader.consume(len)?;
+                    return Ok(symbol);
+                }
+                Entry::Subtable {
+                    offset: sub_offset,
+                    bits: sub_bits,
+                } => {
+                    offset = sub_offset as usize;
+                    depth += bits;
+                    bits = sub_bits;
+                }
+                Entry::Empty => {
+                    return Err(
+                        Error::CorruptData("bit stream contains an unknown code".into()).into(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Bits indexed by a table for `codes` after their first `depth` bits.
+fn table_bits(codes: &[(Symbol, u32, u8)], depth: u8) -> u8 {
+    let longest = codes.iter().map(|&(_, _, len)| len).max().unwrap_or(1);
+
+    (longest - depth).min(TABLE_BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::bit_writer::BitWriter;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::time::Instant;
+
+    /// The bit-by-bit decoder the table replaces, kept as a reference.
+    struct NaiveTable(HashMap<(u32, u8), Symbol>);
+
+    impl NaiveTable {
+        fn new(byte_map: &ByteMap) -> Self {
+            NaiveTable(
+                byte_map
+                    .iter()
+                    .map(|(&symbol, code)| ((code.bit_pattern, code.len), symbol))
+                    .collect(),
+            )
+        }
+
+        fn decode<R: Read>(&self, reader: &mut BitReader<R>) -> io::Result<Symbol> {
+            let mut bit_pattern = 0;
+
+            for len in 1..=u32::BITS as u8 {
+                bit_pattern = (bit_pattern << 1) | reader.read_bit()? as u32;
+
+                if let Some(&symbol) = self.0.get(&(bit_pattern, len)) {
+                    return Ok(symbol);
+                }
+            }
+
+            Err(io::Error::new(io::ErrorKind::InvalidData, "unknown code"))
+        }
+    }
+
+    fn code_lengths(lengths: &[(Symbol, u8)]) -> Vec<CodeLength> {
+        lengths
+            .iter()
+            .map(|&(symbol, len)| CodeLength { symbol, len })
+            .collect()
+    }
+
+    fn encode(byte_map: &ByteMap, input: &[Symbol]) -> Vec<u8> {
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        for symbol in input {
+            let code = &byte_map[symbol];
+            writer.write_bits(code.bit_pattern, code.len).unwrap();
+        }
+        writer.flush().unwrap();
+
+        writer.writer.into_inner()
+    }
+
+    fn decode_all(table: &DecodeTable, encoded: &[u8], len: usize) -> Vec<Symbol> {
+        let mut reader = BitReader::new(Cursor::new(encoded));
+
+        (0..len)
+            .map(|_| table.decode(&mut reader).unwrap())
+            .collect()
+    }
+
+    /// Lengths of 1 to 20 bits, so some codes need secondary tables.
+    fn long_code_lengths() -> Vec<CodeLength> {
+        let mut lengths: Vec<(Symbol, u8)> = (1..20).map(|len| (len, len as u8)).collect();
+        lengths.push((20, 19));
+
+        code_lengths(&lengths)
+    }
+
+    #[test]
+    fn decodes_short_codes() {
+        let mut lengths = code_lengths(&[(1, 1), (2, 2), (3, 2)]);
+        let byte_map = ByteMap::new(&mut lengths);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+
+        let input = [1, 2, 3, 1, 3, 2, 1];
+        assert_eq!(decode_all(&table, &encode(&byte_map, &input), 7), input);
+    }
+
+    #[test]
+    fn decodes_codes_longer_than_primary_table() {
+        let mut lengths = long_code_lengths();
+        let byte_map = ByteMap::new(&mut lengths);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+
+        let input: Vec<Symbol> = (1..=20).chain((1..=20).rev()).collect();
+        let encoded = encode(&byte_map, &input);
+
+        assert_eq!(decode_all(&table, &encoded, input.len()), input);
+    }
+
+    #[test]
+    fn decodes_single_code() {
+        let mut lengths = code_lengths(&[(7, 1)]);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+
+        assert_eq!(decode_all(&table, &[0b1110_0000], 3), [7, 7, 7]);
+
+        let mut reader = BitReader::new(Cursor::new([0b0000_0000]));
+        assert!(table.decode(&mut reader).is_err());
+    }
+
+    #[test]
+    fn rejects_oversubscribed_lengths() {
+        let mut lengths = code_lengths(&[(1, 1), (2, 1), (3, 2)]);
+
+        assert!(DecodeTable::new(&mut lengths).is_err());
+    }
+
+    #[test]
+    fn rejects_lengths_outside_1_to_32() {
+        for len in [0, 33] {
+            let mut lengths = code_lengths(&[(1, 1), (2, len)]);
+
+            let Err(error) = DecodeTable::new(&mut lengths) else {
+                panic!("length {len} should be rejected");
+            };
+            assert!(matches!(Error::from(error), Error::CorruptTable(_)));
+        }
+    }
+
+    #[test]
+    fn accepts_incomplete_codes_of_32_bits() {
+        let mut lengths = code_lengths(&[(1, 32), (2, 32)]);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+
+        // Four levels of at most 1024 entries, not a 2^22 entry subtable.
+        assert!(table.entries.len() <= 4 << TABLE_BITS);
+        assert_eq!(decode_all(&table, &[0, 0, 0, 0, 0, 0, 0, 1], 2), [1, 2]);
+    }
+
+    #[test]
+    fn unused_code_is_rejected() {
+        let mut lengths = code_lengths(&[(1, 1), (2, 2)]);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+
+        let mut reader = BitReader::new(Cursor::new([0b1100_0000]));
+        let error = table.decode(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn matches_naive_decoder() {
+        let mut lengths = long_code_lengths();
+        let byte_map = ByteMap::new(&mut lengths);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+        let naive = NaiveTable::new(&byte_map);
+
+        let input: Vec<Symbol> = (0..5000u32).map(|i| (i * 7 % 20 + 1) as Symbol).collect();
+        let encoded = encode(&byte_map, &input);
+
+        let mut reader = BitReader::new(Cursor::new(&encoded));
+        let naive_output: Vec<Symbol> = (0..input.len())
+            .map(|_| naive.decode(&mut reader).unwrap())
+            .collect();
+
+        assert_eq!(naive_output, input);
+        assert_eq!(decode_all(&table, &encoded, input.len()), input);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_against_naive_decoder() {
+        let mut lengths: Vec<CodeLength> = (0..256)
+            .map(|symbol| CodeLength {
+                symbol,
+                len: match symbol {
+                    0..64 => 7,
+                    64..128 => 8,
+                    _ => 9,
+                },
+            })
+            .collect();
+        let byte_map = ByteMap::new(&mut lengths);
+        let table = DecodeTable::new(&mut lengths).unwrap();
+        let naive = NaiveTable::new(&byte_map);
+
+        let input: Vec<Symbol> = (0..16u32 << 20)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as Symbol)
+            .collect();
+        let encoded = encode(&byte_map, &input);
+
+        let start = Instant::now();
+        let mut reader = BitReader::new(Cursor::new(&encoded));
+        for &symbol in &input {
+            assert_eq!(naive.decode(&mut reader).unwrap(), symbol);
+        }
+        let naive_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut reader = BitReader::new(Cursor::new(&encoded));
+        for &symbol in &input {
+            assert_eq!(table.decode(&mut reader).unwrap(), symbol);
+        }
+        let table_time = start.elapsed();
+
+        let megabytes = input.len() as f64 / (1 << 20) as f64;
+        println!(
+            "naive: {:.1} MiB/s, table: {:.1} MiB/s",
+            megabytes / naive_time.as_secs_f64(),
+            megabytes / table_time.as_secs_f64()
+        );
+    }
+}
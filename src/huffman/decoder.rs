@@ -0,0 +1,421 @@
+use crate::checksum::{Checksum, ChecksumKind};
+use crate::container::{self, Header};
+use crate::error::Error;
+use crate::huffman::BYTE_ALPHABET_SIZE;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::block::BlockType;
+use crate::huffman::byte_map::CodeLength;
+use crate::huffman::decode_table::DecodeTable;
+use crate::huffman::packed_table;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+const MAX_CODE_LENGTH: u8 = u32::BITS as u8;
+const OUTPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The kind of block being decoded.
+#[derive(Clone, Copy)]
+enum Block {
+    Stored,
+    /// Coded with the current table.
+    Coded,
+}
+
+/// Decodes the blocks and trailer that follow a header a chunk at a time.
+pub struct Decoder<R>
+where
+    R: Read,
+{
+    reader: BitReader<BufReader<R>>,
+    checksum_kind: ChecksumKind,
+    original_length: Option<u64>,
+    checksum: Checksum,
+    table: Option<DecodeTable>,
+    /// The block being decoded and the number of its bytes still to come.
+    block: Option<(Block, u64)>,
+    total_length: u64,
+    finished: bool,
+}
+
+impl<R> Decoder<R>
+where
+    R: Read,
+{
+    pub fn new(reader: BitReader<BufReader<R>>, header: &Header) -> Self {
+        Decoder {
+            reader,
+            checksum_kind: header.checksum,
+            original_length: header.original_length,
+            checksum: header.checksum.new_checksum(),
+            table: None,
+            block: None,
+            total_length: 0,
+            finished: false,
+        }
+    }
+
+    /// Decodes the whole stream into `writer`.
+    pub fn decode<W: Write>(mut self, writer: &mut W) -> io::Result<()> {
+        let mut chunk = Vec::with_capacity(OUTPUT_CHUNK_SIZE);
+
+        loop {
+            self.decode_chunk(&mut chunk)?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+
+            writer.write_all(&chunk)?;
+        }
+    }
+
+    /// Replaces the contents of `chunk` with the next bytes of the stream,
+    /// leaving it empty once the end marker and trailer have been read and
+    /// checked.
+    pub fn decode_chunk(&mut self, chunk: &mut Vec<u8>) -> io::Result<()> {
+        chunk.clear();
+
+        while !self.finished {
+            let Some((block, remaining)) = self.block else {
+                self.start_block()?;
+                continue;
+            };
+
+            if remaining == 0 {
+                // Whatever is left of the block's final byte is padding from
+                // `BitWriter::flush`.
+                self.reader.align_to_byte();
+                self.block = None;
+                continue;
+            }
+
+            let chunk_len = remaining.min(OUTPUT_CHUNK_SIZE as u64) as usize;
+            match block {
+                Block::Stored => {
+                    chunk.resize(chunk_len, 0);
+                    self.reader.read_bytes(chunk)?;
+                }
+                Block::Coded => {
+                    let symbols = self
+                        .table
+                        .as_ref()
+                        .ok_or_else(|| Error::CorruptTable("coded block has no table".into()))?;
+                    for _ in 0..chunk_len {
+                        chunk.push(symbols.decode(&mut self.reader)? as u8);
+                    }
+                }
+            }
+
+            self.checksum.update(chunk);
+            self.block = Some((block, remaining - chunk_len as u64));
+            return Ok(());
+        }
+
+        Ok(())
+    }
+
+    /// Reads the length and type of the next block, and its table if it
+    /// has one, or the end marker and trailer.
+    fn start_block(&mut self) -> io::Result<()> {
+        let block_length = container::read_varint(&mut self.reader)?;
+        if block_length == 0 {
+            return self.finish();
+        }
+
+        let mut block_type = [0];
+        self.reader.read_bytes(&mut block_type)?;
+
+        let block = match BlockType::try_from(block_type[0])? {
+            BlockType::Stored => Block::Stored,
+            BlockType::Table => {
+                self.table = Some(self.decode_codes()?);
+                Block::Coded
+            }
+            BlockType::PackedTable => {
+                let mut code_lengths =
+                    packed_table::read_code_lengths(&mut self.reader, BYTE_ALPHABET_SIZE)?;
+                self.table = Some(DecodeTable::new(&mut code_lengths)?);
+                Block::Coded
+            }
+            BlockType::Reuse => {
+                if self.table.is_none() {
+                    return Err(Error::CorruptTable(
+                        "block reuses a table before any was sent".into(),
+                    )
+                    .into());
+                }
+                Block::Coded
+            }
+        };
+
+        self.total_length = self
+            .total_length
+            .checked_add(block_length)
+            .ok_or_else(|| Error::LimitExceeded("stream length overflows u64".into()))?;
+        self.block = Some((block, block_length));
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self
+            .original_length
+            .is_some_and(|length| length != self.total_length)
+        {
+            return Err(invalid_data("stream length does not match the header"));
+        }
+
+        let mut trailer = vec![0; self.checksum_kind.trailer_len()];
+        self.reader.read_bytes(&mut trailer)?;
+        self.checksum.verify(&trailer)?;
+
+        if self.reader.remaining_bits()? != Some(0) {
+            return Err(invalid_data("unexpected data after end of stream"));
+        }
+
+        self.finished = true;
+
+        Ok(())
+    }
+
+    fn decode_codes(&mut self) -> io::Result<DecodeTable> {
+        let mut count = [0; 2];
+        self.reader.read_bytes(&mut count)?;
+
+        let count = u16::from_be_bytes(count) as usize;
+        if count == 0 || count > BYTE_ALPHABET_SIZE {
+            return Err(Error::CorruptTable("code count out of range".into()).into());
+        }
+
+        let mut code_lengths = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut entry = [0; 2];
+            self.reader.read_bytes(&mut entry)?;
+
+            let [byte, len] = entry;
+            if len == 0 || len > MAX_CODE_LENGTH {
+                return Err(Error::CorruptTable("code length out of range".into()).into());
+            }
+
+            code_lengths.push(CodeLength {
+                symbol: byte.into(),
+                len,
+            });
+        }
+
+        DecodeTable::new(&mut code_lengths)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+    use crate::container::CodecId;
+    use crate::huffman::bit_writer::BitWriter;
+    use crate::huffman::byte_map::ByteMap;
+    use crate::huffman::packed_table::PackedTable;
+
+    fn decode_with_header(input: &[u8], header: Header) -> io::Result<Vec<u8>> {
+        let reader = BitReader::new(BufReader::new(Cursor::new(input)));
+        let mut output = Vec::new();
+
+        Decoder::new(reader, &header).decode(&mut output)?;
+
+        Ok(output)
+    }
+
+    fn decode_bytes(input: &[u8]) -> io::Result<Vec<u8>> {
+        decode_with_header(
+            input,
+            Header::new(CodecId::Huffman, ChecksumKind::None, None),
+        )
+    }
+
+    #[test]
+    fn decode_empty_input() -> io::Result<()> {
+        assert!(decode_bytes(&[0])?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_single_byte() -> io::Result<()> {
+        let input = [
+            1, // block length
+            1, // block type (table)
+            0, 1, // code count (u16 BE)
+            b'3', 1,   // (byte, length)
+            128, // encoded data
+            0,   // end of stream
+        ];
+
+        assert_eq!(decode_bytes(&input)?, b"3");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_varying_frequencies() -> io::Result<()> {
+        let input = [
+            6, // block length
+            1, // block type (table)
+            0, 3, // code count
+            97, 1, // 'a'
+            98, 2, // 'b'
+            99, 2,  // 'c'
+            11, // encoded data
+            0,  // end of stream
+        ];
+
+        assert_eq!(decode_bytes(&input)?, b"aaaabc");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_multiple_blocks() -> io::Result<()> {
+        let input = [
+            &[2, 1, 0, 1, b'x', 1, 0b1100_0000][..],      // "xx"
+            &[3, 1, 0, 2, b'a', 1, b'b', 1, 0b0100_0000], // "aba"
+            &[2, 0, b'\n', b'\n'],                        // stored
+            &[2, 2, 0b1000_0000],                         // "ba", reusing the table
+            &[0],
+        ]
+        .concat();
+
+        assert_eq!(decode_bytes(&input)?, b"xxaba\n\nba");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_stops_at_block_length() -> io::Result<()> {
+        // 'a' has the all-zero code, so the padding alone cannot mark the end.
+        let input = [4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000, 0];
+
+        assert_eq!(decode_bytes(&input)?, b"abab");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_truncated_data() {
+        let input = [9, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000];
+
+        let error = decode_bytes(&input).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn decode_rejects_missing_end_marker() {
+        let input = [4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000];
+
+        assert!(decode_bytes(&input).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_trailing_data() {
+        let input = [4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000, 0, 0];
+
+        assert!(decode_bytes(&input).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_length_mismatch() {
+        let input = [4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000, 0];
+        let header = Header::new(CodecId::Huffman, ChecksumKind::None, Some(5));
+
+        assert!(decode_with_header(&input, header).is_err());
+    }
+
+    #[test]
+    fn decode_verifies_checksum_trailer() -> io::Result<()> {
+        let mut input = vec![4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000, 0];
+        input.extend_from_slice(&0x36D7_0AA6u32.to_be_bytes());
+        let header = Header::new(CodecId::Huffman, ChecksumKind::Crc32, Some(4));
+
+        assert_eq!(decode_with_header(&input, header)?, b"abab");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_checksum_mismatch() {
+        let mut input = vec![4, 1, 0, 2, b'a', 1, b'b', 1, 0b0101_0000, 0];
+        input.extend_from_slice(&0x36D7_0AA7u32.to_be_bytes());
+        let header = Header::new(CodecId::Huffman, ChecksumKind::Crc32, Some(4));
+
+        let error = decode_with_header(&input, header).unwrap_err();
+        assert_eq!(error.to_string(), "checksum mismatch");
+    }
+
+    #[test]
+    fn decode_stored_block() -> io::Result<()> {
+        assert_eq!(decode_bytes(&[3, 0, b'x', b'y', b'z', 0])?, b"xyz");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_reuse_without_table() {
+        let input = [2, 2, 0b1000_0000, 0];
+
+        assert!(decode_bytes(&input).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_unknown_block_type() {
+        let input = [1, 7, 0, 0];
+
+        assert!(decode_bytes(&input).is_err());
+    }
+
+    #[test]
+    fn decode_packed_table_block() -> io::Result<()> {
+        let byte_map = ByteMap::new(&mut [
+            CodeLength {
+                symbol: b'a'.into(),
+                len: 1,
+            },
+            CodeLength {
+                symbol: b'b'.into(),
+                len: 2,
+            },
+            CodeLength {
+                symbol: b'c'.into(),
+                len: 2,
+            },
+        ]);
+
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bytes(&[6, BlockType::PackedTable as u8])?;
+        PackedTable::new(&byte_map, BYTE_ALPHABET_SIZE).write_to(&mut writer)?;
+        for &byte in b"aaaabc" {
+            let code = &byte_map[&byte.into()];
+            writer.write_bits(code.bit_pattern, code.len)?;
+        }
+        writer.write_bytes(&[0])?;
+
+        assert_eq!(decode_bytes(&writer.writer)?, b"aaaabc");
+        Ok(())
+    }
+
+    #[test]
+    fn decode_rejects_packed_repeat_without_previous_length() {
+        // A code-length code whose only symbol is REPEAT_PREVIOUS.
+        let input = [1, 3, 0b0000_0100, 0b1100_0000, 0];
+
+        let error = decode_bytes(&input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "code length repeat without a previous length"
+        );
+    }
+
+    #[test]
+    fn decode_rejects_packed_lengths_past_alphabet() {
+        // Two runs of 138 zero lengths.
+        let input = [1, 3, 0b0000_1100, 0b0000_0011, 0xFF, 0b1111_1110, 0];
+
+        let error = decode_bytes(&input).unwrap_err();
+        assert_eq!(error.to_string(), "code lengths overflow the alphabet");
+    }
+}
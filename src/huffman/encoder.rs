@@ -1,37 +1,42 @@
+use crate::checksum::Checksum;
+use crate::error::Error;
+use crate::huffman::Symbol;
 use crate::huffman::bit_writer::BitWriter;
 use crate::huffman::byte_map::ByteMap;
 use std::io::prelude::*;
-use std::io::{self, BufReader, BufWriter};
+use std::io::{self, BufWriter};
 
 pub struct Encoder<'a, R, W>
 where
-    R: Read,
+    R: BufRead,
     W: Write,
 {
-    reader: &'a mut BufReader<R>,
+    reader: &'a mut R,
     writer: &'a mut BitWriter<BufWriter<W>>,
     byte_map: &'a ByteMap,
+    checksum: &'a mut Checksum,
 }
 
 impl<'a, R, W> Encoder<'a, R, W>
 where
-    R: Read,
+    R: BufRead,
     W: Write,
 {
     pub fn new(
-        reader: &'a mut BufReader<R>,
+        reader: &'a mut R,
         writer: &'a mut BitWriter<BufWriter<W>>,
         byte_map: &'a ByteMap,
+        checksum: &'a mut Checksum,
     ) -> Self {
         Encoder {
             reader,
             writer,
             byte_map,
+            checksum,
         }
     }
 
     pub fn encode(self) -> io::Result<()> {
-        println!("{:?}", self.byte_map);
         self.encode_codes()?.encode_data()
     }
 
@@ -39,7 +44,7 @@ where
         let mut codes: Vec<(u8, u8)> = self
             .byte_map
             .iter()
-            .map(|(byte, code)| (*byte, code.len))
+            .map(|(&symbol, code)| (symbol as u8, code.len))
             .collect();
 
         codes
@@ -55,7 +60,7 @@ where
         Ok(self)
     }
 
-    fn encode_data(self) -> io::Result<()> {
+    pub fn encode_data(self) -> io::Result<()> {
         loop {
             let buffer = self.reader.fill_buf()?;
             let length = buffer.len();
@@ -67,12 +72,13 @@ where
             for byte in buffer {
                 let code = self
                     .byte_map
-                    .get(byte)
-                    .expect("Every byte should have a key if byte_map was constructed properly");
+                    .get(&Symbol::from(*byte))
+                    .ok_or_else(|| Error::CorruptTable(format!("no code for byte {byte:#04x}")))?;
 
                 self.writer.write_bits(code.bit_pattern, code.len)?;
             }
 
+            self.checksum.update(buffer);
             self.reader.consume(length);
         }
 
@@ -84,6 +90,8 @@ where
 mod tests {
     use std::io::{BufReader, BufWriter, Cursor};
 
+    use crate::checksum::ChecksumKind;
+    use crate::huffman::DEFAULT_MAX_CODE_LENGTH;
     use crate::huffman::bit_writer::BitWriter;
     use crate::huffman::frequency::Frequencies;
 
@@ -107,11 +115,12 @@ mod tests {
 
         let byte_map = Frequencies::from_input(&mut input)?
             .to_huff_tree()
-            .to_byte_map();
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
 
         input.rewind()?;
 
-        let encoder = Encoder::new(&mut input, &mut output, &byte_map);
+        let mut checksum = ChecksumKind::None.new_checksum();
+        let encoder = Encoder::new(&mut input, &mut output, &byte_map, &mut checksum);
         encoder.encode()?;
 
         let inner_vec = output.writer.into_inner()?.into_inner();
@@ -136,11 +145,15 @@ mod tests {
 
         let byte_map = Frequencies::from_input(&mut input)?
             .to_huff_tree()
-            .to_byte_map();
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
 
         input.rewind()?;
 
-        byte_map.encode(&mut input, &mut output)?;
+        byte_map.encode(
+            &mut input,
+            &mut output,
+            &mut ChecksumKind::None.new_checksum(),
+        )?;
         output.flush()?;
 
         let inner_vec = output.writer.into_inner()?.into_inner();
@@ -164,11 +177,15 @@ mod tests {
 
         let byte_map = Frequencies::from_input(&mut input)?
             .to_huff_tree()
-            .to_byte_map();
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
 
         input.rewind()?;
 
-        byte_map.encode(&mut input, &mut output)?;
+        byte_map.encode(
+            &mut input,
+            &mut output,
+            &mut ChecksumKind::None.new_checksum(),
+        )?;
         output.flush()?;
 
         let inner_vec = output.writer.into_inner()?.into_inner();
@@ -196,11 +213,15 @@ mod tests {
 
         let byte_map = Frequencies::from_input(&mut input)?
             .to_huff_tree()
-            .to_byte_map();
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
 
         input.rewind()?;
 
-        byte_map.encode(&mut input, &mut output)?;
+        byte_map.encode(
+            &mut input,
+            &mut output,
+            &mut ChecksumKind::None.new_checksum(),
+        )?;
         output.flush()?;
 
         let inner_vec = output.writer.into_inner()?.into_inner();
@@ -223,4 +244,46 @@ mod tests {
         assert_eq!(inner_vec, expected);
         Ok(())
     }
+
+    #[test]
+    fn encode_updates_checksum() -> std::io::Result<()> {
+        let mut input = create_input(b"abababab");
+        let mut output = create_output();
+
+        let byte_map = Frequencies::from_input(&mut input)?
+            .to_huff_tree()
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
+
+        input.rewind()?;
+
+        let mut checksum = ChecksumKind::Crc32.new_checksum();
+        byte_map.encode(&mut input, &mut output, &mut checksum)?;
+
+        let inner_vec = output.writer.into_inner()?.into_inner();
+
+        let mut crc = crate::checksum::Crc32::new();
+        crc.update(b"abababab");
+
+        assert_eq!(inner_vec, [0, 2, 97, 1, 98, 1, 85]);
+        assert_eq!(checksum.to_trailer(), crc.finish().to_be_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn byte_without_code_is_an_error() -> std::io::Result<()> {
+        let byte_map = Frequencies::from_input(&mut create_input(b"ab"))?
+            .to_huff_tree()
+            .to_byte_map(DEFAULT_MAX_CODE_LENGTH);
+
+        let error = byte_map
+            .encode(
+                &mut create_input(b"abc"),
+                &mut create_output(),
+                &mut ChecksumKind::None.new_checksum(),
+            )
+            .unwrap_err();
+
+        assert!(matches!(Error::from(error), Error::CorruptTable(_)));
+        Ok(())
+    }
 }
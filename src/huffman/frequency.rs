@@ -1,20 +1,26 @@
 use crate::huffman::BYTE_ALPHABET_SIZE;
 use crate::huffman::tree::HuffmanNode;
+use std::io;
 use std::io::prelude::*;
-use std::io::{self, BufReader};
 use std::ops::{Deref, DerefMut};
 
+/// How often each symbol of an alphabet occurs, indexed by symbol.
 #[derive(Debug, PartialEq, Eq)]
-pub struct Frequencies([u64; BYTE_ALPHABET_SIZE]);
+pub struct Frequencies(Vec<u64>);
 
 impl Frequencies {
+    /// Frequencies over the 256 byte values.
     pub fn new() -> Self {
-        Frequencies([0; BYTE_ALPHABET_SIZE])
+        Frequencies::with_alphabet_size(BYTE_ALPHABET_SIZE)
     }
 
-    pub fn from_input<R>(reader: &mut BufReader<R>) -> io::Result<Self>
+    pub fn with_alphabet_size(alphabet_size: usize) -> Self {
+        Frequencies(vec![0; alphabet_size])
+    }
+
+    pub fn from_input<R>(reader: &mut R) -> io::Result<Self>
     where
-        R: Read + Seek,
+        R: BufRead,
     {
         let mut frequencies = Frequencies::new();
 
@@ -42,14 +48,10 @@ impl Frequencies {
     fn count_byte(&mut self, byte: u8) {
         self[byte as usize] += 1;
     }
-
-    pub fn is_empty(&self) -> bool {
-        self.iter().all(|&freq| freq == 0)
-    }
 }
 
 impl Deref for Frequencies {
-    type Target = [u64; BYTE_ALPHABET_SIZE];
+    type Target = [u64];
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -65,7 +67,7 @@ impl DerefMut for Frequencies {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use std::io::{BufReader, Cursor};
 
     #[test]
     fn get_frequencies_empty_input() {
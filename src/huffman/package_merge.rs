@@ -0,0 +1,181 @@
+use crate::huffman::Symbol;
+use crate::huffman::byte_map::CodeLength;
+use crate::huffman::frequency::Frequencies;
+
+/// An item in a package-merge list: its total weight and how many times each
+/// symbol (by index into the sorted leaves) was merged into it.
+#[derive(Clone)]
+struct Item {
+    weight: u64,
+    symbols: Vec<u16>,
+}
+
+/// Computes optimal code lengths no longer than `max_len` using the
+/// package-merge algorithm.
+///
+/// Every symbol with a non-zero frequency gets a code. If there are more
+/// such symbols than `2^max_len`, the limit is raised to the shortest that
+/// gives each a code.
+pub fn code_lengths(frequencies: &Frequencies, max_len: u8) -> Vec<CodeLength> {
+    let mut leaves: Vec<(u64, Symbol)> = frequencies
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(symbol, &count)| (count, symbol as Symbol))
+        .collect();
+
+    leaves.sort();
+
+    if leaves.len() <= 1 {
+        return leaves
+            .into_iter()
+            .map(|(_, symbol)| CodeLength { symbol, len: 1 })
+            .collect();
+    }
+
+    let max_len = max_len.max(leaves.len().next_power_of_two().trailing_zeros() as u8);
+
+    let leaf_items: Vec<Item> = leaves
+        .iter()
+        .enumerate()
+        .map(|(index, &(weight, _))| Item {
+            weight,
+            symbols: vec![index as u16],
+        })
+        .collect();
+
+    let mut items = leaf_items.clone();
+
+    for _ in 1..max_len {
+        let packages = items.chunks_exact(2).map(|pair| Item {
+            weight: pair[0].weight + pair[1].weight,
+            symbols: [&pair[0].symbols[..], &pair[1].symbols[..]].concat(),
+        });
+
+        items = merge(&leaf_items, packages.collect());
+    }
+
+    let mut lengths = vec![0u8; leaves.len()];
+    for item in &items[..2 * leaves.len() - 2] {
+        for &symbol in &item.symbols {
+            lengths[symbol as usize] += 1;
+        }
+    }
+
+    leaves
+        .iter()
+        .zip(lengths)
+        .map(|(&(_, symbol), len)| CodeLength { symbol, len })
+        .collect()
+}
+
+/// Merges two lists sorted by weight, preferring leaves on ties.
+fn merge(leaves: &[Item], packages: Vec<Item>) -> Vec<Item> {
+    let mut merged = Vec::with_capacity(leaves.len() + packages.len());
+    let mut leaves = leaves.iter().cloned().peekable();
+    let mut packages = packages.into_iter().peekable();
+
+    loop {
+        let take_leaf = match (leaves.peek(), packages.peek()) {
+            (Some(leaf), Some(package)) => leaf.weight <= package.weight,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+
+        let next = if take_leaf {
+            leaves.next()
+        } else {
+            packages.next()
+        };
+
+        merged.extend(next);
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lengths_by_symbol(frequencies: &Frequencies, max_len: u8) -> Vec<(Symbol, u8)> {
+        let mut lengths: Vec<(Symbol, u8)> = code_lengths(frequencies, max_len)
+            .into_iter()
+            .map(|code| (code.symbol, code.len))
+            .collect();
+
+        lengths.sort();
+        lengths
+    }
+
+    fn kraft_sum(lengths: &[(Symbol, u8)], max_len: u8) -> u64 {
+        lengths
+            .iter()
+            .map(|&(_, len)| 1u64 << (max_len - len))
+            .sum()
+    }
+
+    #[test]
+    fn unconstrained_lengths_match_huffman() {
+        let mut frequencies = Frequencies::new();
+        frequencies[b'a' as usize] = 4;
+        frequencies[b'b' as usize] = 1;
+        frequencies[b'c' as usize] = 1;
+
+        assert_eq!(
+            lengths_by_symbol(&frequencies, 15),
+            vec![(97, 1), (98, 2), (99, 2)]
+        );
+    }
+
+    #[test]
+    fn limit_flattens_skewed_distribution() {
+        let mut frequencies = Frequencies::new();
+        for (byte, count) in [1, 1, 2, 4, 8].into_iter().enumerate() {
+            frequencies[byte] = count;
+        }
+
+        // Unlimited Huffman would give lengths 4, 4, 3, 2, 1.
+        let lengths = lengths_by_symbol(&frequencies, 3);
+
+        assert_eq!(lengths, vec![(0, 3), (1, 3), (2, 3), (3, 3), (4, 1)]);
+        assert_eq!(kraft_sum(&lengths, 3), 1 << 3);
+    }
+
+    #[test]
+    fn fibonacci_frequencies_respect_limit() {
+        let mut frequencies = Frequencies::new();
+        let (mut a, mut b) = (1u64, 1u64);
+        for byte in 0..60 {
+            frequencies[byte] = a;
+            (a, b) = (b, a + b);
+        }
+
+        let lengths = lengths_by_symbol(&frequencies, 15);
+
+        assert_eq!(lengths.len(), 60);
+        assert!(lengths.iter().all(|&(_, len)| (1..=15).contains(&len)));
+        assert_eq!(kraft_sum(&lengths, 15), 1 << 15);
+    }
+
+    #[test]
+    fn all_bytes_at_minimum_limit() {
+        let mut frequencies = Frequencies::new();
+        for (byte, count) in frequencies.iter_mut().enumerate() {
+            *count = 1 + byte as u64 * byte as u64;
+        }
+
+        let lengths = lengths_by_symbol(&frequencies, 8);
+
+        assert!(lengths.iter().all(|&(_, len)| len == 8));
+    }
+
+    #[test]
+    fn single_symbol_gets_one_bit() {
+        let mut frequencies = Frequencies::new();
+        frequencies[7] = 100;
+
+        assert_eq!(lengths_by_symbol(&frequencies, 15), vec![(7, 1)]);
+    }
+}
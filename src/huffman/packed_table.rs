@@ -0,0 +1,318 @@
+use crate::error::Error;
+use crate::huffman::Symbol;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::byte_map::{ByteMap, CodeLength};
+use crate::huffman::decode_table::DecodeTable;
+use crate::huffman::frequency::Frequencies;
+use std::io::{self, Read, Write};
+
+/// Symbols `0..=32` are literal code lengths.
+pub const MAX_LITERAL_LENGTH: u8 = u32::BITS as u8;
+/// Repeats the previous length 3-6 times (2 extra bits).
+pub const REPEAT_PREVIOUS: u8 = 33;
+/// A run of 3-10 zero lengths (3 extra bits).
+pub const REPEAT_ZERO_SHORT: u8 = 34;
+/// A run of 11-138 zero lengths (7 extra bits).
+pub const REPEAT_ZERO_LONG: u8 = 35;
+
+pub const ALPHABET_SIZE: usize = REPEAT_ZERO_LONG as usize + 1;
+
+/// The symbols that stand for runs in run-length coded code lengths, which
+/// differ between `PackedTable` and DEFLATE.
+pub struct RepeatSymbols {
+    /// Repeats the previous length 3-6 times (2 extra bits).
+    pub previous: u8,
+    /// A run of 3-10 zero lengths (3 extra bits).
+    pub zero_short: u8,
+    /// A run of 11-138 zero lengths (7 extra bits).
+    pub zero_long: u8,
+}
+
+const REPEAT_SYMBOLS: RepeatSymbols = RepeatSymbols {
+    previous: REPEAT_PREVIOUS,
+    zero_short: REPEAT_ZERO_SHORT,
+    zero_long: REPEAT_ZERO_LONG,
+};
+
+/// Order in which the lengths of the code-length code are written, so that
+/// the rarely used long lengths at the end can be left out.
+pub const LENGTH_ORDER: [u8; ALPHABET_SIZE] = [
+    33, 34, 35, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16, 17, 18, 19, 20, 21, 22,
+    23, 24, 25, 26, 27, 28, 29, 30, 31, 32,
+];
+
+/// Bits used for the number of code-length code lengths that follow.
+pub const COUNT_BITS: u8 = 6;
+/// Bits used for each code-length code length.
+pub const LENGTH_BITS: u8 = 3;
+/// Longest code in the code-length code, the most `LENGTH_BITS` can hold.
+pub const MAX_LENGTH_CODE_LENGTH: u8 = (1 << LENGTH_BITS) - 1;
+
+/// A code table stored as the code length of every symbol of the alphabet
+/// (zero for symbols without a code), run-length coded and then Huffman
+/// coded, like the code lengths of a DEFLATE dynamic block.
+///
+/// ```text
+/// count (6 bits) | count code-length code lengths (3 bits each, in LENGTH_ORDER)
+/// | coded run-length symbols, each followed by its extra bits
+/// ```
+pub struct PackedTable {
+    /// The bit fields to write, as `(bits, len)` pairs.
+    fields: Vec<(u32, u8)>,
+}
+
+impl PackedTable {
+    pub fn new(byte_map: &ByteMap, alphabet_size: usize) -> Self {
+        let mut lengths = vec![0; alphabet_size];
+        for (&symbol, code) in byte_map.iter() {
+            lengths[symbol as usize] = code.len;
+        }
+
+        let runs = run_length_code(&lengths, &REPEAT_SYMBOLS);
+
+        let mut frequencies = Frequencies::with_alphabet_size(ALPHABET_SIZE);
+        for &(symbol, _, _) in &runs {
+            frequencies[symbol as usize] += 1;
+        }
+
+        let length_code = frequencies
+            .to_huff_tree()
+            .to_byte_map(MAX_LENGTH_CODE_LENGTH);
+
+        let count = LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| length_code.contains_key(&symbol.into()))
+            .map_or(0, |position| position + 1);
+
+        let mut fields = vec![(count as u32, COUNT_BITS)];
+
+        for symbol in &LENGTH_ORDER[..count] {
+            let len = length_code
+                .get(&(*symbol).into())
+                .map_or(0, |code| code.len);
+            fields.push((len as u32, LENGTH_BITS));
+        }
+
+        for (symbol, extra, extra_len) in runs {
+            let code = &length_code[&symbol.into()];
+            fields.push((code.bit_pattern, code.len));
+
+            if extra_len > 0 {
+                fields.push((extra, extra_len));
+            }
+        }
+
+        PackedTable { fields }
+    }
+
+    /// Size of the table in bits.
+    pub fn bit_len(&self) -> u64 {
+        self.fields.iter().map(|&(_, len)| len as u64).sum()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut BitWriter<W>) -> io::Result<()> {
+        for &(bits, len) in &self.fields {
+            writer.write_bits(bits, len)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a table written by `PackedTable` for an alphabet of `alphabet_size`
+/// symbols, returning the lengths of the symbols that have a code.
+pub fn read_code_lengths<R: Read>(
+    reader: &mut BitReader<R>,
+    alphabet_size: usize,
+) -> io::Result<Vec<CodeLength>> {
+    let count = reader.read_bits(COUNT_BITS)? as usize;
+    if count > ALPHABET_SIZE {
+        return Err(corrupt_table("code-length code count out of range"));
+    }
+
+    let mut length_code = vec![];
+    for &symbol in &LENGTH_ORDER[..count] {
+        let len = reader.read_bits(LENGTH_BITS)? as u8;
+        if len > 0 {
+            length_code.push(CodeLength {
+                symbol: symbol.into(),
+                len,
+            });
+        }
+    }
+
+    if length_code.is_empty() {
+        return Err(corrupt_table("code-length code is empty"));
+    }
+
+    let length_code = DecodeTable::new(&mut length_code)?;
+    let mut lengths = Vec::with_capacity(alphabet_size);
+
+    while lengths.len() < alphabet_size {
+        // The code-length code only has symbols below `ALPHABET_SIZE`.
+        let symbol = length_code.decode(reader)? as u8;
+
+        let (len, repeat) = match symbol {
+            0..=MAX_LITERAL_LENGTH => (symbol, 1),
+            REPEAT_PREVIOUS => {
+                let &previous = lengths
+                    .last()
+                    .ok_or_else(|| corrupt_table("code length repeat without a previous length"))?;
+                (previous, 3 + reader.read_bits(2)?)
+            }
+            REPEAT_ZERO_SHORT => (0, 3 + reader.read_bits(3)?),
+            REPEAT_ZERO_LONG => (0, 11 + reader.read_bits(7)?),
+            _ => return Err(corrupt_table("unknown code length symbol")),
+        };
+
+        if lengths.len() + repeat as usize > alphabet_size {
+            return Err(corrupt_table("code lengths overflow the alphabet"));
+        }
+
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+
+    let code_lengths: Vec<CodeLength> = lengths
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, len)| len > 0)
+        .map(|(symbol, len)| CodeLength {
+            symbol: symbol as Symbol,
+            len,
+        })
+        .collect();
+
+    if code_lengths.is_empty() {
+        return Err(corrupt_table("code table is empty"));
+    }
+
+    Ok(code_lengths)
+}
+
+fn corrupt_table(message: &str) -> io::Error {
+    Error::CorruptTable(message.into()).into()
+}
+
+/// Splits `lengths` into run-length symbols, each with its extra bits and
+/// their count.
+pub fn run_length_code(lengths: &[u8], symbols: &RepeatSymbols) -> Vec<(u8, u32, u8)> {
+    let mut runs = vec![];
+    let mut i = 0;
+
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..]
+            .iter()
+            .take_while(|&&other| other == len)
+            .count();
+
+        if len == 0 {
+            let run = run.min(138);
+
+            match run {
+                11.. => runs.push((symbols.zero_long, run as u32 - 11, 7)),
+                3.. => runs.push((symbols.zero_short, run as u32 - 3, 3)),
+                _ => runs.extend(std::iter::repeat_n((0, 0, 0), run)),
+            }
+
+            i += run;
+            continue;
+        }
+
+        runs.push((len, 0, 0));
+
+        let mut remaining = run - 1;
+        while remaining >= 3 {
+            let repeat = remaining.min(6);
+            runs.push((symbols.previous, repeat as u32 - 3, 2));
+            remaining -= repeat;
+        }
+
+        runs.extend(std::iter::repeat_n((len, 0, 0), remaining));
+        i += run;
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::BYTE_ALPHABET_SIZE;
+    use std::io::Cursor;
+
+    #[test]
+    fn run_length_code_zero_runs() {
+        let mut lengths = [0; 256];
+        lengths[2] = 4;
+        lengths[8] = 4;
+
+        assert_eq!(
+            run_length_code(&lengths, &REPEAT_SYMBOLS),
+            vec![
+                (0, 0, 0),
+                (0, 0, 0),
+                (4, 0, 0),
+                (REPEAT_ZERO_SHORT, 2, 3),
+                (4, 0, 0),
+                (REPEAT_ZERO_LONG, 127, 7),
+                (REPEAT_ZERO_LONG, 98, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_length_code_repeats_previous_length() {
+        let mut lengths = [0; 256];
+        lengths[..10].fill(5);
+        lengths[10..12].fill(6);
+
+        assert_eq!(
+            run_length_code(&lengths, &REPEAT_SYMBOLS),
+            vec![
+                (5, 0, 0),
+                (REPEAT_PREVIOUS, 3, 2),
+                (REPEAT_PREVIOUS, 0, 2),
+                (6, 0, 0),
+                (6, 0, 0),
+                (REPEAT_ZERO_LONG, 127, 7),
+                (REPEAT_ZERO_LONG, 95, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn packed_table_is_smaller_for_many_codes() {
+        let mut code_lengths: Vec<CodeLength> = (0..256)
+            .map(|symbol| CodeLength { symbol, len: 8 })
+            .collect();
+        let byte_map = ByteMap::new(&mut code_lengths);
+
+        let table = PackedTable::new(&byte_map, BYTE_ALPHABET_SIZE);
+
+        // One literal 8 and 43 repeats, with a two-symbol code-length code.
+        assert!(table.bit_len() < 8 * byte_map.table_len() / 10);
+    }
+
+    #[test]
+    fn bit_len_matches_written_bits() {
+        let byte_map = ByteMap::new(&mut [
+            CodeLength { symbol: 1, len: 1 },
+            CodeLength { symbol: 2, len: 2 },
+            CodeLength {
+                symbol: 200,
+                len: 2,
+            },
+        ]);
+
+        let table = PackedTable::new(&byte_map, BYTE_ALPHABET_SIZE);
+
+        let mut writer = BitWriter::new(Cursor::new(vec![]));
+        table.write_to(&mut writer).unwrap();
+        writer.flush().unwrap();
+
+        let written = writer.writer.into_inner().len() as u64;
+        assert_eq!(written, table.bit_len().div_ceil(8));
+    }
+}
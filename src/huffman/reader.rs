@@ -0,0 +1,135 @@
+use crate::container::{CodecId, Header};
+use crate::error::Error;
+use crate::filter::Pipeline;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::decoder::Decoder;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Decompresses a stream written by `compress` or `HuffmanWriter` as it is
+/// read, undoing any filters recorded in its header.
+pub struct HuffmanReader<R: Read> {
+    decoder: Decoder<R>,
+    filters: Pipeline,
+    /// The last chunk decoded, before its filters are undone.
+    chunk: Vec<u8>,
+    /// Decoded bytes not yet read.
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> HuffmanReader<R> {
+    /// Reads the header, rejecting streams written by other codecs.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+        let header = Header::read_from(&mut reader)?;
+        header.expect_codec(CodecId::Huffman)?;
+
+        Ok(HuffmanReader {
+            decoder: Decoder::new(BitReader::new(reader), &header),
+            filters: Pipeline::decoder(&header.filters),
+            chunk: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+            finished: false,
+        })
+    }
+
+    /// Decodes the next chunk into `output`.
+    fn fill(&mut self) -> io::Result<()> {
+        self.decoder.decode_chunk(&mut self.chunk)?;
+
+        self.output.clear();
+        self.position = 0;
+
+        if self.chunk.is_empty() {
+            self.finished = true;
+            self.filters.finish(&mut self.output)
+        } else {
+            self.filters.push(&self.chunk, &mut self.output)
+        }
+    }
+}
+
+impl<R: Read> Read for HuffmanReader<R> {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            self.fill()?;
+        }
+
+        let len = bytes.len().min(self.output.len() - self.position);
+        bytes[..len].copy_from_slice(&self.output[self.position..self.position + len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Filter;
+    use crate::huffman::{HuffmanWriter, Options, compress};
+    use crate::test_support::compress_bytes;
+    use crate::{BwtOptions, ChecksumKind};
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_back_what_writer_wrote() {
+        let input: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i / 7).to_le_bytes())
+            .collect();
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            block_size: 64 * 1024,
+            filters: vec![Filter::Delta { stride: 4 }, Filter::Rle],
+            ..Options::default()
+        };
+
+        let mut writer = HuffmanWriter::new(Vec::new(), options).unwrap();
+        io::copy(&mut Cursor::new(&input), &mut writer).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut reader = HuffmanReader::new(Cursor::new(compressed)).unwrap();
+        let mut output = Vec::new();
+        let mut piece = [0; 1000];
+        loop {
+            let read = reader.read(&mut piece).unwrap();
+            if read == 0 {
+                break;
+            }
+            output.extend_from_slice(&piece[..read]);
+        }
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn rejects_corrupt_stream_when_read() {
+        let mut compressed = Vec::new();
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            ..Options::default()
+        };
+        compress(Cursor::new(b"abcabcabc"), &mut compressed, &options).unwrap();
+        *compressed.last_mut().unwrap() ^= 1;
+
+        let mut reader = HuffmanReader::new(Cursor::new(compressed)).unwrap();
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+
+        assert_eq!(error.to_string(), "checksum mismatch");
+    }
+
+    #[test]
+    fn rejects_other_codecs() {
+        let compressed = compress_bytes(&BwtOptions::default(), b"abc");
+
+        let error = HuffmanReader::new(Cursor::new(compressed)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
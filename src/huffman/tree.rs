@@ -1,13 +1,14 @@
-use crate::huffman::BYTE_ALPHABET_SIZE;
+use crate::huffman::Symbol;
 use crate::huffman::byte_map::{ByteMap, CodeLength};
 use crate::huffman::frequency::Frequencies;
+use crate::huffman::package_merge;
 use std::cmp;
 use std::collections::BinaryHeap;
 
 #[derive(Debug)]
 pub enum HuffmanNode {
     Leaf {
-        byte: u8,
+        symbol: Symbol,
         fequency: u64,
     },
     Internal {
@@ -21,18 +22,28 @@ impl HuffmanNode {
     pub fn from_frequencies(frequencies: &Frequencies) -> Self {
         let mut nodes = BinaryHeap::new();
 
-        for (byte, &count) in frequencies.iter().enumerate().take(BYTE_ALPHABET_SIZE) {
+        for (symbol, &count) in frequencies.iter().enumerate() {
             if count > 0 {
                 nodes.push(HuffmanNode::Leaf {
-                    byte: byte as u8,
+                    symbol: symbol as Symbol,
                     fequency: count,
                 })
             }
         }
 
-        while nodes.len() > 1 {
-            let left = Box::new(nodes.pop().unwrap());
-            let right = Box::new(nodes.pop().unwrap());
+        loop {
+            let Some(left) = nodes.pop() else {
+                // No symbol occurs at all.
+                return HuffmanNode::Leaf {
+                    symbol: 0,
+                    fequency: 0,
+                };
+            };
+            let Some(right) = nodes.pop() else {
+                return left;
+            };
+
+            let (left, right) = (Box::new(left), Box::new(right));
             let frequency = left.frequency() + right.frequency();
 
             nodes.push(HuffmanNode::Internal {
@@ -41,10 +52,16 @@ impl HuffmanNode {
                 frequency,
             })
         }
+    }
 
-        nodes
-            .pop()
-            .expect("There should always be exactly one node left after building Huffman tree")
+    /// One more than the largest symbol in the tree.
+    fn alphabet_size(&self) -> usize {
+        match self {
+            HuffmanNode::Leaf { symbol, .. } => *symbol as usize + 1,
+            HuffmanNode::Internal { left, right, .. } => {
+                left.alphabet_size().max(right.alphabet_size())
+            }
+        }
     }
 
     fn frequency(&self) -> u64 {
@@ -54,13 +71,32 @@ impl HuffmanNode {
         }
     }
 
-    pub fn to_byte_map(&self) -> ByteMap {
+    /// Builds the canonical codes for the tree, falling back to package-merge
+    /// when any code would be longer than `max_code_length` bits.
+    pub fn to_byte_map(&self, max_code_length: u8) -> ByteMap {
         let mut code_lengths = vec![];
         self.traverse(0, &mut code_lengths);
 
+        if code_lengths.iter().any(|code| code.len > max_code_length) {
+            let mut frequencies = Frequencies::with_alphabet_size(self.alphabet_size());
+            self.collect_frequencies(&mut frequencies);
+
+            code_lengths = package_merge::code_lengths(&frequencies, max_code_length);
+        }
+
         ByteMap::new(&mut code_lengths)
     }
 
+    fn collect_frequencies(&self, frequencies: &mut Frequencies) {
+        match self {
+            HuffmanNode::Internal { left, right, .. } => {
+                left.collect_frequencies(frequencies);
+                right.collect_frequencies(frequencies);
+            }
+            HuffmanNode::Leaf { symbol, fequency } => frequencies[*symbol as usize] = *fequency,
+        }
+    }
+
     fn traverse(&self, mut len: u8, codes: &mut Vec<CodeLength>) {
         match self {
             HuffmanNode::Internal {
@@ -72,8 +108,14 @@ impl HuffmanNode {
                 left.traverse(len, codes);
                 right.traverse(len, codes);
             }
-            HuffmanNode::Leaf { byte, fequency: _ } => {
-                codes.push(CodeLength { byte: *byte, len });
+            HuffmanNode::Leaf {
+                symbol,
+                fequency: _,
+            } => {
+                codes.push(CodeLength {
+                    symbol: *symbol,
+                    len,
+                });
             }
         }
     }
@@ -106,7 +148,7 @@ mod tests {
     #[test]
     fn test_leaf_frequency() {
         let leaf = HuffmanNode::Leaf {
-            byte: 42,
+            symbol: 42,
             fequency: 10,
         };
         assert_eq!(leaf.frequency(), 10);
@@ -115,11 +157,11 @@ mod tests {
     #[test]
     fn test_internal_frequency() {
         let left = HuffmanNode::Leaf {
-            byte: 1,
+            symbol: 1,
             fequency: 5,
         };
         let right = HuffmanNode::Leaf {
-            byte: 2,
+            symbol: 2,
             fequency: 15,
         };
         let internal = HuffmanNode::Internal {
@@ -139,11 +181,14 @@ mod tests {
         let root = HuffmanNode::from_frequencies(&freqs);
         assert_eq!(root.frequency(), 8);
 
-        fn find_leaf(node: &HuffmanNode, byte: u8) -> Option<u64> {
+        fn find_leaf(node: &HuffmanNode, symbol: Symbol) -> Option<u64> {
             match node {
-                HuffmanNode::Leaf { byte: b, fequency } if *b == byte => Some(*fequency),
+                HuffmanNode::Leaf {
+                    symbol: s,
+                    fequency,
+                } if *s == symbol => Some(*fequency),
                 HuffmanNode::Internal { left, right, .. } => {
-                    find_leaf(left, byte).or_else(|| find_leaf(right, byte))
+                    find_leaf(left, symbol).or_else(|| find_leaf(right, symbol))
                 }
                 _ => None,
             }
@@ -156,11 +201,11 @@ mod tests {
     #[test]
     fn test_ordering_of_nodes() {
         let leaf_small = HuffmanNode::Leaf {
-            byte: 0,
+            symbol: 0,
             fequency: 1,
         };
         let leaf_large = HuffmanNode::Leaf {
-            byte: 1,
+            symbol: 1,
             fequency: 10,
         };
 
@@ -174,6 +219,61 @@ mod tests {
         assert_eq!(second.frequency(), 10);
     }
 
+    fn fibonacci_frequencies(symbols: usize) -> Frequencies {
+        let mut freqs = Frequencies::new();
+        let (mut a, mut b) = (1u64, 1u64);
+        for count in freqs.iter_mut().take(symbols) {
+            *count = a;
+            (a, b) = (b, a + b);
+        }
+
+        freqs
+    }
+
+    #[test]
+    fn test_byte_map_respects_max_code_length() {
+        let freqs = fibonacci_frequencies(50);
+        let root = HuffmanNode::from_frequencies(&freqs);
+
+        let mut unlimited = vec![];
+        root.traverse(0, &mut unlimited);
+        assert!(unlimited.iter().any(|code| code.len > 32));
+
+        let byte_map = root.to_byte_map(15);
+        assert_eq!(byte_map.len(), 50);
+        assert!(byte_map.values().all(|code| code.len <= 15));
+    }
+
+    #[test]
+    fn test_byte_map_codes_are_prefix_free_when_limited() {
+        let byte_map = HuffmanNode::from_frequencies(&fibonacci_frequencies(30)).to_byte_map(8);
+
+        let codes: Vec<_> = byte_map.values().collect();
+        for a in &codes {
+            for b in &codes {
+                if std::ptr::eq(*a, *b) || a.len > b.len {
+                    continue;
+                }
+
+                assert_ne!(b.bit_pattern >> (b.len - a.len), a.bit_pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_map_keeps_optimal_lengths_within_limit() {
+        let mut freqs = Frequencies::new();
+        freqs[1] = 2;
+        freqs[2] = 3;
+        freqs[3] = 5;
+
+        let byte_map = HuffmanNode::from_frequencies(&freqs).to_byte_map(15);
+
+        assert_eq!(byte_map[&3].len, 1);
+        assert_eq!(byte_map[&1].len, 2);
+        assert_eq!(byte_map[&2].len, 2);
+    }
+
     #[test]
     fn test_tree_combination() {
         let mut freqs = Frequencies::new();
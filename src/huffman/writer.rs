@@ -0,0 +1,281 @@
+use crate::container::{CodecId, Header};
+use crate::error::Error;
+use crate::filter::{self, Pipeline};
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::block::BlockEncoder;
+use crate::huffman::{BYTE_ALPHABET_SIZE, Options, check_options};
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+
+enum State<W: Write> {
+    /// Nothing written yet: the header waits for the first block to fill,
+    /// so that an input that fits in one block records its length.
+    Pending(W),
+    Encoding(BlockEncoder<W>),
+    /// Finished, or abandoned after an error.
+    Done,
+}
+
+/// Compresses what is written to it into `W`, as `compress` does.
+///
+/// Input is buffered and coded a block at a time. `finish` codes the last
+/// block and writes the end of the stream; dropping the writer does the
+/// same, but ignores any error.
+pub struct HuffmanWriter<W: Write> {
+    state: State<W>,
+    options: Options,
+    filters: Pipeline,
+    /// Filtered input not yet coded.
+    block: Vec<u8>,
+}
+
+impl<W: Write> HuffmanWriter<W> {
+    /// Rejects `options` that `compress` would.
+    pub fn new(writer: W, options: Options) -> Result<Self, Error> {
+        check_options(
+            options.block_size,
+            options.max_code_length,
+            BYTE_ALPHABET_SIZE,
+        )?;
+
+        filter::check_filters(&options.filters)?;
+
+        Ok(HuffmanWriter {
+            state: State::Pending(writer),
+            filters: Pipeline::encoder(&options.filters),
+            block: Vec::new(),
+            options,
+        })
+    }
+
+    /// Writes the rest of the stream, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_stream()
+    }
+
+    fn finish_stream(&mut self) -> io::Result<W> {
+        self.filters.finish(&mut self.block)?;
+        self.encode_full_blocks()?;
+
+        if let State::Pending(_) = self.state {
+            self.start(Some(self.block.len() as u64))?;
+        }
+
+        let State::Encoding(mut encoder) = std::mem::replace(&mut self.state, State::Done) else {
+            return Err(finished());
+        };
+
+        if !self.block.is_empty() {
+            encoder.encode(&self.block)?;
+            self.block.clear();
+        }
+
+        encoder.finish()
+    }
+
+    /// Writes the header, which records `original_length` if it is known.
+    fn start(&mut self, original_length: Option<u64>) -> io::Result<()> {
+        let writer = match std::mem::replace(&mut self.state, State::Done) {
+            State::Pending(writer) => writer,
+            state => {
+                self.state = state;
+                return Ok(());
+            }
+        };
+
+        let mut writer = BitWriter::new(BufWriter::new(writer));
+        Header::new(CodecId::Huffman, self.options.checksum, original_length)
+            .with_filters(&self.options.filters)
+            .write_to(&mut writer.writer)?;
+
+        let checksum = self.options.checksum.new_checksum();
+        self.state = State::Encoding(BlockEncoder::new(
+            writer,
+            checksum,
+            self.options.max_code_length,
+        ));
+
+        Ok(())
+    }
+
+    /// Codes every full block that more input follows.
+    fn encode_full_blocks(&mut self) -> io::Result<()> {
+        let block_size = self.options.block_size;
+        if self.block.len() <= block_size {
+            return Ok(());
+        }
+
+        self.start(None)?;
+        let State::Encoding(encoder) = &mut self.state else {
+            return Err(finished());
+        };
+
+        let mut coded = 0;
+        while self.block.len() - coded > block_size {
+            encoder.encode(&self.block[coded..coded + block_size])?;
+            coded += block_size;
+        }
+        self.block.drain(..coded);
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HuffmanWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if let State::Done = self.state {
+            return Err(finished());
+        }
+
+        let len = bytes.len().min(self.options.block_size);
+        self.filters.push(&bytes[..len], &mut self.block)?;
+        self.encode_full_blocks()
+            .inspect_err(|_| self.state = State::Done)?;
+
+        Ok(len)
+    }
+
+    /// Codes the input buffered so far as a short block, so that all of it
+    /// can be decoded from what has been written, and flushes the output.
+    ///
+    /// Filters may still hold back a few bytes.
+    fn flush(&mut self) -> io::Result<()> {
+        self.start(None)?;
+        let State::Encoding(encoder) = &mut self.state else {
+            return Err(finished());
+        };
+
+        let mut result = Ok(());
+        if !self.block.is_empty() {
+            result = encoder.encode(&self.block);
+            self.block.clear();
+        }
+
+        result
+            .and_then(|()| encoder.flush())
+            .inspect_err(|_| self.state = State::Done)
+    }
+}
+
+impl<W: Write> Drop for HuffmanWriter<W> {
+    fn drop(&mut self) {
+        if !matches!(self.state, State::Done) {
+            let _ = self.finish_stream();
+        }
+    }
+}
+
+fn finished() -> io::Error {
+    io::Error::other("write to a finished or failed stream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChecksumKind;
+    use crate::filter::Filter;
+    use crate::huffman::compress;
+    use crate::test_support::{decompress_bytes, random_bytes, text};
+    use std::io::Cursor;
+
+    /// Fails every write, like a closed pipe.
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _bytes: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn matches_compress_for_any_write_sizes() {
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            block_size: 1000,
+            filters: vec![Filter::Delta { stride: 2 }],
+            ..Options::default()
+        };
+
+        for len in [0, 999, 1000, 1001, 10_000] {
+            let input = text(len, 0);
+
+            let mut expected = Vec::new();
+            compress(Cursor::new(&input), &mut expected, &options).unwrap();
+
+            for piece_len in [1, 7, 1000, 4096] {
+                let mut writer = HuffmanWriter::new(Vec::new(), options.clone()).unwrap();
+                for piece in input.chunks(piece_len) {
+                    writer.write_all(piece).unwrap();
+                }
+
+                assert_eq!(writer.finish().unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn flush_makes_written_input_decodable() {
+        let mut output = Vec::new();
+        let mut writer = HuffmanWriter::new(&mut output, Options::default()).unwrap();
+        writer.write_all(b"first line\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"second line\n").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            decompress_bytes(&output).unwrap(),
+            b"first line\nsecond line\n"
+        );
+    }
+
+    #[test]
+    fn drop_finishes_stream() {
+        let input = text(5000, 0);
+        let mut output = Vec::new();
+
+        {
+            let mut writer = HuffmanWriter::new(&mut output, Options::default()).unwrap();
+            writer.write_all(&input).unwrap();
+        }
+
+        assert_eq!(decompress_bytes(&output).unwrap(), input);
+    }
+
+    #[test]
+    fn errors_leave_the_writer_failed() {
+        let options = Options {
+            block_size: 1000,
+            ..Options::default()
+        };
+
+        // Enough stored blocks to fill the output buffer while writing.
+        let mut writer = HuffmanWriter::new(BrokenPipe, options.clone()).unwrap();
+        let error = writer.write_all(&random_bytes(100_000, 0)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        let error = writer.write(b"more").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+
+        let mut writer = HuffmanWriter::new(BrokenPipe, options).unwrap();
+        writer.write_all(b"short").unwrap();
+        let error = writer.flush().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        let error = writer.flush().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn new_rejects_invalid_options() {
+        let options = Options {
+            block_size: 0,
+            ..Options::default()
+        };
+
+        let error = HuffmanWriter::new(Vec::new(), options).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -1,19 +1,79 @@
+mod adaptive_huffman;
+mod ans;
+mod bwt;
+mod checksum;
+mod codec;
+mod container;
+mod context_huffman;
+pub mod deflate;
+mod error;
+mod filter;
+mod framing;
+pub mod gzip;
 mod huffman;
+mod lz77;
+pub mod lzw;
+mod options;
+mod range_coder;
+#[cfg(test)]
+mod test_support;
 
-use std::io::{self, prelude::*};
+pub use adaptive_huffman::Options as AdaptiveHuffmanOptions;
+pub use ans::Options as AnsOptions;
+pub use ans::Variant as AnsVariant;
+pub use bwt::Options as BwtOptions;
+pub use checksum::ChecksumKind;
+pub use codec::{Codec, codec_named, registry};
+pub use container::{CodecId, Header};
+pub use context_huffman::Options as ContextHuffmanOptions;
+pub use error::Error;
+pub use filter::Filter;
+pub use huffman::Options as HuffmanOptions;
+pub use huffman::max_compressed_len;
+pub use huffman::{HuffmanReader, HuffmanWriter};
+pub use lz77::Options as Lz77Options;
+pub use options::CompressOptions;
+pub use range_coder::Options as RangeCoderOptions;
 
-pub fn compress<R, W>(input: R, output: W) -> io::Result<()>
+use std::io::{BufReader, prelude::*};
+
+pub fn compress<R, W>(input: R, output: W) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    Ok(huffman::compress(
+        input,
+        output,
+        &HuffmanOptions::default(),
+    )?)
+}
+
+/// Compresses `input` with the codec and settings `options` selects.
+pub fn compress_with<R, W>(
+    mut input: R,
+    mut output: W,
+    options: &CompressOptions,
+) -> Result<(), Error>
 where
-    R: Read + Seek,
+    R: Read,
     W: Write,
 {
-    huffman::compress(input, output)
+    options.codec()?.compress(&mut input, &mut output)
 }
 
-pub fn decompress<R, W>(_input: R, _output: W) -> io::Result<()>
+/// Decompresses a stream written by any registered `Codec`; the codec and
+/// any filter to undo are read from the header.
+pub fn decompress<R, W>(input: R, output: W) -> Result<(), Error>
 where
-    R: Read + Seek,
+    R: Read,
     W: Write,
 {
-    todo!()
+    let mut reader = BufReader::new(input);
+    let header = Header::read_from(&mut reader)?;
+    let mut writer = filter::Decoder::new(output, &header.filters);
+
+    codec::codec_with_id(header.codec)?.decompress(&mut reader, &mut writer, &header)?;
+
+    Ok(writer.finish()?)
 }
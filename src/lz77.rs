@@ -0,0 +1,194 @@
+pub(crate) mod codes;
+mod decoder;
+mod encoder;
+pub(crate) mod match_finder;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{CodecId, Header};
+use crate::framing::{self, Frame};
+use crate::huffman::{self, DEFAULT_BLOCK_SIZE, DEFAULT_MAX_CODE_LENGTH};
+use crate::lz77::codes::LITERAL_LENGTH_ALPHABET_SIZE;
+use crate::lz77::decoder::Decoder;
+use crate::lz77::encoder::BlockEncoder;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+pub const DEFAULT_MAX_CHAIN_LENGTH: usize = 128;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and coded as one block.
+    pub block_size: usize,
+    /// Longest code, in bits, that a code table may assign to a symbol.
+    pub max_code_length: u8,
+    /// Most earlier positions compared when looking for a match.
+    pub max_chain_length: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_code_length: DEFAULT_MAX_CODE_LENGTH,
+            max_chain_length: DEFAULT_MAX_CHAIN_LENGTH,
+        }
+    }
+}
+
+/// Compresses `input` by replacing repeated strings with length/distance
+/// pairs that point up to 32 KiB back, then Huffman coding the literals,
+/// lengths and distances with DEFLATE's symbol ranges.
+///
+/// Each block is written as `framing` describes, stored or coded. Matches
+/// may refer to data in earlier blocks.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    huffman::check_options(
+        options.block_size,
+        options.max_code_length,
+        LITERAL_LENGTH_ALPHABET_SIZE,
+    )?;
+
+    let frame = Frame {
+        codec: CodecId::Lz77,
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+    let mut encoder = BlockEncoder::new(options.max_code_length, options.max_chain_length.max(1));
+
+    framing::compress(input, output, &frame, |writer, block| {
+        framing::write_smaller(writer, block, &encoder.encode(block)?)
+    })
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::Lz77)?;
+
+    let mut decoder = Decoder::default();
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        decoder.decode(reader, length, output)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::checksum::ChecksumKind;
+    use crate::container::{CodecId, Header};
+    use crate::error::Error;
+    use crate::framing::{BlockType, MAX_BLOCK_SIZE};
+    use crate::test_support::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_records_lz77_codec() {
+        let compressed = compress_bytes(&Options::default(), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.codec, CodecId::Lz77);
+        assert_eq!(header.original_length, Some(3));
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let options = Options::default();
+
+        assert_round_trip(&options, b"");
+        assert_round_trip(&options, b"a");
+        assert_round_trip(&options, b"abcabcabcabc");
+        assert_round_trip(&options, &[0; 100_000]);
+        assert_round_trip(&options, &(0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn repeated_strings_beat_plain_huffman() {
+        let input = log_lines(2000);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+        let mut huffman = Vec::new();
+        crate::compress(Cursor::new(&input), &mut huffman).unwrap();
+
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+        assert!(compressed.len() * 3 < huffman.len());
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn incompressible_block_is_stored() {
+        let input = random_bytes(4096, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Stored);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn matches_span_blocks() {
+        let input = log_lines(500);
+        let options = Options {
+            block_size: 1000,
+            checksum: ChecksumKind::Crc32,
+            ..Options::default()
+        };
+
+        let compressed = compress_bytes(&options, &input);
+
+        assert!(compressed.len() * 4 < input.len());
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn short_chains_still_round_trip() {
+        let options = Options {
+            max_chain_length: 1,
+            ..Options::default()
+        };
+
+        assert_round_trip(&options, &log_lines(300));
+    }
+
+    #[test]
+    fn rejects_code_length_limit_below_alphabet() {
+        assert_rejects_options(&Options {
+            max_code_length: 8,
+            ..Options::default()
+        });
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        assert_rejects_corrupted_data(&options, &log_lines(100));
+    }
+
+    #[test]
+    fn rejects_blocks_longer_than_the_limits() {
+        let compressed = compress_bytes(&Options::default(), b"abcabcabc");
+
+        for (original_length, block_length) in [(None, MAX_BLOCK_SIZE as u64 + 1), (Some(9), 10)] {
+            let patched = with_first_block_length(&compressed, original_length, block_length);
+            let error = decompress_bytes(&patched).unwrap_err();
+
+            assert!(matches!(Error::from(error), Error::LimitExceeded(_)));
+        }
+    }
+}
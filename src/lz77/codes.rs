@@ -0,0 +1,153 @@
+use crate::huffman::Symbol;
+
+/// Literal bytes, then one symbol per match length range.
+pub const LITERAL_LENGTH_ALPHABET_SIZE: usize = 256 + LENGTH_BASE.len();
+pub const DISTANCE_ALPHABET_SIZE: usize = DISTANCE_BASE.len();
+
+/// Shortest match worth a length/distance pair.
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 258;
+/// Farthest back a match may reach.
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+// The length and distance ranges of DEFLATE (RFC 1951, section 3.2.5).
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// A symbol together with the extra bits that narrow down its range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Code {
+    pub symbol: Symbol,
+    pub extra: u32,
+    pub extra_bits: u8,
+}
+
+pub fn length_code(length: usize) -> Code {
+    let index = range_index(&LENGTH_BASE, length);
+
+    Code {
+        symbol: (256 + index) as Symbol,
+        extra: (length - LENGTH_BASE[index] as usize) as u32,
+        extra_bits: LENGTH_EXTRA_BITS[index],
+    }
+}
+
+pub fn distance_code(distance: usize) -> Code {
+    let index = range_index(&DISTANCE_BASE, distance);
+
+    Code {
+        symbol: index as Symbol,
+        extra: (distance - DISTANCE_BASE[index] as usize) as u32,
+        extra_bits: DISTANCE_EXTRA_BITS[index],
+    }
+}
+
+/// The shortest length of a length symbol and the number of extra bits
+/// that follow it.
+pub fn length_range(symbol: Symbol) -> (usize, u8) {
+    let index = symbol as usize - 256;
+
+    (LENGTH_BASE[index] as usize, LENGTH_EXTRA_BITS[index])
+}
+
+/// The shortest distance of a distance symbol and the number of extra bits
+/// that follow it.
+pub fn distance_range(symbol: Symbol) -> (usize, u8) {
+    let index = symbol as usize;
+
+    (DISTANCE_BASE[index] as usize, DISTANCE_EXTRA_BITS[index])
+}
+
+fn range_index(bases: &[u16], value: usize) -> usize {
+    bases.partition_point(|&base| base as usize <= value) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_codes_match_deflate() {
+        assert_eq!(
+            length_code(3),
+            Code {
+                symbol: 256,
+                extra: 0,
+                extra_bits: 0
+            }
+        );
+        assert_eq!(
+            length_code(12),
+            Code {
+                symbol: 264,
+                extra: 1,
+                extra_bits: 1
+            }
+        );
+        assert_eq!(
+            length_code(257),
+            Code {
+                symbol: 283,
+                extra: 30,
+                extra_bits: 5
+            }
+        );
+        assert_eq!(length_code(258).symbol, 284);
+    }
+
+    #[test]
+    fn distance_codes_match_deflate() {
+        assert_eq!(distance_code(1).symbol, 0);
+        assert_eq!(
+            distance_code(6),
+            Code {
+                symbol: 4,
+                extra: 1,
+                extra_bits: 1
+            }
+        );
+        assert_eq!(
+            distance_code(WINDOW_SIZE),
+            Code {
+                symbol: 29,
+                extra: 8191,
+                extra_bits: 13
+            }
+        );
+    }
+
+    #[test]
+    fn every_length_and_distance_round_trips() {
+        for length in MIN_MATCH..=MAX_MATCH {
+            let code = length_code(length);
+            let (base, extra_bits) = length_range(code.symbol);
+
+            assert_eq!(extra_bits, code.extra_bits);
+            assert!(code.extra < 1 << extra_bits);
+            assert_eq!(base + code.extra as usize, length);
+        }
+
+        for distance in 1..=WINDOW_SIZE {
+            let code = distance_code(distance);
+            let (base, extra_bits) = distance_range(code.symbol);
+
+            assert_eq!(extra_bits, code.extra_bits);
+            assert!(code.extra < 1 << extra_bits);
+            assert_eq!(base + code.extra as usize, distance);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::Error;
+use crate::framing::{self, BlockType, Output};
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::decode_table::DecodeTable;
+use crate::huffman::packed_table;
+use crate::lz77::codes::{self, DISTANCE_ALPHABET_SIZE, LITERAL_LENGTH_ALPHABET_SIZE, WINDOW_SIZE};
+use std::io;
+use std::io::prelude::*;
+
+const STORED_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Decodes blocks, keeping enough of the earlier ones for their matches.
+#[derive(Default)]
+pub struct Decoder {
+    /// The last `WINDOW_SIZE` bytes of earlier blocks, then the current one.
+    window: Vec<u8>,
+}
+
+impl Decoder {
+    /// Decodes the block of `block_length` bytes that follows in `reader`
+    /// and writes it to `output`.
+    pub fn decode<R, W>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        block_length: u64,
+        output: &mut Output<W>,
+    ) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+    {
+        let start = self.window.len();
+
+        match framing::read_block_type(reader)? {
+            BlockType::Stored => self.decode_stored(reader, block_length)?,
+            BlockType::Coded => self.decode_coded(reader, block_length)?,
+        }
+
+        reader.align_to_byte();
+        output.write_all(&self.window[start..])?;
+
+        let keep = self.window.len().min(WINDOW_SIZE);
+        self.window.drain(..self.window.len() - keep);
+
+        Ok(())
+    }
+
+    fn decode_stored<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        block_length: u64,
+    ) -> io::Result<()> {
+        let mut remaining = block_length;
+
+        while remaining > 0 {
+            let chunk_len = remaining.min(STORED_CHUNK_SIZE as u64) as usize;
+            let start = self.window.len();
+
+            self.window.resize(start + chunk_len, 0);
+            reader.read_bytes(&mut self.window[start..])?;
+            remaining -= chunk_len as u64;
+        }
+
+        Ok(())
+    }
+
+    fn decode_coded<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        block_length: u64,
+    ) -> io::Result<()> {
+        let literal_lengths = DecodeTable::new(&mut packed_table::read_code_lengths(
+            reader,
+            LITERAL_LENGTH_ALPHABET_SIZE,
+        )?)?;
+        let distances = DecodeTable::new(&mut packed_table::read_code_lengths(
+            reader,
+            DISTANCE_ALPHABET_SIZE,
+        )?)?;
+
+        let end = self.window.len() as u64 + block_length;
+
+        while (self.window.len() as u64) < end {
+            let symbol = literal_lengths.decode(reader)?;
+
+            if symbol < 256 {
+                self.window.push(symbol as u8);
+                continue;
+            }
+
+            let (base, extra_bits) = codes::length_range(symbol);
+            let length = base + reader.read_bits(extra_bits)? as usize;
+
+            let (base, extra_bits) = codes::distance_range(distances.decode(reader)?);
+            let distance = base + reader.read_bits(extra_bits)? as usize;
+
+            if distance > self.window.len() {
+                return Err(invalid_data(
+                    "match reaches back before the start of the stream",
+                ));
+            }
+
+            if self.window.len() as u64 + length as u64 > end {
+                return Err(invalid_data("match runs past the end of the block"));
+            }
+
+            // The source may overlap the bytes being written, as in a run.
+            let from = self.window.len() - distance;
+            for i in from..from + length {
+                self.window.push(self.window[i]);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    Error::CorruptData(message.into()).into()
+}
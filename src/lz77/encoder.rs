@@ -0,0 +1,108 @@
+use crate::error::Error;
+use crate::huffman::bit_writer::BitWriter;
+use crate::huffman::byte_map::{ByteMap, CodeLength};
+use crate::huffman::frequency::Frequencies;
+use crate::huffman::packed_table::PackedTable;
+use crate::lz77::codes::{self, Code, DISTANCE_ALPHABET_SIZE, LITERAL_LENGTH_ALPHABET_SIZE};
+use crate::lz77::match_finder::{MatchFinder, Token};
+use std::io;
+use std::io::prelude::*;
+
+/// Codes blocks as Huffman coded LZ77 tokens, finding matches in earlier
+/// blocks as well.
+pub struct BlockEncoder {
+    max_code_length: u8,
+    match_finder: MatchFinder,
+}
+
+impl BlockEncoder {
+    pub fn new(max_code_length: u8, max_chain_length: usize) -> Self {
+        BlockEncoder {
+            max_code_length,
+            match_finder: MatchFinder::new(max_chain_length),
+        }
+    }
+
+    /// Returns the code tables and coded tokens of `block`, padded to a
+    /// byte.
+    pub fn encode(&mut self, block: &[u8]) -> io::Result<Vec<u8>> {
+        let tokens = self.match_finder.tokenize(block);
+        let codes: Vec<(Code, Option<Code>)> =
+            tokens.iter().map(|&token| to_codes(token)).collect();
+
+        let mut literal_lengths = Frequencies::with_alphabet_size(LITERAL_LENGTH_ALPHABET_SIZE);
+        let mut distances = Frequencies::with_alphabet_size(DISTANCE_ALPHABET_SIZE);
+
+        for (literal_length, distance) in &codes {
+            literal_lengths[literal_length.symbol as usize] += 1;
+
+            if let Some(distance) = distance {
+                distances[distance.symbol as usize] += 1;
+            }
+        }
+
+        let literal_length_map = literal_lengths
+            .to_huff_tree()
+            .to_byte_map(self.max_code_length);
+        let distance_map = if distances.iter().all(|&count| count == 0) {
+            // A block without matches still sends a (one code) distance table.
+            ByteMap::new(&mut [CodeLength { symbol: 0, len: 1 }])
+        } else {
+            distances.to_huff_tree().to_byte_map(self.max_code_length)
+        };
+
+        let literal_length_table =
+            PackedTable::new(&literal_length_map, LITERAL_LENGTH_ALPHABET_SIZE);
+        let distance_table = PackedTable::new(&distance_map, DISTANCE_ALPHABET_SIZE);
+
+        let mut writer = BitWriter::new(Vec::new());
+        literal_length_table.write_to(&mut writer)?;
+        distance_table.write_to(&mut writer)?;
+
+        for (literal_length, distance) in codes {
+            write_code(&mut writer, &literal_length_map, literal_length)?;
+
+            if let Some(distance) = distance {
+                write_code(&mut writer, &distance_map, distance)?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(writer.writer)
+    }
+}
+
+/// The literal/length code of a token and, for a match, its distance code.
+fn to_codes(token: Token) -> (Code, Option<Code>) {
+    match token {
+        Token::Literal(byte) => (
+            Code {
+                symbol: byte.into(),
+                extra: 0,
+                extra_bits: 0,
+            },
+            None,
+        ),
+        Token::Match { length, distance } => (
+            codes::length_code(length),
+            Some(codes::distance_code(distance)),
+        ),
+    }
+}
+
+fn write_code<W: Write>(
+    writer: &mut BitWriter<W>,
+    byte_map: &ByteMap,
+    code: Code,
+) -> io::Result<()> {
+    let entry = byte_map
+        .get(&code.symbol)
+        .ok_or_else(|| Error::CorruptTable(format!("no code for symbol {}", code.symbol)))?;
+    writer.write_bits(entry.bit_pattern, entry.len)?;
+
+    if code.extra_bits > 0 {
+        writer.write_bits(code.extra, code.extra_bits)?;
+    }
+
+    Ok(())
+}
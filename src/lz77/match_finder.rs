@@ -0,0 +1,254 @@
+use crate::lz77::codes::{MAX_MATCH, MIN_MATCH, WINDOW_SIZE};
+
+const HASH_BITS: u32 = 15;
+const NO_POSITION: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    /// A copy of `length` bytes starting `distance` bytes back.
+    Match {
+        length: usize,
+        distance: usize,
+    },
+}
+
+/// Finds repeated strings with hash chains: every position is filed under a
+/// hash of its first `MIN_MATCH` bytes, linked to the previous position with
+/// the same hash.
+///
+/// Matches may reach back into earlier blocks, up to `WINDOW_SIZE` bytes.
+pub struct MatchFinder {
+    max_chain_length: usize,
+    history: Vec<u8>,
+}
+
+impl MatchFinder {
+    /// `max_chain_length` bounds the candidates compared per position,
+    /// trading ratio for speed.
+    pub fn new(max_chain_length: usize) -> Self {
+        MatchFinder {
+            max_chain_length,
+            history: Vec::with_capacity(WINDOW_SIZE),
+        }
+    }
+
+    /// Greedily splits `block` into literals and matches.
+    pub fn tokenize(&mut self, block: &[u8]) -> Vec<Token> {
+        let start = self.history.len();
+        let mut data = std::mem::take(&mut self.history);
+        data.extend_from_slice(block);
+
+        let mut chains = HashChains::new(&data);
+        for position in 0..start {
+            chains.insert(position);
+        }
+
+        let mut tokens = vec![];
+        let mut position = start;
+
+        while position < data.len() {
+            let (length, distance) = chains.longest_match(position, self.max_chain_length);
+
+            if length >= MIN_MATCH {
+                tokens.push(Token::Match { length, distance });
+            } else {
+                tokens.push(Token::Literal(data[position]));
+            }
+
+            let end = position + length.max(1);
+            while position < end {
+                chains.insert(position);
+                position += 1;
+            }
+        }
+
+        let keep = data.len().min(WINDOW_SIZE);
+        data.drain(..data.len() - keep);
+        self.history = data;
+
+        tokens
+    }
+}
+
+struct HashChains<'a> {
+    data: &'a [u8],
+    /// The most recent position for each hash.
+    head: Vec<u32>,
+    /// The previous position with the same hash, for each position.
+    previous: Vec<u32>,
+}
+
+impl<'a> HashChains<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        HashChains {
+            data,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            previous: vec![NO_POSITION; data.len()],
+        }
+    }
+
+    fn hash(&self, position: usize) -> Option<usize> {
+        let bytes = self.data.get(position..position + MIN_MATCH)?;
+        let key = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+
+        Some((key.wrapping_mul(0x9E37_79B1) >> (u32::BITS - HASH_BITS)) as usize)
+    }
+
+    fn insert(&mut self, position: usize) {
+        if let Some(hash) = self.hash(position) {
+            self.previous[position] = self.head[hash];
+            self.head[hash] = position as u32;
+        }
+    }
+
+    /// The longest earlier match for the bytes at `position` as
+    /// `(length, distance)`, or a length of zero if there is none.
+    fn longest_match(&self, position: usize, max_chain_length: usize) -> (usize, usize) {
+        let Some(hash) = self.hash(position) else {
+            return (0, 0);
+        };
+
+        let max_length = (self.data.len() - position).min(MAX_MATCH);
+        let target = &self.data[position..position + max_length];
+
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash];
+
+        for _ in 0..max_chain_length {
+            if candidate == NO_POSITION || position - candidate as usize > WINDOW_SIZE {
+                break;
+            }
+
+            let candidate_position = candidate as usize;
+            let length = target
+                .iter()
+                .zip(&self.data[candidate_position..])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if length > best.0 {
+                best = (length, position - candidate_position);
+
+                if length == max_length {
+                    break;
+                }
+            }
+
+            candidate = self.previous[candidate_position];
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(tokens: &[Token], history: &[u8]) -> Vec<u8> {
+        let mut output = history.to_vec();
+
+        for &token in tokens {
+            match token {
+                Token::Literal(byte) => output.push(byte),
+                Token::Match { length, distance } => {
+                    for _ in 0..length {
+                        output.push(output[output.len() - distance]);
+                    }
+                }
+            }
+        }
+
+        output.split_off(history.len())
+    }
+
+    #[test]
+    fn short_input_is_literals() {
+        let tokens = MatchFinder::new(16).tokenize(b"abc");
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c')
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_string_becomes_a_match() {
+        let tokens = MatchFinder::new(16).tokenize(b"abcdabcd");
+
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(
+            tokens[4],
+            Token::Match {
+                length: 4,
+                distance: 4
+            }
+        );
+    }
+
+    #[test]
+    fn run_overlaps_its_own_output() {
+        let tokens = MatchFinder::new(16).tokenize(&[b'x'; 100]);
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(b'x'),
+                Token::Match {
+                    length: 99,
+                    distance: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_reach_into_previous_blocks() {
+        let mut finder = MatchFinder::new(16);
+        finder.tokenize(b"hello, world");
+
+        let tokens = finder.tokenize(b"hello, world");
+
+        assert_eq!(
+            tokens,
+            [Token::Match {
+                length: 12,
+                distance: 12
+            }]
+        );
+    }
+
+    #[test]
+    fn matches_stay_within_window() {
+        let mut finder = MatchFinder::new(16);
+        finder.tokenize(b"0123456789");
+        finder.tokenize(&vec![b'-'; WINDOW_SIZE]);
+
+        let tokens = finder.tokenize(b"0123456789");
+
+        assert!(
+            tokens
+                .iter()
+                .all(|token| matches!(token, Token::Literal(_)))
+        );
+    }
+
+    #[test]
+    fn tokens_expand_to_input() {
+        let text = b"one two three, one two three four, two three four five";
+        let input: Vec<u8> = text.iter().cycle().take(5000).copied().collect();
+
+        let mut finder = MatchFinder::new(32);
+        let first = finder.tokenize(&input[..3000]);
+        let second = finder.tokenize(&input[3000..]);
+
+        assert_eq!(expand(&first, b""), input[..3000]);
+        assert_eq!(expand(&second, &input[..3000]), input[3000..]);
+        assert!(first.len() + second.len() < 200);
+    }
+}
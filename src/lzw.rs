@@ -0,0 +1,331 @@
+//! LZW streams with variable-width codes, as found in TIFF and PDF files.
+//!
+//! Codes are packed most significant bit first. Codes 0 to 255 stand for
+//! single bytes, 256 clears the dictionary and 257 ends the stream. Codes
+//! start 9 bits wide and widen by a bit each time the dictionary outgrows
+//! them, up to `Options::max_code_width`.
+
+mod decoder;
+mod encoder;
+
+use crate::error::Error;
+use crate::huffman::bit_reader::BitReader;
+use crate::huffman::bit_writer::BitWriter;
+use crate::lzw::encoder::Encoder;
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+
+pub const MIN_CODE_WIDTH: u8 = 9;
+pub const MAX_CODE_WIDTH: u8 = 16;
+/// The widest code TIFF and PDF readers accept.
+pub const DEFAULT_MAX_CODE_WIDTH: u8 = 12;
+
+/// Resets the dictionary to the single bytes.
+const CLEAR: u32 = 256;
+const END: u32 = 257;
+/// The first code given to a string of more than one byte.
+const FIRST_CODE: u32 = 258;
+
+/// Input bytes between checks of the compression ratio, as in the Unix
+/// `compress` tool.
+const RATIO_CHECK_INTERVAL: u64 = 10_000;
+
+/// When the encoder clears a full dictionary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetPolicy {
+    /// As soon as it fills, as TIFF encoders do.
+    #[default]
+    WhenFull,
+    /// Never: the full dictionary is used to the end of the stream.
+    Never,
+    /// Once the compression ratio drops below the best seen since the
+    /// dictionary filled, as the Unix `compress` tool does.
+    OnRatioDrop,
+}
+
+/// Settings for `compress` and `decompress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Widest code in bits, from 9 to 16.
+    pub max_code_width: u8,
+    /// Widen codes one code before the dictionary outgrows them, as TIFF
+    /// and PDF (`EarlyChange` 1) streams do, rather than at the point it
+    /// does, as GIF and `compress` streams do.
+    pub early_change: bool,
+    /// Ignored by `decompress`, which follows clear codes wherever they
+    /// appear.
+    pub reset: ResetPolicy,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            max_code_width: DEFAULT_MAX_CODE_WIDTH,
+            early_change: true,
+            reset: ResetPolicy::default(),
+        }
+    }
+}
+
+impl Options {
+    /// The number of codes, single bytes included, in a full dictionary.
+    /// With early change the last two codes of the widest width go unused,
+    /// as in TIFF.
+    fn code_limit(&self) -> u32 {
+        (1 << self.max_code_width) - 2 * self.early_change as u32
+    }
+
+    /// The width of codes written while `next_code` is the next code to
+    /// be given to a string.
+    fn code_width(&self, next_code: u32) -> u8 {
+        let width = u32::BITS - (next_code + self.early_change as u32).leading_zeros();
+
+        (width as u8).clamp(MIN_CODE_WIDTH, self.max_code_width)
+    }
+}
+
+fn check_options(options: &Options) -> io::Result<()> {
+    if !(MIN_CODE_WIDTH..=MAX_CODE_WIDTH).contains(&options.max_code_width) {
+        return Err(Error::InvalidOptions(format!(
+            "max code width must be between {MIN_CODE_WIDTH} and {MAX_CODE_WIDTH}"
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Compresses `input` into an LZW stream that starts with a clear code and
+/// ends with the end code, padded with zeros to a whole byte.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    check_options(options)?;
+
+    let mut reader = BufReader::new(input);
+    let mut writer = BitWriter::new(BufWriter::new(output));
+    let mut encoder = Encoder::new(&mut writer, options)?;
+
+    loop {
+        let bytes = reader.fill_buf()?;
+        if bytes.is_empty() {
+            break;
+        }
+
+        let read = bytes.len();
+        encoder.encode(bytes)?;
+        reader.consume(read);
+    }
+
+    encoder.finish()?;
+
+    writer.flush()?;
+    Ok(writer.writer.flush()?)
+}
+
+/// Decompresses an LZW stream written with the same code width settings,
+/// rejecting any data after its end code.
+pub fn decompress<R, W>(input: R, output: W, options: &Options) -> Result<(), Error>
+where
+    R: Read,
+    W: Write,
+{
+    check_options(options)?;
+
+    let mut reader = BitReader::new(BufReader::new(input));
+    let mut writer = BufWriter::new(output);
+
+    decoder::decode(&mut reader, &mut writer, options)?;
+
+    reader.align_to_byte();
+    if reader.remaining_bits()? != Some(0) {
+        return Err(Error::CorruptData(
+            "unexpected data after end of stream".into(),
+        ));
+    }
+
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{compress_with, decompress_with, random_bytes, text};
+    use std::io::Cursor;
+
+    /// The example of the PDF reference, section 3.3.3: the codes
+    /// 256 45 258 258 65 259 66 257, 9 bits each.
+    const PDF_EXAMPLE: &[u8] = b"-----A---B";
+    const PDF_EXAMPLE_CODED: [u8; 9] = [0x80, 0x0b, 0x60, 0x50, 0x22, 0x0c, 0x0c, 0x85, 0x01];
+
+    fn pack(input: &[u8], options: &Options) -> Vec<u8> {
+        compress_with(input, |input, output| compress(input, output, options))
+    }
+
+    fn unpack(input: &[u8], options: &Options) -> io::Result<Vec<u8>> {
+        decompress_with(input, |input, output| decompress(input, output, options))
+    }
+
+    /// Round trips `input` and returns its compressed length.
+    fn packed_len(input: &[u8], options: &Options) -> usize {
+        let compressed = pack(input, options);
+        assert_eq!(unpack(&compressed, options).unwrap(), input);
+
+        compressed.len()
+    }
+
+    #[test]
+    fn compresses_pdf_example() {
+        let compressed = pack(PDF_EXAMPLE, &Options::default());
+
+        assert_eq!(compressed, PDF_EXAMPLE_CODED);
+    }
+
+    #[test]
+    fn decompresses_pdf_example() {
+        let output = unpack(&PDF_EXAMPLE_CODED, &Options::default()).unwrap();
+
+        assert_eq!(output, PDF_EXAMPLE);
+    }
+
+    #[test]
+    fn empty_input_is_clear_and_end_codes() {
+        let compressed = pack(b"", &Options::default());
+
+        // 256 and 257 in 9 bits each, then padding.
+        assert_eq!(compressed, [0x80, 0x40, 0x40]);
+        assert_eq!(unpack(&compressed, &Options::default()).unwrap(), b"");
+    }
+
+    #[test]
+    fn round_trips_every_width_and_policy() {
+        let input = [text(30_000, 1), random_bytes(5_000, 2), vec![7; 20_000]].concat();
+
+        for max_code_width in MIN_CODE_WIDTH..=MAX_CODE_WIDTH {
+            for early_change in [false, true] {
+                for reset in [
+                    ResetPolicy::WhenFull,
+                    ResetPolicy::Never,
+                    ResetPolicy::OnRatioDrop,
+                ] {
+                    let options = Options {
+                        max_code_width,
+                        early_change,
+                        reset,
+                    };
+
+                    packed_len(&input, &options);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_long_repeats() {
+        // Strings one byte longer than the last, which exercises codes used
+        // as soon as they are made.
+        let options = Options {
+            max_code_width: MAX_CODE_WIDTH,
+            ..Options::default()
+        };
+
+        packed_len(&[b'x'; 100_000], &options);
+        packed_len(b"abababababababababab", &options);
+    }
+
+    #[test]
+    fn wider_codes_compress_text_better() {
+        let input = text(200_000, 3);
+
+        let narrow = packed_len(&input, &Options::default());
+        let wide = packed_len(
+            &input,
+            &Options {
+                max_code_width: MAX_CODE_WIDTH,
+                ..Options::default()
+            },
+        );
+
+        assert!(wide < narrow);
+        assert!(wide < input.len() / 3);
+    }
+
+    #[test]
+    fn ratio_drop_clears_dictionary_of_stale_strings() {
+        // The strings learnt from the first text are no use for the second.
+        let input = [text(100_000, 4), text(100_000, 5)].concat();
+        let options = |reset| Options {
+            reset,
+            ..Options::default()
+        };
+
+        let never = packed_len(&input, &options(ResetPolicy::Never));
+        let on_ratio_drop = packed_len(&input, &options(ResetPolicy::OnRatioDrop));
+
+        assert!(on_ratio_drop < never);
+    }
+
+    #[test]
+    fn decoder_needs_matching_code_widths() {
+        let input = text(10_000, 6);
+        let compressed = pack(&input, &Options::default());
+        let gif_style = Options {
+            early_change: false,
+            ..Options::default()
+        };
+
+        assert_ne!(unpack(&compressed, &gif_style).ok(), Some(input));
+    }
+
+    #[test]
+    fn rejects_code_not_in_dictionary() {
+        // 256, then 300 before any string has been added.
+        let mut writer = BitWriter::new(vec![]);
+        writer.write_bits(CLEAR, 9).unwrap();
+        writer.write_bits(300, 9).unwrap();
+        writer.write_bits(END, 9).unwrap();
+        writer.flush().unwrap();
+
+        let error = unpack(&writer.writer, &Options::default()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_missing_end_code() {
+        let compressed = pack(&text(1_000, 7), &Options::default());
+
+        let error = unpack(&compressed[..compressed.len() - 2], &Options::default()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_data_after_end_code() {
+        let mut compressed = pack(b"abc", &Options::default());
+        compressed.push(0);
+
+        let error = unpack(&compressed, &Options::default()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_invalid_code_width() {
+        for max_code_width in [8, 17] {
+            let options = Options {
+                max_code_width,
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            let error = compress(Cursor::new(b"abc"), &mut output, &options).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+            let error = unpack(&PDF_EXAMPLE_CODED, &options).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::error::Error;
+use crate::huffman::bit_reader::BitReader;
+use crate::lzw::{CLEAR, END, FIRST_CODE, Options};
+use std::io::{self, Read, Write};
+
+/// Decodes codes from `reader` up to and including the end code.
+///
+/// The decoder adds each string a code after the encoder does, since it
+/// needs the first byte of the next string to complete it, so it reads each
+/// code with the width the encoder had one string later.
+pub fn decode<R, W>(reader: &mut BitReader<R>, writer: &mut W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let limit = options.code_limit();
+
+    // The code of each string without its last byte, its last byte and its
+    // first byte.
+    let mut prefixes = vec![0u32; limit as usize];
+    let mut last_bytes: Vec<u8> = (0..limit).map(|code| code as u8).collect();
+    let mut first_bytes = last_bytes.clone();
+
+    let mut next_code = FIRST_CODE;
+    let mut previous: Option<u32> = None;
+    let mut string = Vec::new();
+
+    loop {
+        let encoder_next_code = match previous {
+            Some(_) => (next_code + 1).min(limit),
+            None => next_code,
+        };
+        let code = reader.read_bits(options.code_width(encoder_next_code))?;
+
+        match code {
+            CLEAR => {
+                next_code = FIRST_CODE;
+                previous = None;
+                continue;
+            }
+            END => return Ok(()),
+            _ => {}
+        }
+
+        let Some(previous_code) = previous.replace(code) else {
+            if code >= CLEAR {
+                return Err(not_in_dictionary());
+            }
+
+            writer.write_all(&[code as u8])?;
+            continue;
+        };
+
+        if next_code < limit {
+            // A code used as soon as it is made starts like the previous
+            // string, which is also its first part.
+            let first_byte = if code < next_code {
+                first_bytes[code as usize]
+            } else if code == next_code {
+                first_bytes[previous_code as usize]
+            } else {
+                return Err(not_in_dictionary());
+            };
+
+            prefixes[next_code as usize] = previous_code;
+            last_bytes[next_code as usize] = first_byte;
+            first_bytes[next_code as usize] = first_bytes[previous_code as usize];
+            next_code += 1;
+        } else if code >= next_code {
+            return Err(not_in_dictionary());
+        }
+
+        string.clear();
+        let mut code = code;
+        while code >= FIRST_CODE {
+            string.push(last_bytes[code as usize]);
+            code = prefixes[code as usize];
+        }
+        string.push(code as u8);
+        string.reverse();
+
+        writer.write_all(&string)?;
+    }
+}
+
+fn not_in_dictionary() -> io::Error {
+    Error::CorruptData("LZW code not in dictionary".into()).into()
+}
@@ -0,0 +1,134 @@
+use crate::huffman::bit_writer::BitWriter;
+use crate::lzw::{CLEAR, END, FIRST_CODE, Options, RATIO_CHECK_INTERVAL, ResetPolicy};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Codes input as it arrives, carrying the string matched so far from one
+/// call to `encode` to the next.
+pub struct Encoder<'a, W: Write> {
+    writer: &'a mut BitWriter<W>,
+    options: &'a Options,
+    /// The code of each string of more than one byte, by the code of the
+    /// string without its last byte and that byte.
+    dictionary: HashMap<(u32, u8), u32>,
+    next_code: u32,
+    /// The code of the string that the input since the last code matches.
+    current: Option<u32>,
+    /// Input bytes and output bits since the dictionary was last cleared.
+    bytes_in: u64,
+    bits_out: u64,
+    /// When `ResetPolicy::OnRatioDrop` next compares the ratio with the
+    /// best one, as bytes in and bits out.
+    next_check: u64,
+    best_ratio: Option<(u64, u64)>,
+}
+
+impl<'a, W: Write> Encoder<'a, W> {
+    /// Starts the stream with a clear code.
+    pub fn new(writer: &'a mut BitWriter<W>, options: &'a Options) -> io::Result<Self> {
+        let mut encoder = Encoder {
+            writer,
+            options,
+            dictionary: HashMap::new(),
+            next_code: FIRST_CODE,
+            current: None,
+            bytes_in: 0,
+            bits_out: 0,
+            next_check: 0,
+            best_ratio: None,
+        };
+        encoder.write_code(CLEAR)?;
+
+        Ok(encoder)
+    }
+
+    pub fn encode(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &byte in bytes {
+            self.bytes_in += 1;
+
+            let Some(current) = self.current else {
+                self.current = Some(byte as u32);
+                continue;
+            };
+
+            if let Some(&code) = self.dictionary.get(&(current, byte)) {
+                self.current = Some(code);
+                continue;
+            }
+
+            self.write_code(current)?;
+            if self.next_code < self.options.code_limit() {
+                self.dictionary.insert((current, byte), self.next_code);
+                self.next_code += 1;
+            }
+            self.current = Some(byte as u32);
+
+            if self.should_clear() {
+                self.write_code(CLEAR)?;
+                self.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the code of the last string and the end code.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(current) = self.current {
+            self.write_code(current)?;
+
+            // The decoder widens its codes as if the last code had added a
+            // string, as TIFF encoders do.
+            if self.next_code < self.options.code_limit() {
+                self.next_code += 1;
+            }
+        }
+
+        self.write_code(END)
+    }
+
+    fn write_code(&mut self, code: u32) -> io::Result<()> {
+        let width = self.options.code_width(self.next_code);
+        self.bits_out += width as u64;
+
+        self.writer.write_bits(code, width)
+    }
+
+    fn should_clear(&mut self) -> bool {
+        if self.next_code < self.options.code_limit() {
+            return false;
+        }
+
+        match self.options.reset {
+            ResetPolicy::WhenFull => true,
+            ResetPolicy::Never => false,
+            ResetPolicy::OnRatioDrop => {
+                if self.bytes_in < self.next_check {
+                    return false;
+                }
+                self.next_check = self.bytes_in + RATIO_CHECK_INTERVAL;
+
+                match self.best_ratio {
+                    Some((best_in, best_out))
+                        if self.bytes_in * best_out < best_in * self.bits_out =>
+                    {
+                        true
+                    }
+                    _ => {
+                        self.best_ratio = Some((self.bytes_in, self.bits_out));
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.dictionary.clear();
+        self.next_code = FIRST_CODE;
+        self.bytes_in = 0;
+        self.bits_out = 0;
+        self.next_check = 0;
+        self.best_ratio = None;
+    }
+}
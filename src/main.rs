@@ -1,8 +1,11 @@
-use clap::{Parser, Subcommand};
-use compressor::{compress, decompress};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use compressor::{
+    ChecksumKind, CompressOptions, Error, Filter, codec_named, compress_with, decompress, gzip,
+};
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter};
-use std::path::PathBuf; // import your lib functions
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 /// Simple file compressor CLI
 #[derive(Parser)]
@@ -12,55 +15,263 @@ struct Cli {
     command: Commands,
 }
 
+/// A registered codec, by the name `codec_named` knows it by.
+#[derive(Clone, Copy, ValueEnum)]
+enum Codec {
+    /// Huffman coding of single bytes
+    Huffman,
+    /// Huffman coding with tables chosen by the previous byte
+    ContextHuffman,
+    /// LZ77 string matching followed by Huffman coding
+    Lz77,
+    /// Single-pass Huffman coding that adapts to the input
+    AdaptiveHuffman,
+    /// Range coding of single bytes, closer to their entropy than Huffman
+    RangeCoder,
+    /// rANS coding of single bytes, as close as range coding but faster
+    Rans,
+    /// Table-driven tANS (FSE) coding of single bytes
+    Tans,
+    /// Burrows–Wheeler transform, move-to-front and Huffman coding, like bzip2
+    Bwt,
+}
+
+/// A checksum to store in the stream trailer.
+#[derive(Clone, Copy, ValueEnum)]
+enum Checksum {
+    /// No checksum
+    None,
+    /// CRC-32, as gzip uses
+    Crc32,
+    /// 64-bit xxHash, faster than CRC-32
+    Xxhash64,
+}
+
+impl From<Checksum> for ChecksumKind {
+    fn from(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::None => ChecksumKind::None,
+            Checksum::Crc32 => ChecksumKind::Crc32,
+            Checksum::Xxhash64 => ChecksumKind::XxHash64,
+        }
+    }
+}
+
+/// A standard format to read or write instead of this crate's own.
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// gzip file (RFC 1952)
+    Gzip,
+    /// zlib stream (RFC 1950)
+    Zlib,
+}
+
+impl From<Format> for gzip::Format {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Gzip => gzip::Format::Gzip,
+            Format::Zlib => gzip::Format::Zlib,
+        }
+    }
+}
+
+/// Parses `rle`, `delta:N`, `xor:N` or `shuffle:N`.
+fn parse_filter(value: &str) -> Result<Filter, String> {
+    let (name, parameter) = match value.split_once(':') {
+        Some((name, parameter)) => {
+            let parameter = parameter
+                .parse()
+                .map_err(|_| format!("`{parameter}` is not a byte count from 0 to 255"))?;
+            (name, Some(parameter))
+        }
+        None => (value, None),
+    };
+
+    match (name, parameter) {
+        ("rle", None) => Ok(Filter::Rle),
+        ("delta", Some(stride)) => Ok(Filter::Delta { stride }),
+        ("xor", Some(stride)) => Ok(Filter::Xor { stride }),
+        ("shuffle", Some(width)) => Ok(Filter::Shuffle { width }),
+        ("delta" | "xor" | "shuffle", None) => {
+            Err(format!("`{name}` needs a width, as in `{name}:4`"))
+        }
+        ("rle", Some(_)) => Err("`rle` takes no width".into()),
+        _ => Err(format!("unknown filter `{name}`")),
+    }
+}
+
+#[derive(Args)]
+struct CompressArgs {
+    /// Input file path, or `-` for stdin
+    #[arg(short, long, value_name = "FILE")]
+    input: PathBuf,
+
+    /// Output file path, or `-` for stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// Compression method
+    #[arg(long, value_enum, default_value_t = Codec::Huffman)]
+    codec: Codec,
+
+    /// Compression level, from 1 (fastest) to 9 (smallest)
+    #[arg(short, long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(1..=9))]
+    level: u8,
+
+    /// Bytes coded as one block [default: the codec's own]
+    #[arg(long, value_name = "BYTES")]
+    block_size: Option<usize>,
+
+    /// Longest code, in bits, a code table may assign [default: the codec's own]
+    #[arg(long, value_name = "BITS")]
+    max_code_length: Option<u8>,
+
+    /// Checksum stored with the data and verified on decompression
+    #[arg(long, value_enum, default_value_t = Checksum::None)]
+    checksum: Checksum,
+
+    /// Most threads to compress with, used by the range-coder, rans, tans and bwt codecs
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Transform to apply before coding, repeated to chain several: rle,
+    /// delta:N, xor:N or shuffle:N (huffman codec only)
+    #[arg(long = "filter", value_name = "FILTER", value_parser = parse_filter)]
+    filters: Vec<Filter>,
+
+    /// Write a gzip or zlib stream instead
+    #[arg(long, value_enum, conflicts_with_all = ["codec", "level", "block_size", "max_code_length", "checksum", "threads", "filters"])]
+    format: Option<Format>,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Compress a file
     #[command(alias = "c")]
-    Compress {
-        /// Input file path
-        #[arg(short, long, value_name = "FILE")]
-        input: PathBuf,
-
-        /// Output file path
-        #[arg(short, long, value_name = "FILE")]
-        output: PathBuf,
-    },
+    Compress(CompressArgs),
     /// Decompress a file
     #[command(alias = "d")]
     Decompress {
-        /// Input file path
+        /// Input file path, or `-` for stdin
         #[arg(short, long, value_name = "FILE")]
         input: PathBuf,
 
-        /// Output file path
+        /// Output file path, or `-` for stdout
         #[arg(short, long, value_name = "FILE")]
         output: PathBuf,
+
+        /// Read a gzip or zlib stream instead
+        #[arg(long, value_enum)]
+        format: Option<Format>,
     },
 }
 
+fn is_std_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn open_input(path: &Path) -> io::Result<Box<dyn Read>> {
+    if is_std_stream(path) {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+fn create_output(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_std_stream(path) {
+        return Ok(Box::new(io::stdout().lock()));
+    }
+
+    Ok(Box::new(BufWriter::new(File::create(path)?)))
+}
+
+/// gzip settings that record the input file's name and modification time,
+/// like the `gzip` tool does.
+fn gzip_options(format: Format, input: &Path) -> io::Result<gzip::Options> {
+    let mut options = gzip::Options {
+        format: format.into(),
+        ..gzip::Options::default()
+    };
+
+    if is_std_stream(input) {
+        return Ok(options);
+    }
+
+    options.file_name = input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    options.modification_time = File::open(input)?
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32);
+
+    Ok(options)
+}
+
+/// Compresses `reader` to `writer` as `args` select.
+fn compress(args: &CompressArgs, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<()> {
+    if let Some(format) = args.format {
+        let options = gzip_options(format, &args.input)?;
+        return Ok(gzip::compress(reader, writer, &options)?);
+    }
+
+    let name = args
+        .codec
+        .to_possible_value()
+        .ok_or_else(|| Error::Unsupported("codec has no name".into()))?;
+    let codec = codec_named(name.get_name())
+        .ok_or_else(|| Error::Unsupported(format!("no codec named {}", name.get_name())))?;
+
+    let mut options = CompressOptions::new()
+        .with_codec(codec.id())
+        .with_level(args.level)
+        .with_checksum(args.checksum.into())
+        .with_threads(args.threads)
+        .with_filters(&args.filters);
+    if let Some(block_size) = args.block_size {
+        options = options.with_block_size(block_size);
+    }
+    if let Some(max_code_length) = args.max_code_length {
+        options = options.with_max_code_length(max_code_length);
+    }
+
+    Ok(compress_with(reader, writer, &options)?)
+}
+
 fn main() -> io::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Compress { input, output } => {
-            let input_file = File::open(&input)?;
-            let mut reader = BufReader::new(input_file);
+        Commands::Compress(args) => {
+            let mut reader = open_input(&args.input)?;
+            let mut writer = create_output(&args.output)?;
 
-            let output_file = File::create(&output)?;
-            let mut writer = BufWriter::new(output_file);
+            compress(&args, &mut reader, &mut writer)?;
+            writer.flush()?;
 
-            compress(&mut reader, &mut writer)?;
-            println!("Compression finished successfully!");
+            if !is_std_stream(&args.output) {
+                println!("Compression finished successfully!");
+            }
         }
-        Commands::Decompress { input, output } => {
-            let input_file = File::open(&input)?;
-            let mut reader = BufReader::new(input_file);
+        Commands::Decompress {
+            input,
+            output,
+            format,
+        } => {
+            let mut reader = open_input(&input)?;
+            let mut writer = create_output(&output)?;
 
-            let output_file = File::create(&output)?;
-            let mut writer = BufWriter::new(output_file);
+            match format {
+                Some(format) => gzip::decompress(&mut reader, &mut writer, format.into())?,
+                None => decompress(&mut reader, &mut writer)?,
+            }
+            writer.flush()?;
 
-            decompress(&mut reader, &mut writer)?;
-            println!("Decompression finished successfully!");
+            if !is_std_stream(&output) {
+                println!("Decompression finished successfully!");
+            }
         }
     }
 
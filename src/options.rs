@@ -0,0 +1,301 @@
+//! Settings shared by every codec, for choosing between them and trading
+//! speed for ratio without knowing each codec's own options.
+
+use crate::checksum::ChecksumKind;
+use crate::codec::Codec;
+use crate::container::CodecId;
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::{adaptive_huffman, ans, bwt, context_huffman, huffman, lz77, range_coder};
+
+/// Fastest compression level.
+pub const MIN_LEVEL: u8 = 1;
+/// Smallest output, the slowest level.
+pub const MAX_LEVEL: u8 = 9;
+/// The level each codec's own default options match.
+pub const DEFAULT_LEVEL: u8 = 6;
+
+/// Match candidates `lz77` compares at each level, from `MIN_LEVEL`.
+const CHAIN_LENGTHS: [usize; 9] = [4, 8, 16, 32, 64, 128, 256, 1024, 4096];
+/// Code tables `context_huffman` may give a block at each level.
+const TABLE_COUNTS: [usize; 9] = [1, 2, 4, 8, 16, 32, 64, 128, 256];
+
+/// Options for `compress_with`, built up from the defaults:
+///
+/// ```
+/// use compressor::{ChecksumKind, CodecId, CompressOptions};
+///
+/// let options = CompressOptions::new()
+///     .with_codec(CodecId::Lz77)
+///     .with_level(9)
+///     .with_checksum(ChecksumKind::Crc32);
+/// ```
+///
+/// Settings a codec has no use for are ignored by it, except filters: only
+/// the Huffman codec applies them, and the others reject them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressOptions {
+    codec: CodecId,
+    level: u8,
+    block_size: Option<usize>,
+    max_code_length: Option<u8>,
+    checksum: ChecksumKind,
+    threads: usize,
+    filters: Vec<Filter>,
+}
+
+impl CompressOptions {
+    /// Huffman coding at the default level, with each codec's own block
+    /// size and code length limit, no checksum, one thread and no filters.
+    pub fn new() -> Self {
+        CompressOptions {
+            codec: CodecId::Huffman,
+            level: DEFAULT_LEVEL,
+            block_size: None,
+            max_code_length: None,
+            checksum: ChecksumKind::None,
+            threads: 1,
+            filters: vec![],
+        }
+    }
+
+    pub fn with_codec(self, codec: CodecId) -> Self {
+        CompressOptions { codec, ..self }
+    }
+
+    /// From `MIN_LEVEL` to `MAX_LEVEL`. Higher levels search harder for
+    /// matches with `lz77` and allow more code tables with
+    /// `context_huffman`; the other codecs have nothing to trade.
+    pub fn with_level(self, level: u8) -> Self {
+        CompressOptions { level, ..self }
+    }
+
+    /// Upper bound on the bytes coded as one block, in place of the codec's
+    /// default.
+    pub fn with_block_size(self, block_size: usize) -> Self {
+        CompressOptions {
+            block_size: Some(block_size),
+            ..self
+        }
+    }
+
+    /// Longest code, in bits, that a code table may assign, in place of
+    /// the codec's default.
+    pub fn with_max_code_length(self, max_code_length: u8) -> Self {
+        CompressOptions {
+            max_code_length: Some(max_code_length),
+            ..self
+        }
+    }
+
+    pub fn with_checksum(self, checksum: ChecksumKind) -> Self {
+        CompressOptions { checksum, ..self }
+    }
+
+    /// Most threads compressing may use, at least one. The range coder,
+    /// ANS and BWT codecs code that many blocks at once; the others code
+    /// each block with what they learnt from the ones before, so use one.
+    pub fn with_threads(self, threads: usize) -> Self {
+        CompressOptions { threads, ..self }
+    }
+
+    /// Transforms applied to the input before it is coded, in order, and
+    /// recorded in the header for `decompress` to undo.
+    pub fn with_filters(self, filters: &[Filter]) -> Self {
+        CompressOptions {
+            filters: filters.to_vec(),
+            ..self
+        }
+    }
+
+    /// The selected codec, configured with these settings.
+    pub fn codec(&self) -> Result<Box<dyn Codec>, Error> {
+        if !(MIN_LEVEL..=MAX_LEVEL).contains(&self.level) {
+            return Err(Error::InvalidOptions(format!(
+                "level must be from {MIN_LEVEL} to {MAX_LEVEL}"
+            )));
+        }
+
+        if self.threads == 0 {
+            return Err(Error::InvalidOptions(
+                "thread count must be at least 1".into(),
+            ));
+        }
+
+        if !self.filters.is_empty() && self.codec != CodecId::Huffman {
+            return Err(Error::InvalidOptions(
+                "only the huffman codec applies filters".into(),
+            ));
+        }
+
+        let step = (self.level - MIN_LEVEL) as usize;
+        let checksum = self.checksum;
+        let max_code_length = self
+            .max_code_length
+            .unwrap_or(huffman::DEFAULT_MAX_CODE_LENGTH);
+        let block_size = self.block_size.unwrap_or(huffman::DEFAULT_BLOCK_SIZE);
+
+        let codec: Box<dyn Codec> = match self.codec {
+            CodecId::Huffman => Box::new(huffman::Options {
+                checksum,
+                block_size,
+                max_code_length,
+                filters: self.filters.clone(),
+            }),
+            CodecId::Lz77 => Box::new(lz77::Options {
+                checksum,
+                block_size,
+                max_code_length,
+                max_chain_length: CHAIN_LENGTHS[step],
+            }),
+            CodecId::AdaptiveHuffman => Box::new(adaptive_huffman::Options {
+                checksum,
+                block_size,
+            }),
+            CodecId::RangeCoder => Box::new(range_coder::Options {
+                checksum,
+                block_size,
+                threads: self.threads,
+            }),
+            CodecId::Rans | CodecId::Tans => Box::new(ans::Options {
+                checksum,
+                block_size,
+                variant: match self.codec {
+                    CodecId::Rans => ans::Variant::Rans,
+                    _ => ans::Variant::Tans,
+                },
+                threads: self.threads,
+            }),
+            CodecId::ContextHuffman => Box::new(context_huffman::Options {
+                checksum,
+                block_size,
+                max_code_length,
+                max_tables: TABLE_COUNTS[step],
+            }),
+            CodecId::Bwt => Box::new(bwt::Options {
+                checksum,
+                block_size: self.block_size.unwrap_or(bwt::DEFAULT_BLOCK_SIZE),
+                max_code_length,
+                threads: self.threads,
+            }),
+        };
+
+        Ok(codec)
+    }
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        CompressOptions::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Header;
+    use crate::{compress_with, decompress};
+    use std::io::Cursor;
+
+    #[test]
+    fn default_level_matches_codec_defaults() {
+        assert_eq!(
+            CHAIN_LENGTHS[(DEFAULT_LEVEL - MIN_LEVEL) as usize],
+            lz77::DEFAULT_MAX_CHAIN_LENGTH
+        );
+        assert_eq!(
+            TABLE_COUNTS[(DEFAULT_LEVEL - MIN_LEVEL) as usize],
+            context_huffman::DEFAULT_MAX_TABLES
+        );
+    }
+
+    #[test]
+    fn every_codec_and_level_round_trips() {
+        let input = b"options pick the codec, and the level how hard it works. ".repeat(200);
+
+        for codec in crate::registry() {
+            for level in [MIN_LEVEL, DEFAULT_LEVEL, MAX_LEVEL] {
+                let options = CompressOptions::new()
+                    .with_codec(codec.id())
+                    .with_level(level)
+                    .with_block_size(4096)
+                    .with_checksum(ChecksumKind::Crc32)
+                    .with_threads(3);
+
+                let mut compressed = Vec::new();
+                compress_with(Cursor::new(&input), &mut compressed, &options).unwrap();
+
+                let mut output = Vec::new();
+                decompress(Cursor::new(&compressed), &mut output).unwrap();
+                assert_eq!(output, input, "{} at level {level}", codec.name());
+            }
+        }
+    }
+
+    #[test]
+    fn higher_level_compresses_repetitive_input_better() {
+        let input: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| format!("{} ", i % 997 * 7919 % 1000).into_bytes())
+            .collect();
+
+        let compressed_len = |level| {
+            let options = CompressOptions::new()
+                .with_codec(CodecId::Lz77)
+                .with_level(level);
+            let mut compressed = Vec::new();
+            compress_with(Cursor::new(&input), &mut compressed, &options).unwrap();
+            compressed.len()
+        };
+
+        assert!(compressed_len(MAX_LEVEL) < compressed_len(MIN_LEVEL));
+    }
+
+    #[test]
+    fn rejects_out_of_range_settings() {
+        for options in [
+            CompressOptions::new().with_level(0),
+            CompressOptions::new().with_level(10),
+            CompressOptions::new().with_threads(0),
+        ] {
+            assert!(matches!(options.codec(), Err(Error::InvalidOptions(_))));
+        }
+
+        let options = CompressOptions::new().with_max_code_length(0);
+        let error = compress_with(Cursor::new(b"abc"), Vec::new(), &options).unwrap_err();
+        assert!(matches!(error, Error::InvalidOptions(_)));
+    }
+
+    #[test]
+    fn huffman_applies_filters() {
+        let input: Vec<u8> = (0..5000u32).flat_map(|i| (i * 3).to_le_bytes()).collect();
+        let filters = [
+            Filter::Delta { stride: 4 },
+            Filter::Shuffle { width: 4 },
+            Filter::Rle,
+        ];
+        let options = CompressOptions::new().with_filters(&filters);
+
+        let mut compressed = Vec::new();
+        compress_with(Cursor::new(&input), &mut compressed, &options).unwrap();
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+        assert_eq!(header.filters, filters);
+
+        let mut output = Vec::new();
+        decompress(Cursor::new(&compressed), &mut output).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn other_codecs_reject_filters() {
+        for codec in crate::registry() {
+            let options = CompressOptions::new()
+                .with_codec(codec.id())
+                .with_filters(&[Filter::Rle]);
+
+            match codec.id() {
+                CodecId::Huffman => assert!(options.codec().is_ok()),
+                _ => assert!(matches!(options.codec(), Err(Error::InvalidOptions(_)))),
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+mod coder;
+pub(crate) mod model;
+
+use crate::checksum::ChecksumKind;
+use crate::container::{CodecId, Header};
+use crate::framing::{self, BlockType, Frame};
+use crate::huffman::DEFAULT_BLOCK_SIZE;
+use crate::huffman::frequency::Frequencies;
+use crate::range_coder::coder::{Decoder, Encoder};
+use crate::range_coder::model::Model;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Bits of precision of the scaled frequencies.
+const PRECISION_BITS: u32 = 16;
+
+/// Settings for `compress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Checksum of the input stored in the stream trailer.
+    pub checksum: ChecksumKind,
+    /// Upper bound on the bytes buffered and coded as one block.
+    pub block_size: usize,
+    /// Most blocks coded at once, each on a thread of its own.
+    pub threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            checksum: ChecksumKind::None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            threads: 1,
+        }
+    }
+}
+
+/// Compresses `input` with a range coder driven by each block's byte
+/// frequencies, which unlike a Huffman code can spend less than a bit on a
+/// byte and so comes within a fraction of a percent of the order-0 entropy.
+///
+/// Each block is written as `framing` describes, stored or coded. A coded
+/// block starts with its frequencies scaled to `PRECISION_BITS`.
+pub fn compress<R, W>(input: R, output: W, options: &Options) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let frame = Frame {
+        codec: CodecId::RangeCoder,
+        checksum: options.checksum,
+        block_size: options.block_size,
+    };
+
+    framing::compress_parallel(input, output, &frame, options.threads, encode_block)
+}
+
+/// The frequency table and range coded bytes of a non-empty block.
+fn encode_block(block: &[u8]) -> io::Result<Vec<u8>> {
+    let model = Model::new(&Frequencies::from_input(&mut &block[..])?, PRECISION_BITS)?;
+
+    let mut encoder = Encoder::new();
+    for &byte in block {
+        encoder.encode(&model, byte);
+    }
+
+    let mut coded = Vec::new();
+    model.write_to(&mut coded)?;
+    coded.extend(encoder.finish());
+
+    Ok(coded)
+}
+
+/// Decodes the blocks and trailer that follow `header`, which was read from
+/// the start of `reader`.
+pub fn decompress<R, W>(reader: BufReader<R>, output: W, header: &Header) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    header.expect_codec(CodecId::RangeCoder)?;
+
+    framing::decompress(reader, output, header, |reader, length, output| {
+        match framing::read_block_type(reader)? {
+            BlockType::Stored => output.copy_stored(reader, length),
+            BlockType::Coded => {
+                let model = Model::read_from(reader, PRECISION_BITS)?;
+                let lookup = model.lookup_table();
+                let mut decoder = Decoder::new(reader)?;
+
+                output.write_decoded(length, || decoder.decode(&model, &lookup))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::checksum::ChecksumKind;
+    use crate::container::{CodecId, Header};
+    use crate::error::Error;
+    use crate::framing::BlockType;
+    use crate::test_support::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_records_range_coder_codec() {
+        let compressed = compress_bytes(&Options::default(), b"abc");
+        let header = Header::read_from(&mut Cursor::new(&compressed)).unwrap();
+
+        assert_eq!(header.codec, CodecId::RangeCoder);
+        assert_eq!(header.original_length, Some(3));
+    }
+
+    #[test]
+    fn round_trip_test_vectors() {
+        let options = Options::default();
+
+        assert_round_trip(&options, b"");
+        assert_round_trip(&options, b"a");
+        assert_round_trip(&options, b"the rain in spain stays mainly in the plain");
+        assert_round_trip(&options, &(0..=255).collect::<Vec<u8>>());
+        assert_round_trip(&options, &skewed_bytes(1_000_000, 0));
+    }
+
+    #[test]
+    fn output_is_close_to_order_0_entropy() {
+        let input = skewed_bytes(super::DEFAULT_BLOCK_SIZE, 0);
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        // The data within half a percent, plus the table and framing.
+        let bound = entropy_bytes(&input) * 1.005 + 100.0;
+        assert!((compressed.len() as f64) < bound);
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+    }
+
+    #[test]
+    fn repeated_symbol_costs_almost_nothing() {
+        // A Huffman code spends at least a bit on each byte.
+        let input = [b'a'; 100_000];
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        assert!(compressed.len() < 30);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn random_input_is_stored() {
+        let input = random_bytes(10_000, 0);
+
+        let compressed = compress_bytes(&Options::default(), &input);
+
+        assert_eq!(first_block_type(&compressed), BlockType::Stored);
+        assert_eq!(decompress_bytes(&compressed).unwrap(), input);
+    }
+
+    #[test]
+    fn small_blocks_round_trip() {
+        let options = Options {
+            checksum: ChecksumKind::Crc32,
+            block_size: 1000,
+            threads: 1,
+        };
+        let input = skewed_bytes(10_000, 0);
+
+        assert_round_trip(&options, &input);
+
+        // Blocks coded on several threads are written just the same.
+        let parallel = Options {
+            threads: 4,
+            ..options.clone()
+        };
+        assert_eq!(
+            compress_bytes(&parallel, &input),
+            compress_bytes(&options, &input)
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let options = Options {
+            checksum: ChecksumKind::XxHash64,
+            ..Options::default()
+        };
+
+        assert_rejects_corrupted_data(&options, &skewed_bytes(10_000, 0));
+    }
+
+    #[test]
+    fn rejects_block_longer_than_limits() {
+        // A block of one repeated byte costs no bits, however long.
+        let compressed = compress_bytes(&Options::default(), &[b'a'; 100]);
+        assert_eq!(first_block_type(&compressed), BlockType::Coded);
+
+        for original_length in [Some(100), None] {
+            let patched = with_first_block_length(&compressed, original_length, 1 << 50);
+
+            let error = decompress_bytes(&patched).unwrap_err();
+            assert!(matches!(Error::from(error), Error::LimitExceeded(_)));
+        }
+    }
+
+    #[test]
+    fn rejects_zero_block_size_or_threads() {
+        assert_rejects_options(&Options {
+            block_size: 0,
+            ..Options::default()
+        });
+        assert_rejects_options(&Options {
+            threads: 0,
+            ..Options::default()
+        });
+    }
+}
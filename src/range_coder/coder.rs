@@ -0,0 +1,181 @@
+use crate::range_coder::model::Model;
+use std::io::{self, Read};
+
+/// The range is widened a byte at a time whenever it drops below this.
+const NORMALIZE_BELOW: u32 = 1 << 24;
+
+/// A range encoder with a 32-bit range, as in LZMA: `low` keeps a carry bit
+/// above its 32 bits, and a run of 0xFF bytes that a carry could still
+/// change is held back in `cache` and `cache_len` until it is settled.
+pub struct Encoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_len: u64,
+    output: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_len: 1,
+            output: vec![],
+        }
+    }
+
+    pub fn encode(&mut self, model: &Model, byte: u8) {
+        let (start, width) = model.range(byte);
+        let step = self.range >> model.precision_bits();
+
+        self.low += step as u64 * start as u64;
+        self.range = step * width;
+
+        while self.range < NORMALIZE_BELOW {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    /// Writes out the rest of `low` and returns the coded bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+
+        self.output
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xFF00_0000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+
+            while self.cache_len > 0 {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xFF;
+                self.cache_len -= 1;
+            }
+
+            self.cache = (self.low >> 24) as u8;
+        }
+
+        self.cache_len += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+}
+
+/// Decodes what `Encoder` wrote, reading exactly the bytes it wrote.
+pub struct Decoder<'a, R: Read> {
+    reader: &'a mut R,
+    code: u32,
+    range: u32,
+}
+
+impl<'a, R: Read> Decoder<'a, R> {
+    pub fn new(reader: &'a mut R) -> io::Result<Self> {
+        let mut decoder = Decoder {
+            reader,
+            code: 0,
+            range: u32::MAX,
+        };
+
+        for _ in 0..5 {
+            decoder.shift_in()?;
+        }
+
+        Ok(decoder)
+    }
+
+    /// Decodes a byte, given `lookup`, the lookup table of `model`.
+    pub fn decode(&mut self, model: &Model, lookup: &[u8]) -> io::Result<u8> {
+        let step = self.range >> model.precision_bits();
+
+        // Corrupt input can point past the end of the range.
+        let value = (self.code / step).min(model.total() - 1);
+        let byte = lookup[value as usize];
+        let (start, width) = model.range(byte);
+
+        self.code = self.code.wrapping_sub(step * start);
+        self.range = step * width;
+
+        while self.range < NORMALIZE_BELOW {
+            self.range <<= 8;
+            self.shift_in()?;
+        }
+
+        Ok(byte)
+    }
+
+    fn shift_in(&mut self) -> io::Result<()> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        self.code = (self.code << 8) | byte[0] as u32;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::frequency::Frequencies;
+    use crate::range_coder::PRECISION_BITS;
+    use std::io::Cursor;
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let model = Model::new(
+            &Frequencies::from_input(&mut &input[..]).unwrap(),
+            PRECISION_BITS,
+        )
+        .unwrap();
+
+        let mut encoder = Encoder::new();
+        for &byte in input {
+            encoder.encode(&model, byte);
+        }
+        let coded = encoder.finish();
+
+        let lookup = model.lookup_table();
+        let mut reader = Cursor::new(&coded);
+        let mut decoder = Decoder::new(&mut reader).unwrap();
+        let output = input
+            .iter()
+            .map(|_| decoder.decode(&model, &lookup).unwrap())
+            .collect::<Vec<u8>>();
+
+        // The decoder reads exactly what the encoder wrote.
+        assert_eq!(reader.position(), coded.len() as u64);
+        assert_eq!(output, input);
+
+        coded
+    }
+
+    #[test]
+    fn round_trips_varied_input() {
+        round_trip(b"a");
+        round_trip(b"abracadabra");
+        round_trip(&(0..=255).cycle().take(10_000).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn carries_propagate_through_pending_bytes() {
+        // A dominant symbol at the top of the range pushes `low` up against
+        // 0xFF.. repeatedly, with rare symbols at the bottom in between.
+        let mut input = vec![0xFF; 50_000];
+        for i in (0..input.len()).step_by(997) {
+            input[i] = 0;
+        }
+
+        round_trip(&input);
+    }
+
+    #[test]
+    fn single_symbol_costs_no_bits() {
+        let coded = round_trip(&[b'a'; 100_000]);
+
+        assert_eq!(coded.len(), 5);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::container;
+use crate::error::Error;
+use crate::huffman::frequency::Frequencies;
+use std::io::{self, Read, Write};
+
+const BYTE_ALPHABET_SIZE: usize = 256;
+
+/// Most bits of precision a model supports, which keeps a 32-bit coder
+/// exact.
+pub const MAX_PRECISION_BITS: u32 = 16;
+
+/// Byte frequencies scaled so they add up to a power of two, the total,
+/// each byte that occurs keeping a frequency of at least one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Model {
+    frequencies: [u32; BYTE_ALPHABET_SIZE],
+    /// The sum of the frequencies of all lower bytes, then the total.
+    cumulative: [u32; BYTE_ALPHABET_SIZE + 1],
+    precision_bits: u32,
+}
+
+impl Model {
+    /// Scales `frequencies`, of which at least one must be non-zero, to add
+    /// up to `1 << precision_bits`.
+    pub fn new(frequencies: &Frequencies, precision_bits: u32) -> io::Result<Self> {
+        if !(u8::BITS..=MAX_PRECISION_BITS).contains(&precision_bits) {
+            return Err(Error::InvalidOptions("unsupported model precision".into()).into());
+        }
+
+        let all: u64 = frequencies.iter().sum();
+        if all == 0 {
+            return Err(Error::InvalidOptions("a model needs at least one symbol".into()).into());
+        }
+
+        let total = 1u32 << precision_bits;
+        let mut scaled = [0u32; BYTE_ALPHABET_SIZE];
+        for (scaled, &count) in scaled.iter_mut().zip(frequencies.iter()) {
+            if count > 0 {
+                let share = (count as u128 * total as u128 / all as u128) as u32;
+                *scaled = share.max(1);
+            }
+        }
+
+        // Rounding leaves the sum a little off; settle the difference on the
+        // most frequent bytes, where it costs the least.
+        let mut sum: u32 = scaled.iter().sum();
+        while sum != total {
+            let largest = (0..BYTE_ALPHABET_SIZE)
+                .filter(|&byte| sum < total || scaled[byte] > 1)
+                .max_by_key(|&byte| scaled[byte])
+                .ok_or_else(|| corrupt_table("no symbol can absorb the rounding difference"))?;
+
+            if sum < total {
+                scaled[largest] += total - sum;
+                sum = total;
+            } else {
+                let excess = (sum - total).min(scaled[largest] / 2).max(1);
+                scaled[largest] -= excess;
+                sum -= excess;
+            }
+        }
+
+        Ok(Model::from_scaled(scaled, precision_bits))
+    }
+
+    fn from_scaled(frequencies: [u32; BYTE_ALPHABET_SIZE], precision_bits: u32) -> Self {
+        let mut cumulative = [0; BYTE_ALPHABET_SIZE + 1];
+        for byte in 0..BYTE_ALPHABET_SIZE {
+            cumulative[byte + 1] = cumulative[byte] + frequencies[byte];
+        }
+
+        Model {
+            frequencies,
+            cumulative,
+            precision_bits,
+        }
+    }
+
+    pub fn precision_bits(&self) -> u32 {
+        self.precision_bits
+    }
+
+    /// The sum of the scaled frequencies.
+    pub fn total(&self) -> u32 {
+        1 << self.precision_bits
+    }
+
+    /// The scaled frequency of each byte.
+    pub fn frequencies(&self) -> &[u32] {
+        &self.frequencies
+    }
+
+    /// The start and width of the slice of the total given to `byte`.
+    pub fn range(&self, byte: u8) -> (u32, u32) {
+        (
+            self.cumulative[byte as usize],
+            self.frequencies[byte as usize],
+        )
+    }
+
+    /// A table from each value below the total to the byte whose slice
+    /// holds it.
+    pub fn lookup_table(&self) -> Vec<u8> {
+        let mut table = Vec::with_capacity(self.total() as usize);
+        for (byte, &frequency) in self.frequencies.iter().enumerate() {
+            table.extend(std::iter::repeat_n(byte as u8, frequency as usize));
+        }
+
+        table
+    }
+
+    /// Writes the number of bytes that occur, then each of them with its
+    /// scaled frequency as a varint.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let symbols = self.frequencies.iter().filter(|&&frequency| frequency > 0);
+        container::write_varint(writer, symbols.count() as u64)?;
+
+        for (byte, &frequency) in self.frequencies.iter().enumerate() {
+            if frequency > 0 {
+                writer.write_all(&[byte as u8])?;
+                container::write_varint(writer, frequency as u64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a table written by `write_to` for a model of the same precision.
+    pub fn read_from<R: Read>(reader: &mut R, precision_bits: u32) -> io::Result<Self> {
+        let total = 1u64 << precision_bits.min(MAX_PRECISION_BITS);
+
+        let count = container::read_varint(reader)?;
+        if !(1..=BYTE_ALPHABET_SIZE as u64).contains(&count) {
+            return Err(corrupt_table(
+                "invalid number of symbols in frequency table",
+            ));
+        }
+
+        let mut frequencies = [0; BYTE_ALPHABET_SIZE];
+        let mut sum = 0u64;
+        let mut previous = None;
+
+        for _ in 0..count {
+            let mut byte = [0];
+            reader.read_exact(&mut byte)?;
+            let frequency = container::read_varint(reader)?;
+
+            if previous.is_some_and(|previous| previous >= byte[0]) {
+                return Err(corrupt_table("frequency table symbols are out of order"));
+            }
+            if frequency == 0 || frequency > total {
+                return Err(corrupt_table("invalid frequency in frequency table"));
+            }
+
+            frequencies[byte[0] as usize] = frequency as u32;
+            sum += frequency;
+            previous = Some(byte[0]);
+        }
+
+        if sum != total {
+            return Err(corrupt_table("frequency table does not add up"));
+        }
+
+        Ok(Model::from_scaled(frequencies, precision_bits))
+    }
+}
+
+fn corrupt_table(message: &str) -> io::Error {
+    Error::CorruptTable(message.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PRECISION_BITS: u32 = 12;
+    const TOTAL: u32 = 1 << PRECISION_BITS;
+
+    fn model_for(input: &[u8]) -> Model {
+        Model::new(
+            &Frequencies::from_input(&mut &input[..]).unwrap(),
+            PRECISION_BITS,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn scaled_frequencies_add_up_to_total() {
+        let mut input = vec![b'a'; 1_000_000];
+        input.extend(0..=255);
+
+        let model = model_for(&input);
+
+        assert_eq!(model.cumulative[BYTE_ALPHABET_SIZE], TOTAL);
+        assert!(model.frequencies.iter().all(|&frequency| frequency >= 1));
+        assert!(model.range(b'a').1 > TOTAL - 260);
+    }
+
+    #[test]
+    fn single_symbol_takes_whole_range() {
+        let model = model_for(b"zzzz");
+
+        assert_eq!(model.range(b'z'), (0, TOTAL));
+        assert_eq!(model.range(b'a'), (0, 0));
+    }
+
+    #[test]
+    fn table_round_trip() {
+        let model = model_for(b"the rain in spain stays mainly in the plain");
+
+        let mut bytes = Vec::new();
+        model.write_to(&mut bytes).unwrap();
+
+        assert_eq!(
+            Model::read_from(&mut Cursor::new(bytes), PRECISION_BITS).unwrap(),
+            model
+        );
+    }
+
+    #[test]
+    fn rejects_table_that_does_not_add_up() {
+        // Two symbols, 'a' and 'b', with frequencies of one each.
+        let bytes = [2, b'a', 1, b'b', 1];
+
+        assert!(Model::read_from(&mut Cursor::new(bytes), PRECISION_BITS).is_err());
+    }
+}
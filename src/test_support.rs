@@ -0,0 +1,188 @@
+//! Fixtures shared by the codecs' tests.
+
+use crate::codec::Codec;
+use crate::container::{self, Header};
+use crate::decompress;
+use crate::error::Error;
+use crate::framing::BlockType;
+use std::io::{self, Cursor, Read};
+use std::iter;
+
+/// A reader that, like a pipe, cannot seek and returns short reads.
+pub struct Pipe<'a>(pub &'a [u8]);
+
+impl Read for Pipe<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(1000);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+/// Runs `compress` on `input`, read through a `Pipe`, and returns what it
+/// wrote.
+pub fn compress_with<F, E>(input: &[u8], compress: F) -> Vec<u8>
+where
+    F: FnOnce(&mut dyn Read, &mut Vec<u8>) -> Result<(), E>,
+    E: std::fmt::Debug,
+{
+    let mut output = Vec::new();
+    compress(&mut Pipe(input), &mut output).expect("compression should succeed");
+    output
+}
+
+/// Runs `decompress` on `input` and returns what it wrote.
+pub fn decompress_with<F, E>(input: &[u8], decompress: F) -> io::Result<Vec<u8>>
+where
+    F: FnOnce(&[u8], &mut Vec<u8>) -> Result<(), E>,
+    E: Into<io::Error>,
+{
+    let mut output = Vec::new();
+    decompress(input, &mut output).map_err(Into::into)?;
+    Ok(output)
+}
+
+pub fn compress_bytes(codec: &dyn Codec, input: &[u8]) -> Vec<u8> {
+    compress_with(input, |input, output| codec.compress(input, output))
+}
+
+pub fn decompress_bytes(input: &[u8]) -> io::Result<Vec<u8>> {
+    decompress_with(input, |input, output| decompress(input, output))
+}
+
+pub fn assert_round_trip(codec: &dyn Codec, input: &[u8]) {
+    let output = decompress_bytes(&compress_bytes(codec, input)).unwrap();
+    assert_eq!(output, input, "decompressed output must match the input");
+}
+
+/// The type of the first block of a stream whose blocks start with one.
+pub fn first_block_type(compressed: &[u8]) -> BlockType {
+    let mut reader = Cursor::new(compressed);
+    Header::read_from(&mut reader).unwrap();
+    container::read_varint(&mut reader).unwrap();
+
+    BlockType::try_from(compressed[reader.position() as usize]).unwrap()
+}
+
+/// `compressed` with its header's original length and its first block
+/// length replaced.
+pub fn with_first_block_length(
+    compressed: &[u8],
+    original_length: Option<u64>,
+    block_length: u64,
+) -> Vec<u8> {
+    let mut reader = Cursor::new(compressed);
+    let header = Header::read_from(&mut reader).unwrap();
+    container::read_varint(&mut reader).unwrap();
+
+    let mut patched = Vec::new();
+    Header::new(header.codec, header.checksum, original_length)
+        .write_to(&mut patched)
+        .unwrap();
+    container::write_varint(&mut patched, block_length).unwrap();
+    patched.extend_from_slice(&compressed[reader.position() as usize..]);
+
+    patched
+}
+
+/// Flips a bit in the middle of `input` compressed by `codec`, which should
+/// have a checksum so that any damage is caught.
+pub fn assert_rejects_corrupted_data(codec: &dyn Codec, input: &[u8]) {
+    let mut compressed = compress_bytes(codec, input);
+    let middle = compressed.len() / 2;
+    compressed[middle] ^= 0x10;
+
+    assert!(decompress_bytes(&compressed).is_err());
+}
+
+pub fn assert_rejects_options(codec: &dyn Codec) {
+    let error = codec
+        .compress(&mut Cursor::new(b"abc"), &mut Vec::new())
+        .unwrap_err();
+
+    assert!(matches!(error, Error::InvalidOptions(_)), "{error}");
+}
+
+/// The outputs of an xorshift generator, the same on every run for a given
+/// `seed`.
+fn xorshift(seed: u64) -> impl Iterator<Item = u64> {
+    let mut state = seed ^ 0x2545_F491_4F6C_DD1D;
+
+    iter::repeat_with(move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    })
+}
+
+/// Uniformly random bytes, which no codec can compress.
+pub fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    xorshift(seed).map(|x| (x >> 56) as u8).take(len).collect()
+}
+
+/// Bytes drawn independently from a skewed distribution, each byte half as
+/// likely as the one before.
+pub fn skewed_bytes(len: usize, seed: u64) -> Vec<u8> {
+    xorshift(seed)
+        .map(|x| (x >> 8).trailing_zeros().min(20) as u8 + b'a')
+        .take(len)
+        .collect()
+}
+
+/// The order-0 entropy of `input`, in bytes.
+pub fn entropy_bytes(input: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in input {
+        counts[byte as usize] += 1;
+    }
+
+    let len = input.len() as f64;
+    let bits: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| -(count as f64) * (count as f64 / len).log2())
+        .sum();
+
+    bits / 8.0
+}
+
+/// `len` bytes of words from a vocabulary of 64 chosen by `seed`, so that
+/// each byte depends strongly on the ones before it, as in text.
+pub fn text(len: usize, seed: u64) -> Vec<u8> {
+    let vocabulary: Vec<Vec<u8>> = random_bytes(64 * 8, seed)
+        .chunks(8)
+        .map(|word| {
+            let len = 2 + word[0] as usize % 6;
+            let mut word: Vec<u8> = word[1..=len].iter().map(|byte| b'a' + byte % 26).collect();
+            word.push(b' ');
+            word
+        })
+        .collect();
+
+    let mut text = Vec::with_capacity(len);
+    for choice in random_bytes(len, seed + 1) {
+        if text.len() >= len {
+            break;
+        }
+        text.extend(&vocabulary[choice as usize % vocabulary.len()]);
+    }
+
+    text.truncate(len);
+    text
+}
+
+/// Lines of a log that differ only in their timestamps and ids, as LZ77
+/// matching likes.
+pub fn log_lines(count: usize) -> Vec<u8> {
+    (0..count)
+        .flat_map(|i| {
+            format!(
+                "2024-01-01T00:00:{:02} INFO request handled id={i}\n",
+                i % 60
+            )
+            .into_bytes()
+        })
+        .collect()
+}